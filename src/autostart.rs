@@ -0,0 +1,73 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use miette::{bail, IntoDiagnostic, Result};
+
+use crate::config::config_home;
+
+/// Write an XDG autostart entry that runs `gestures start` at login
+pub fn install(conf: Option<&Path>, wayland: bool, force: bool) -> Result<PathBuf> {
+    let dir = PathBuf::from(config_home()).join("autostart");
+    let path = dir.join("gestures.desktop");
+    if path.exists() && !force {
+        bail!(
+            "{} already exists, use --force to overwrite it",
+            path.display()
+        );
+    }
+
+    let mut args = vec![env::current_exe().into_diagnostic()?];
+    if let Some(conf) = conf {
+        args.push("--conf".into());
+        args.push(fs::canonicalize(conf).into_diagnostic()?);
+    }
+    if wayland {
+        args.push("--wayland-disp".into());
+    }
+    args.push("start".into());
+    let exec = args
+        .iter()
+        .map(|a| quote(&a.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    fs::create_dir_all(&dir).into_diagnostic()?;
+    fs::write(&path, desktop_entry(&exec)).into_diagnostic()?;
+    Ok(path)
+}
+
+fn desktop_entry(exec: &str) -> String {
+    format!(
+        "[Desktop Entry]
+Type=Application
+Name=Gestures
+Comment=Libinput based touchpad gestures
+Exec={exec}
+Terminal=false
+NoDisplay=true
+X-GNOME-Autostart-enabled=true
+"
+    )
+}
+
+/// Quote an argument for the `Exec` key of a desktop entry
+pub fn quote(arg: &str) -> String {
+    // `%` starts a field code and has to be doubled, quoted or not
+    let arg = arg.replace('%', "%%");
+    let reserved = |c: char| c.is_whitespace() || "\"'\\><~|&;$*?#()`".contains(c);
+    if !arg.is_empty() && !arg.contains(reserved) {
+        return arg;
+    }
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    // Backslashes are unescaped once more when the value is read as a string
+    quoted.replace('\\', "\\\\")
+}
@@ -0,0 +1,124 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use miette::Result;
+use parking_lot::RwLock;
+
+use crate::config::Config;
+use crate::event_handler::{EventHandler, GestureInput};
+use crate::gestures::{pinch::PinchDir, swipe::SwipeDir, Gesture};
+use crate::ipc_client::synthetic_events;
+use crate::xdo_handler::start_handler;
+use crate::InjectGesture;
+
+/// Feed `rounds` rounds of made up swipes, pinches and holds through the handler, with the rules
+/// of `config` stripped of their actions. Returns the number of events and how long they took.
+pub fn bench(mut config: Config, rounds: u32) -> Result<(usize, Duration)> {
+    without_actions(&mut config);
+    let mut xdoh = start_handler(false)?;
+    let mut eh = EventHandler::new(
+        Arc::new(RwLock::new(config)),
+        Default::default(),
+        Default::default(),
+    );
+    let events = events();
+    let start = Instant::now();
+    for _ in 0..rounds {
+        for event in &events {
+            eh.dispatch(event.clone(), &mut xdoh)?;
+        }
+    }
+    Ok((events.len() * rounds as usize, start.elapsed()))
+}
+
+/// One gesture of each kind, direction and finger count from 2 to 5
+fn events() -> Vec<GestureInput> {
+    let swipe_dirs = [
+        SwipeDir::N,
+        SwipeDir::NE,
+        SwipeDir::E,
+        SwipeDir::SE,
+        SwipeDir::S,
+        SwipeDir::SW,
+        SwipeDir::W,
+        SwipeDir::NW,
+    ];
+    let pinch_dirs = [
+        PinchDir::In,
+        PinchDir::Out,
+        PinchDir::Clockwise,
+        PinchDir::CounterClockwise,
+    ];
+    let mut gestures = Vec::new();
+    for fingers in 2..=5 {
+        gestures.extend(swipe_dirs.iter().map(|dir| InjectGesture::Swipe {
+            fingers,
+            dir: dir.clone(),
+        }));
+        gestures.extend(pinch_dirs.iter().map(|dir| InjectGesture::Pinch {
+            fingers,
+            dir: dir.clone(),
+        }));
+        gestures.push(InjectGesture::Hold { fingers });
+    }
+    gestures.iter().flat_map(synthetic_events).collect()
+}
+
+/// Leave the rules matching as configured, but with nothing to run when they fire
+pub fn without_actions(config: &mut Config) {
+    config.activation_key = None;
+    config.cheatsheet = None;
+    config.default_swipe_action = None;
+    config.default_pinch_action = None;
+    config.default_hold_action = None;
+    config.dispatcher = None;
+    config.on_missing_command = None;
+    config.screenshot_command = None;
+    for gesture in &mut config.gestures {
+        for command in gesture.commands_mut() {
+            command.clear();
+        }
+        match gesture {
+            Gesture::Swipe(s) => {
+                s.double = None;
+                s.cycle.clear();
+                s.window = None;
+                s.media = None;
+                s.text = None;
+                s.screenshot = None;
+                s.repeat_key = None;
+                s.dbus = None;
+                s.center_cursor = false;
+                s.click = None;
+                s.hold_modifiers = None;
+                s.hotkey = None;
+                s.sound = None;
+                s.set = None;
+            }
+            Gesture::Pinch(p) => {
+                p.cycle.clear();
+                p.hold_modifiers = None;
+                p.hotkey = None;
+                p.sound = None;
+                p.set = None;
+            }
+            Gesture::Hold(h) => {
+                h.cycle.clear();
+                h.window = None;
+                h.media = None;
+                h.text = None;
+                h.screenshot = None;
+                h.dbus = None;
+                h.center_cursor = false;
+                h.click = None;
+                h.hold_modifiers = None;
+                h.hotkey = None;
+                h.sound = None;
+                h.set = None;
+            }
+            Gesture::Group(_) | Gesture::None => (),
+        }
+    }
+}
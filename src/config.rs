@@ -1,30 +1,628 @@
-use std::{env, fs, path::Path};
+use std::{
+    env,
+    ffi::{c_char, c_int, c_void, CStr},
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
 
-use miette::{bail, IntoDiagnostic, Result};
-// use serde::{Deserialize, Serialize};
-use knuffel::{parse, Decode};
+use input::{ffi, AsRaw, Device, DeviceConfigResult, Libinput};
+use knuffel::{
+    ast::{Literal, TypeName},
+    decode::{Context, Kind},
+    errors::DecodeError,
+    parse,
+    span::Spanned,
+    traits::ErrorSpan,
+    Decode, DecodeScalar,
+};
+use miette::Diagnostic;
+use schemars::JsonSchema;
+use serde::Serialize;
+use thiserror::Error;
 
-use crate::gestures::Gesture;
+use crate::dbus::DBusCall;
+use crate::gestures::{progress_problem, swipe::SwipeDefaults, Fingers, Gesture};
+use crate::hotkey;
+use crate::utils::{missing_program, Runner};
 
-#[derive(Decode, PartialEq, Debug, Default)]
+/// Environment variable holding the whole config, read instead of the config files when set
+pub const CONFIG_ENV: &str = "GESTURES_CONFIG";
+
+#[derive(Decode, Serialize, JsonSchema, PartialEq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
 pub struct Config {
     // pub device: Option<String>,
+    /// Flip the sign of `dx` before swipe direction classification and drag
+    #[knuffel(child)]
+    #[schemars(default)]
+    pub invert_horizontal: bool,
+    /// Flip the sign of `dy` before swipe direction classification and drag
+    #[knuffel(child)]
+    #[schemars(default)]
+    pub invert_vertical: bool,
+    /// Clockwise rotation of the touchpad in degrees, one of 0, 90, 180 or 270
+    #[knuffel(child, unwrap(argument))]
+    pub rotation: Option<i32>,
+    /// Degrees a swipe has to move past a direction boundary before its direction changes
+    #[knuffel(child, unwrap(argument))]
+    pub swipe_hysteresis: Option<f64>,
+    /// Commit each pinch to either scaling or rotating early on and ignore the other motion
+    #[knuffel(child)]
+    #[schemars(default)]
+    pub pinch_lock: bool,
+    /// Milliseconds over which swipe and pinch updates are merged into one before acting on them
+    #[knuffel(child, unwrap(argument))]
+    pub coalesce_updates: Option<u64>,
+    /// Rules for N fingers also match gestures with up to this many fingers more or less
+    #[knuffel(child, unwrap(argument))]
+    pub finger_tolerance: Option<u32>,
+    /// libinput settings applied to the gesture devices
+    #[knuffel(child)]
+    pub device_config: Option<DeviceConfig>,
+    /// Lowest priority of the messages libinput itself prints
+    #[knuffel(child, unwrap(argument))]
+    pub libinput_log: Option<LibinputLog>,
+    /// Gesture devices whose name contains one of these, ignoring case, are left alone
+    #[knuffel(child, unwrap(arguments), default)]
+    #[schemars(default)]
+    pub ignore_devices: Vec<String>,
+    /// Commands run when a swipe, pinch or hold ends without any rule handling it
+    #[knuffel(child, unwrap(argument))]
+    pub default_swipe_action: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    pub default_pinch_action: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    pub default_hold_action: Option<String>,
+    /// Gestures are only recognized while this modifier is held
+    #[knuffel(child, unwrap(argument))]
+    pub activation_key: Option<Modifier>,
+    /// Command run instead of a gesture's actions while a modifier key is held
+    #[knuffel(child)]
+    pub cheatsheet: Option<Cheatsheet>,
+    /// Values substituted for `{var.name}` in the commands of the rules
+    #[knuffel(child, default)]
+    #[serde(skip)]
+    pub vars: Vars,
+    /// Run gesture commands as this user instead of the daemon's own user
+    #[knuffel(child, unwrap(argument))]
+    pub run_as: Option<String>,
+    /// Command run for every gesture event instead of the rules, with the gesture in its
+    /// environment
+    #[knuffel(child, unwrap(argument))]
+    pub dispatcher: Option<String>,
+    /// Command run when a command can't run because its program is not installed, with that
+    /// command in `$1`
+    #[knuffel(child, unwrap(argument))]
+    pub on_missing_command: Option<String>,
+    /// Command that takes the screenshots of `screenshot`, with the area in `$1`
+    #[knuffel(child, unwrap(argument))]
+    pub screenshot_command: Option<String>,
+    /// Drag settings for the swipe rules of each finger count, merged into the rules on load
+    #[knuffel(children(name = "swipe-defaults"))]
+    #[serde(skip)]
+    pub swipe_defaults: Vec<SwipeDefaults>,
+    /// Further config files merged into this one, relative to the directory of this file
+    #[knuffel(children(name = "include"), unwrap(argument))]
+    #[serde(skip)]
+    pub include: Vec<PathBuf>,
     #[knuffel(children)]
     pub gestures: Vec<Gesture>,
+    /// How often the config was reloaded, for the handler to drop what it kept about the rules
+    /// of the previous one. Not read from the file.
+    #[serde(skip)]
+    pub reloads: u32,
+    /// File given with `--conf`, reloads read it again instead of the default location. Not read
+    /// from the file.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+}
+
+/// libinput settings for the gesture devices, each one is left alone unless set
+#[derive(Decode, Serialize, JsonSchema, PartialEq, Debug, Default, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct DeviceConfig {
+    #[knuffel(child, unwrap(argument))]
+    pub tap: Option<bool>,
+    #[knuffel(child, unwrap(argument))]
+    pub natural_scroll: Option<bool>,
+    #[knuffel(child, unwrap(argument))]
+    pub accel_profile: Option<AccelProfile>,
+    /// Between -1.0 and 1.0
+    #[knuffel(child, unwrap(argument))]
+    pub accel_speed: Option<f64>,
+}
+
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccelProfile {
+    Flat,
+    Adaptive,
+}
+
+impl DeviceConfig {
+    /// Apply the settings to `device`, logging the ones it rejects
+    pub fn apply(&self, device: &mut Device) {
+        let name = device.name().to_string();
+        let check = |setting: &str, result: DeviceConfigResult| match result {
+            Ok(()) => log::debug!("Set {setting} on {name}"),
+            Err(e) => log::warn!("Could not set {setting} on {name}: {e:?}"),
+        };
+        if let Some(tap) = self.tap {
+            check("tap", device.config_tap_set_enabled(tap));
+        }
+        if let Some(natural) = self.natural_scroll {
+            check(
+                "natural-scroll",
+                device.config_scroll_set_natural_scroll_enabled(natural),
+            );
+        }
+        if let Some(profile) = self.accel_profile {
+            let profile = match profile {
+                AccelProfile::Flat => input::AccelProfile::Flat,
+                AccelProfile::Adaptive => input::AccelProfile::Adaptive,
+            };
+            check("accel-profile", device.config_accel_set_profile(profile));
+        }
+        if let Some(speed) = self.accel_speed {
+            check("accel-speed", device.config_accel_set_speed(speed));
+        }
+    }
+}
+
+/// Priority of libinput's own log messages
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LibinputLog {
+    Debug,
+    Info,
+    Error,
+}
+
+impl LibinputLog {
+    /// Pass libinput's messages of this priority and above on to `log`
+    pub fn apply(self, input: &Libinput) {
+        let priority = match self {
+            LibinputLog::Debug => ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_DEBUG,
+            LibinputLog::Info => ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_INFO,
+            LibinputLog::Error => ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_ERROR,
+        };
+        // SAFETY: the context is valid for as long as `input` is
+        unsafe {
+            libinput_log_set_handler(input.as_raw_mut(), Some(log_libinput));
+            ffi::libinput_log_set_priority(input.as_raw_mut(), priority);
+        }
+        log::debug!("Set the libinput log priority to {self:?}");
+    }
+
+    /// The level a libinput message of `priority` is logged at
+    pub fn level(priority: ffi::libinput_log_priority) -> log::Level {
+        match priority {
+            ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_DEBUG => log::Level::Debug,
+            ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_INFO => log::Level::Info,
+            _ => log::Level::Error,
+        }
+    }
+}
+
+/// A C `va_list`, which every ABI we run on passes as a pointer
+type VaList = *mut c_void;
+
+type LogHandler =
+    unsafe extern "C" fn(*mut ffi::libinput, ffi::libinput_log_priority, *const c_char, VaList);
+
+// The bindings leave out the functions taking a `va_list`
+extern "C" {
+    fn libinput_log_set_handler(libinput: *mut ffi::libinput, handler: Option<LogHandler>);
+    fn vsnprintf(buf: *mut c_char, size: usize, format: *const c_char, args: VaList) -> c_int;
+}
+
+/// Format a libinput message and log it with the `libinput` target
+unsafe extern "C" fn log_libinput(
+    _: *mut ffi::libinput,
+    priority: ffi::libinput_log_priority,
+    format: *const c_char,
+    args: VaList,
+) {
+    let mut buf = [0 as c_char; 1024];
+    // SAFETY: libinput hands over a format string with the arguments it takes, and vsnprintf
+    // stops at the end of `buf`, which it always terminates
+    let message = unsafe {
+        if vsnprintf(buf.as_mut_ptr(), buf.len(), format, args) < 0 {
+            return;
+        }
+        CStr::from_ptr(buf.as_ptr())
+    };
+    let message = message.to_string_lossy();
+    log::log!(target: "libinput", LibinputLog::level(priority), "{}", message.trim_end());
+}
+
+/// Shows what the gestures do, run when a gesture begins while `modifier` is held
+#[derive(Decode, Serialize, JsonSchema, PartialEq, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Cheatsheet {
+    /// `{kind}` and `{fingers}` are replaced with the type and finger count of the gesture
+    #[knuffel(argument)]
+    pub command: String,
+    #[knuffel(property)]
+    pub modifier: Modifier,
+}
+
+/// Named values for commands, one child node per value such as `accent "#ff8800"`
+#[derive(Decode, PartialEq, Debug, Default, Clone)]
+pub struct Vars {
+    #[knuffel(children)]
+    pub values: Vec<Var>,
+}
+
+#[derive(Decode, PartialEq, Debug, Clone)]
+pub struct Var {
+    #[knuffel(node_name)]
+    pub name: String,
+    #[knuffel(argument)]
+    pub value: VarValue,
+}
+
+/// A string, number or boolean, kept as written for substitution into commands
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarValue(pub String);
+
+impl<S: ErrorSpan> DecodeScalar<S> for VarValue {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>, ctx: &mut Context<S>) {
+        <String as DecodeScalar<S>>::type_check(type_name, ctx)
+    }
+
+    fn raw_decode(
+        value: &Spanned<Literal, S>,
+        ctx: &mut Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        let s = match &**value {
+            Literal::String(s) => s.to_string(),
+            Literal::Int(_) => i64::raw_decode(value, ctx)?.to_string(),
+            Literal::Decimal(_) => f64::raw_decode(value, ctx)?.to_string(),
+            Literal::Bool(b) => b.to_string(),
+            Literal::Null => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::String, value));
+                String::new()
+            }
+        };
+        Ok(Self(s))
+    }
+}
+
+impl Vars {
+    /// Replace every `{var.name}` in `command`, a later definition of a name wins
+    fn substitute(&self, command: &str) -> Result<String, ConfigError> {
+        let mut out = String::new();
+        let mut rest = command;
+        while let Some(start) = rest.find("{var.") {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            let name = &rest[start + "{var.".len()..start + len];
+            let var = self.values.iter().rev().find(|v| v.name == name);
+            let Some(var) = var else {
+                return Err(ConfigError::Invalid(format!(
+                    "{command:?} uses {{var.{name}}}, but there is no such var"
+                )));
+            };
+            out.push_str(&rest[..start]);
+            out.push_str(&var.value.0);
+            rest = &rest[start + len + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Modifier {
+    Ctrl,
+    Shift,
+    Alt,
+    Super,
+}
+
+impl Modifier {
+    /// Linux key codes of the left and right key
+    pub fn keys(self) -> [u32; 2] {
+        match self {
+            Modifier::Ctrl => [29, 97],
+            Modifier::Shift => [42, 54],
+            Modifier::Alt => [56, 100],
+            Modifier::Super => [125, 126],
+        }
+    }
+}
+
+/// Why a config could not be loaded
+#[derive(Debug, Error, Diagnostic)]
+pub enum ConfigError {
+    #[error("Could not read config file {}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// The file is not valid kdl or doesn't match the config schema, with the offending spans
+    #[error(transparent)]
+    Parse(#[from] knuffel::Error),
+    /// The config parsed but contains values that make no sense
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Could not find config file")]
+    NotFound,
+    /// Reloading a config that was read from stdin, which can't be read a second time
+    #[error("The config was read from stdin and can't be reloaded")]
+    Stdin,
 }
 
 impl Config {
-    pub fn read_from_file(file: &Path) -> Result<Self> {
+    /// Read the config from `file`, or from stdin if `file` is `-`
+    pub fn read_from_file(file: &Path) -> Result<Self, ConfigError> {
+        if file == Path::new("-") {
+            let mut s = String::new();
+            io::stdin()
+                .read_to_string(&mut s)
+                .map_err(|source| ConfigError::Io {
+                    path: file.to_path_buf(),
+                    source,
+                })?;
+            return Self::parse_str("<stdin>", &s);
+        }
+        let mut config = Self::read_with_includes(file, &mut Vec::new())?;
+        config.apply_vars()?;
+        config.apply_swipe_defaults();
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parse and validate a config, `name` is only used in error messages. Included files are
+    /// looked up relative to the current directory.
+    pub fn parse_str(name: &str, text: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::parse_unmerged(name, text)?;
+        config.merge_includes(Path::new("."), &mut Vec::new())?;
+        config.apply_vars()?;
+        config.apply_swipe_defaults();
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Read `file` together with the files it includes. `chain` holds the files whose includes
+    /// are being read, to catch files that end up including themselves.
+    fn read_with_includes(file: &Path, chain: &mut Vec<PathBuf>) -> Result<Self, ConfigError> {
         log::debug!("{:?}", &file);
-        match fs::read_to_string(file) {
-            Ok(s) => Ok(parse::<Config>(file.to_str().unwrap(), &s).into_diagnostic()?),
-            _ => bail!("Could not read config file"),
+        let io_error = |source| ConfigError::Io {
+            path: file.to_path_buf(),
+            source,
+        };
+        let path = fs::canonicalize(file).map_err(io_error)?;
+        if chain.contains(&path) {
+            let cycle: Vec<String> = chain
+                .iter()
+                .skip_while(|p| **p != path)
+                .chain([&path])
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(ConfigError::Invalid(format!(
+                "Config files include each other: {}",
+                cycle.join(" -> ")
+            )));
         }
+        let s = fs::read_to_string(&path).map_err(io_error)?;
+        let mut config = Self::parse_unmerged(&file.to_string_lossy(), &s)?;
+        let dir = path.parent().unwrap_or(Path::new("/")).to_path_buf();
+        chain.push(path);
+        let merged = config.merge_includes(&dir, chain);
+        chain.pop();
+        merged?;
+        Ok(config)
+    }
+
+    /// Parse a single file, without its includes, defaults or validation
+    fn parse_unmerged(name: &str, text: &str) -> Result<Self, ConfigError> {
+        let mut config = parse::<Config>(name, text)?;
+        config.gestures = config
+            .gestures
+            .into_iter()
+            .flat_map(|g| match g {
+                Gesture::Group(group) => group.expand(),
+                g => vec![g],
+            })
+            .collect();
+        Ok(config)
     }
 
-    pub fn read_default_config() -> Result<Self> {
-        let config_home = env::var("XDG_CONFIG_HOME")
-            .unwrap_or_else(|_| format!("{}/.config", env::var("HOME").unwrap()));
+    fn merge_includes(&mut self, dir: &Path, chain: &mut Vec<PathBuf>) -> Result<(), ConfigError> {
+        for include in std::mem::take(&mut self.include) {
+            let included = Self::read_with_includes(&dir.join(include), chain)?;
+            self.merge(included);
+        }
+        Ok(())
+    }
+
+    /// Merge an included config into this one. Options it sets win, its gestures, swipe defaults
+    /// and ignored devices are added after these.
+    fn merge(&mut self, other: Config) {
+        self.invert_horizontal |= other.invert_horizontal;
+        self.invert_vertical |= other.invert_vertical;
+        self.rotation = other.rotation.or(self.rotation);
+        self.swipe_hysteresis = other.swipe_hysteresis.or(self.swipe_hysteresis);
+        self.pinch_lock |= other.pinch_lock;
+        self.finger_tolerance = other.finger_tolerance.or(self.finger_tolerance);
+        self.coalesce_updates = other.coalesce_updates.or(self.coalesce_updates);
+        self.libinput_log = other.libinput_log.or(self.libinput_log);
+        if other.device_config.is_some() {
+            self.device_config = other.device_config;
+        }
+        self.ignore_devices.extend(other.ignore_devices);
+        if other.activation_key.is_some() {
+            self.activation_key = other.activation_key;
+        }
+        if other.cheatsheet.is_some() {
+            self.cheatsheet = other.cheatsheet;
+        }
+        for (own, theirs) in [
+            (&mut self.default_swipe_action, other.default_swipe_action),
+            (&mut self.default_pinch_action, other.default_pinch_action),
+            (&mut self.default_hold_action, other.default_hold_action),
+            (&mut self.run_as, other.run_as),
+            (&mut self.dispatcher, other.dispatcher),
+            (&mut self.on_missing_command, other.on_missing_command),
+            (&mut self.screenshot_command, other.screenshot_command),
+        ] {
+            if theirs.is_some() {
+                *own = theirs;
+            }
+        }
+        self.vars.values.extend(other.vars.values);
+        self.swipe_defaults.extend(other.swipe_defaults);
+        self.gestures.extend(other.gestures);
+    }
+
+    /// Substitute the `vars` into the commands of the rules, the default actions and the
+    /// cheatsheet
+    fn apply_vars(&mut self) -> Result<(), ConfigError> {
+        let vars = &self.vars;
+        let commands = self
+            .gestures
+            .iter_mut()
+            .flat_map(Gesture::commands_mut)
+            .chain(
+                [
+                    &mut self.default_swipe_action,
+                    &mut self.default_pinch_action,
+                    &mut self.default_hold_action,
+                    &mut self.dispatcher,
+                    &mut self.on_missing_command,
+                    &mut self.screenshot_command,
+                ]
+                .into_iter()
+                .flatten(),
+            )
+            .chain(self.cheatsheet.as_mut().map(|c| &mut c.command));
+        for command in commands {
+            *command = vars.substitute(command)?;
+        }
+        Ok(())
+    }
+
+    /// Fill the swipe rules from the `swipe-defaults` for their finger count, then from the ones
+    /// for any finger count
+    fn apply_swipe_defaults(&mut self) {
+        let find = |fingers| self.swipe_defaults.iter().find(|d| d.fingers == fingers);
+        for gesture in &mut self.gestures {
+            if let Gesture::Swipe(swipe) = gesture {
+                for defaults in [find(swipe.fingers), find(Fingers::ANY)]
+                    .into_iter()
+                    .flatten()
+                {
+                    swipe.inherit(defaults);
+                }
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(r) = self.rotation {
+            if ![0, 90, 180, 270].contains(&r) {
+                return Err(ConfigError::Invalid(format!(
+                    "rotation must be one of 0, 90, 180 or 270, got {r}"
+                )));
+            }
+        }
+        for gesture in &self.gestures {
+            let problem = match gesture {
+                Gesture::Swipe(s) => s
+                    .dbus
+                    .as_deref()
+                    .and_then(DBusCall::problem)
+                    .or_else(|| s.smoothing_problem())
+                    .or_else(|| progress_problem(s.progress_max)),
+                Gesture::Hold(h) => h.dbus.as_deref().and_then(DBusCall::problem),
+                Gesture::Pinch(p) => p
+                    .value_problem()
+                    .or_else(|| progress_problem(p.progress_max)),
+                _ => None,
+            };
+            if let Some(problem) = problem {
+                return Err(ConfigError::Invalid(problem));
+            }
+            if let Some(Err(problem)) = gesture.hotkey().map(hotkey::parse) {
+                return Err(ConfigError::Invalid(problem));
+            }
+            if let Some((previous, _)) = gesture.requires_previous() {
+                if !self.gestures.iter().any(|g| g.id() == Some(previous)) {
+                    return Err(ConfigError::Invalid(format!(
+                        "requires-previous refers to {previous:?}, but no rule has that id"
+                    )));
+                }
+            }
+        }
+        for (command, program) in self.missing_programs() {
+            log::warn!("{program} is not installed, {command:?} won't work");
+        }
+        Ok(())
+    }
+
+    /// How to run the commands of the rules
+    pub fn runner(&self) -> Runner {
+        Runner {
+            run_as: self.run_as.clone(),
+            on_missing_command: self.on_missing_command.clone(),
+        }
+    }
+
+    /// Commands whose program is not installed, with that program
+    pub fn missing_programs(&self) -> Vec<(&str, &str)> {
+        let options = [
+            &self.default_swipe_action,
+            &self.default_pinch_action,
+            &self.default_hold_action,
+            &self.dispatcher,
+            &self.on_missing_command,
+            &self.screenshot_command,
+        ];
+        self.gestures
+            .iter()
+            .flat_map(Gesture::commands)
+            .chain(options.into_iter().flatten().map(String::as_str))
+            .chain(self.cheatsheet.as_ref().map(|c| c.command.as_str()))
+            .filter_map(|command| Some((command, missing_program(command)?)))
+            .collect()
+    }
+
+    /// Whether gestures from the device called `name` are excluded by `ignore-devices`
+    pub fn ignores_device(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        self.ignore_devices
+            .iter()
+            .any(|ignored| name.contains(&ignored.to_lowercase()))
+    }
+
+    /// Map swipe deltas as reported by the device onto screen directions
+    pub fn transform_swipe(&self, dx: f64, dy: f64) -> (f64, f64) {
+        // y grows downwards, so this turns the movement clockwise
+        let (dx, dy) = match self.rotation.unwrap_or_default() {
+            90 => (-dy, dx),
+            180 => (-dx, -dy),
+            270 => (dy, -dx),
+            _ => (dx, dy),
+        };
+        (
+            if self.invert_horizontal { -dx } else { dx },
+            if self.invert_vertical { -dy } else { dy },
+        )
+    }
+
+    pub fn read_default_config() -> Result<Self, ConfigError> {
+        if let Ok(text) = env::var(CONFIG_ENV) {
+            log::info!("Using the configuration from ${CONFIG_ENV}");
+            return Self::parse_str(&format!("${CONFIG_ENV}"), &text)
+                .inspect_err(|e| log::error!("{e}"));
+        }
+        let config_home = config_home();
 
         log::debug!("{:?}", &config_home);
 
@@ -35,6 +633,11 @@ impl Config {
             }
         }
 
-        bail!("Could not find config file")
+        Err(ConfigError::NotFound)
     }
 }
+
+/// `$XDG_CONFIG_HOME`, falling back to `$HOME/.config`
+pub fn config_home() -> String {
+    env::var("XDG_CONFIG_HOME").unwrap_or_else(|_| format!("{}/.config", env::var("HOME").unwrap()))
+}
@@ -0,0 +1,107 @@
+use knuffel::Decode;
+use nix::unistd::User;
+use schemars::JsonSchema;
+use serde::Serialize;
+use zbus::blocking::{connection, Connection};
+use zbus::zvariant::{ObjectPath, StructureBuilder, Value};
+use zbus::Message;
+
+/// A D-Bus method call made when a rule fires
+#[derive(Decode, Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub struct DBusCall {
+    /// Bus name of the service, e.g. `org.mpris.MediaPlayer2.spotify`
+    #[knuffel(argument)]
+    pub destination: String,
+    #[knuffel(argument)]
+    pub path: String,
+    /// Interface and method name, e.g. `org.mpris.MediaPlayer2.Player.Next`
+    #[knuffel(argument)]
+    pub method: String,
+    /// Arguments in `dbus-send` notation, `string:hello` or `int32:5`
+    #[knuffel(arguments)]
+    pub args: Vec<String>,
+    /// Call the method on the system bus instead of the session bus
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub system: bool,
+}
+
+impl DBusCall {
+    /// Why the call can't be made, checked when the config is loaded
+    pub fn problem(&self) -> Option<String> {
+        if !self.method.contains('.') {
+            return Some(format!(
+                "D-Bus method {:?} has to include its interface",
+                self.method
+            ));
+        }
+        self.args.iter().find_map(|arg| arg_value(arg).err())
+    }
+
+    /// The method call, without waiting for a reply just like `dbus-send` by default
+    pub fn message(&self) -> zbus::Result<Message> {
+        let (interface, member) = self.method.rsplit_once('.').unwrap_or_default();
+        let message = Message::method_call(self.path.as_str(), member)?
+            .destination(self.destination.as_str())?
+            .interface(interface)?;
+        let args = self.args.iter().map(|arg| arg_value(arg));
+        let args = args
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(zbus::Error::Failure)?;
+        if args.is_empty() {
+            return message.build(&());
+        }
+        let body = args
+            .into_iter()
+            .fold(StructureBuilder::new(), StructureBuilder::append_field);
+        message.build(&body.build()?)
+    }
+
+    /// Make the call on the system bus or the session bus, the one of `run_as` if set
+    pub fn call(&self, run_as: Option<&str>) -> zbus::Result<()> {
+        let connection = match (self.system, run_as) {
+            (true, _) => Connection::system()?,
+            (false, None) => Connection::session()?,
+            (false, Some(name)) => {
+                let user = User::from_name(name)
+                    .ok()
+                    .flatten()
+                    .ok_or_else(|| zbus::Error::Failure(format!("No run-as user {name:?}")))?;
+                let address = format!("unix:path=/run/user/{}/bus", user.uid);
+                connection::Builder::address(address.as_str())?.build()?
+            }
+        };
+        connection.send(&self.message()?)
+    }
+}
+
+/// An argument in `dbus-send` notation as the value it stands for
+fn arg_value(arg: &str) -> Result<Value<'static>, String> {
+    let Some((kind, value)) = arg.split_once(':') else {
+        return Err(format!(
+            "D-Bus argument {arg:?} has to start with its type, like string:"
+        ));
+    };
+    let invalid = || format!("D-Bus argument {arg:?} is not a valid {kind}");
+    Ok(match kind {
+        "string" => Value::from(value.to_string()),
+        "objpath" => ObjectPath::try_from(value.to_string())
+            .map_err(|_| invalid())?
+            .into(),
+        "boolean" => value.parse::<bool>().map_err(|_| invalid())?.into(),
+        "byte" => value.parse::<u8>().map_err(|_| invalid())?.into(),
+        "int16" => value.parse::<i16>().map_err(|_| invalid())?.into(),
+        "uint16" => value.parse::<u16>().map_err(|_| invalid())?.into(),
+        "int32" => value.parse::<i32>().map_err(|_| invalid())?.into(),
+        "uint32" => value.parse::<u32>().map_err(|_| invalid())?.into(),
+        "int64" => value.parse::<i64>().map_err(|_| invalid())?.into(),
+        "uint64" => value.parse::<u64>().map_err(|_| invalid())?.into(),
+        "double" => value.parse::<f64>().map_err(|_| invalid())?.into(),
+        _ => {
+            return Err(format!(
+                "D-Bus argument {arg:?} has an unknown type {kind:?}"
+            ))
+        }
+    })
+}
@@ -1,11 +1,15 @@
 use std::{
+    cell::Cell,
     fs::OpenOptions,
     os::{
         fd::{AsFd, OwnedFd},
         unix::prelude::OpenOptionsExt,
     },
     path::Path,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use input::{
@@ -14,7 +18,9 @@ use input::{
             GestureEndEvent, GestureEventCoordinates, GestureEventTrait, GestureHoldEvent,
             GesturePinchEvent, GesturePinchEventTrait, GestureSwipeEvent,
         },
-        Event, EventTrait, GestureEvent,
+        keyboard::{KeyState, KeyboardEvent, KeyboardEventTrait},
+        switch::{Switch, SwitchEvent, SwitchState},
+        DeviceEvent, Event, EventTrait, GestureEvent,
     },
     DeviceCapability, Libinput, LibinputInterface,
 };
@@ -22,20 +28,155 @@ use miette::{miette, Result};
 use nix::{
     fcntl::OFlag,
     poll::{poll, PollFd, PollFlags, PollTimeout},
+    time::{clock_gettime, ClockId},
 };
 
-use crate::config::Config;
-use crate::gestures::{hold::*, pinch::*, swipe::*, *};
-use crate::utils::exec_command_from_string;
-use crate::xdo_handler::XDoHandler;
+use crate::config::{Config, Modifier};
+use crate::gestures::{hold::*, in_fallback_order, pinch::*, swipe::*, *};
+use crate::hotkey::Hotkeys;
+use crate::power::{Power, POWER_SUPPLY_DIR};
+use crate::stats::Stats;
+use crate::tune::Tune;
+use crate::utils::{
+    call_dbus, exec_command_from_string, exec_command_queued, exec_command_then,
+    exec_command_unless_busy, exec_command_with_env, play_sound, substitute_progress,
+    substitute_value, Runner, Serial,
+};
+use crate::xdo_handler::{HeldKeys, XDoHandler};
 
-use std::collections::HashMap;
 use parking_lot::RwLock;
+use std::collections::HashMap;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Consecutive dispatch failures after which the libinput context is recreated
+const MAX_DISPATCH_FAILURES: u32 = 5;
+/// Upper bound for the delay between attempts to recreate the libinput context
+const MAX_REINIT_BACKOFF: Duration = Duration::from_secs(30);
+/// Attempts to recreate the libinput context before giving up, a few minutes with the backoff
+const MAX_REINIT_ATTEMPTS: u32 = 10;
+/// Weight of the newest update in the smoothed swipe velocity
+const VELOCITY_SMOOTHING: f64 = 0.5;
+/// A swipe whose last update is older than this ended at rest
+const VELOCITY_WINDOW: Duration = Duration::from_millis(100);
+/// Updates of a swipe that its speed is measured over before `grab-speed` decides on a drag
+const GRAB_UPDATES: u32 = 3;
+/// Movement of a swipe update, and change of scale and degrees of rotation of a pinch update,
+/// below which the fingers count as resting for `idle-timeout`
+const IDLE_DISTANCE: f64 = 0.5;
+const IDLE_SCALE: f64 = 0.005;
+const IDLE_ANGLE: f64 = 0.2;
+/// How long a power state read from sysfs is used before reading it again
+const POWER_CACHE: Duration = Duration::from_secs(5);
+/// How often the main loop looks for a pause while a drag or repeated key is held down
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+/// Default time between the clicks of a double or triple click, well within the double-click
+/// timeout of common toolkits
+pub const DEFAULT_CLICK_INTERVAL_MS: u64 = 50;
+
+/// A gesture event as the handler sees it. Unlike libinput's events these can be recorded,
+/// replayed and made up in tests.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GestureInput {
+    SwipeBegin {
+        fingers: i32,
+    },
+    SwipeUpdate {
+        dx: f64,
+        dy: f64,
+    },
+    /// With the count of fingers still down at the end, when known
+    SwipeEnd {
+        fingers: Option<i32>,
+        cancelled: bool,
+    },
+    PinchBegin {
+        fingers: i32,
+    },
+    PinchUpdate {
+        scale: f64,
+        angle_delta: f64,
+    },
+    PinchEnd {
+        cancelled: bool,
+    },
+    HoldBegin {
+        fingers: i32,
+    },
+    HoldEnd {
+        cancelled: bool,
+    },
+    /// The `hotkey` of the rule at this index was pressed
+    Hotkey {
+        rule: usize,
+    },
+}
+
+impl GestureInput {
+    pub fn from_libinput(event: GestureEvent) -> Option<Self> {
+        let input = match event {
+            GestureEvent::Swipe(GestureSwipeEvent::Begin(e)) => Self::SwipeBegin {
+                fingers: e.finger_count(),
+            },
+            GestureEvent::Swipe(GestureSwipeEvent::Update(e)) => Self::SwipeUpdate {
+                dx: e.dx(),
+                dy: e.dy(),
+            },
+            GestureEvent::Swipe(GestureSwipeEvent::End(e)) => Self::SwipeEnd {
+                fingers: Some(e.finger_count()),
+                cancelled: e.cancelled(),
+            },
+            GestureEvent::Pinch(GesturePinchEvent::Begin(e)) => Self::PinchBegin {
+                fingers: e.finger_count(),
+            },
+            GestureEvent::Pinch(GesturePinchEvent::Update(e)) => Self::PinchUpdate {
+                scale: e.scale(),
+                angle_delta: e.angle_delta(),
+            },
+            GestureEvent::Pinch(GesturePinchEvent::End(e)) => Self::PinchEnd {
+                cancelled: e.cancelled(),
+            },
+            GestureEvent::Hold(GestureHoldEvent::Begin(e)) => Self::HoldBegin {
+                fingers: e.finger_count(),
+            },
+            GestureEvent::Hold(GestureHoldEvent::End(e)) => Self::HoldEnd {
+                cancelled: e.cancelled(),
+            },
+            _ => return None,
+        };
+        Some(input)
+    }
+
+    /// Fold the update `next` into this one if it is an update of the same kind, as if libinput
+    /// had reported both as one
+    fn merge(&mut self, next: &GestureInput) -> bool {
+        match (self, next) {
+            (Self::SwipeUpdate { dx, dy }, Self::SwipeUpdate { dx: ndx, dy: ndy }) => {
+                *dx += ndx;
+                *dy += ndy;
+                true
+            }
+            (
+                Self::PinchUpdate { scale, angle_delta },
+                Self::PinchUpdate {
+                    scale: nscale,
+                    angle_delta: nangle,
+                },
+            ) => {
+                // The scale is relative to the start of the pinch already
+                *scale = *nscale;
+                *angle_delta += nangle;
+                true
+            }
+            _ => false,
+        }
+    }
+}
 
 // Add cache struct
 #[derive(Debug)]
 struct GestureCache {
-    swipe_gestures: HashMap<i32, Vec<Gesture>>,
+    swipe_gestures: HashMap<Fingers, Vec<(usize, Gesture)>>,
     last_update: std::time::Instant,
 }
 
@@ -48,25 +189,204 @@ impl GestureCache {
     }
 }
 
+/// Longest rest of the fingers during a swipe or pinch, for `idle-timeout`
+#[derive(Debug, Default)]
+struct Idle {
+    /// When the gesture last moved noticeably, `None` if it didn't since `Begin`
+    moved: Option<Instant>,
+    longest: Duration,
+}
+
+impl Idle {
+    /// Note an update of a gesture that `began` then, which `moved` noticeably or not, and return
+    /// the longest rest so far
+    fn track(&mut self, moved: bool, began: Instant) -> Duration {
+        let now = Instant::now();
+        let rest = now.duration_since(self.moved.unwrap_or(began));
+        self.longest = self.longest.max(rest);
+        if moved {
+            self.moved = Some(now);
+        }
+        self.longest
+    }
+}
+
+/// Bookkeeping for the pinch currently in progress, reset on every `Begin`
+#[derive(Debug, Default)]
+struct PinchState {
+    /// Rotation accumulated since `Begin`, in degrees
+    angle: f64,
+    /// Scale of the previous update, for [`ScaleMode::Delta`]
+    scale: Option<f64>,
+    /// What the pinch was committed to with `pinch-lock`
+    kind: Option<PinchKind>,
+    idle: Idle,
+}
+
+/// Bookkeeping for the swipe currently in progress, reset on every `Begin`
+#[derive(Debug, Default)]
+struct SwipeState {
+    /// Movement accumulated since `Begin`
+    dx: f64,
+    dy: f64,
+    /// Rules whose overshoot action already fired during this swipe
+    overshoot_fired: Vec<usize>,
+    /// Movement at the point furthest from where the swipe began
+    farthest: (f64, f64),
+    /// Smoothed speed of the recent updates in units per second
+    velocity: f64,
+    last_update: Option<Instant>,
+    /// Direction of the latest update and since when the swipe has kept it
+    direction: Option<(SwipeDir, Instant)>,
+    /// Direction each rule with `direction-debounce` last acted on
+    settled: HashMap<usize, SwipeDir>,
+    /// Movement of the previous drag step, for `drag-smoothing`
+    drag_step: Option<(f64, f64)>,
+    /// Updates received since `Begin`
+    updates: u32,
+    /// Started too fast for the drag rules with `grab-speed`, which leave it to the others
+    flicked: bool,
+    /// The drag started once the swipe passed a `drag-threshold`, which takes the swipe away from
+    /// the directional rules
+    past_threshold: bool,
+    idle: Idle,
+}
+
+impl SwipeState {
+    /// Fold an update that moved `distance` into the velocity
+    fn track_velocity(&mut self, distance: f64) {
+        let now = Instant::now();
+        if let Some(last) = self.last_update.replace(now) {
+            let dt = now.duration_since(last).as_secs_f64();
+            if dt > 0.0 {
+                self.velocity += VELOCITY_SMOOTHING * (distance / dt - self.velocity);
+            }
+        }
+    }
+
+    /// Note the direction of an update, restarting the debounce when it changed
+    fn track_direction(&mut self, dir: &SwipeDir) {
+        if self.direction.as_ref().is_none_or(|(d, _)| d != dir) {
+            self.direction = Some((dir.clone(), Instant::now()));
+        }
+    }
+
+    /// Velocity as the fingers lift, zero if they rested before that
+    fn end_velocity(&self) -> f64 {
+        match self.last_update {
+            Some(last) if last.elapsed() <= VELOCITY_WINDOW => self.velocity,
+            _ => 0.0,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct EventHandler {
     config: Arc<RwLock<Config>>, // Changed from std::sync::RwLock
+    stats: Arc<RwLock<Stats>>,
+    /// Set through IPC, events are drained without acting on them while paused
+    paused: Arc<AtomicBool>,
     event: Gesture,
     cache: GestureCache,
+    swipe: SwipeState,
+    /// Button and modifiers pressed for a drag during the current swipe. Kept apart from the
+    /// swipe's bookkeeping and from the rules, so the end of the swipe lets go of it either way.
+    pressed: Option<(i32, Option<String>)>,
+    /// Keys of the `repeat-key` rules the current swipe goes the way of, held down until it
+    /// turns away or ends
+    repeating: Vec<(usize, Option<HeldKeys>)>,
+    pinch: PinchState,
+    /// Update actions fired per rule during the current swipe or pinch
+    repeats: HashMap<usize, u32>,
+    /// Rules whose start action fired during the current swipe or pinch
+    started: Vec<usize>,
+    /// When the current swipe or pinch began
+    began: Instant,
+    /// Pointer position when the current hold started, as a fraction of the screen size
+    hold_position: Option<(f64, f64)>,
+    /// Keys held down on any keyboard, to tell when the cheatsheet modifier is held
+    pressed_keys: Vec<u32>,
+    /// Power state and when it was read, for rules with `when-power`
+    power: Cell<Option<(Option<Power>, Instant)>>,
+    /// Whether a 2-in-1 is folded into a tablet, for rules with `when-tablet-mode`
+    tablet_mode: bool,
+    /// Swipe rules with a `double` whose actions wait for a second swipe, with when they matched
+    pending: Vec<(usize, Instant)>,
+    /// Rules with a `delay` that fired, whether as the `double` of a swipe, with when their actions
+    /// are due
+    delayed: Vec<(usize, bool, Instant)>,
+    /// Rule with `confirm` that fired, whether as the `double` of a swipe, with until when a tap
+    /// runs its actions
+    armed: Option<(usize, bool, Instant)>,
+    /// Position in the `cycle` of each rule that has one, of the command that runs next
+    cycles: HashMap<usize, usize>,
+    /// Flags of the pinch rules with `skip-busy`, set while their last `update` command runs
+    busy: HashMap<usize, Arc<AtomicBool>>,
+    /// Command queues of the rules with `serialize`, by rule
+    serials: HashMap<usize, Serial>,
+    /// Update held back by `coalesce-updates`, with the ones after it merged in
+    coalesced: Option<GestureInput>,
+    /// When the handler last acted on an update, the next one waits for the `coalesce-updates`
+    /// window after it
+    last_update: Option<Instant>,
+    /// Keys grabbed for the rules' `hotkey`s, on Xorg only
+    hotkeys: Option<Hotkeys>,
+    /// How often to log that the handler is still polling, and when it last did
+    heartbeat: Option<(Duration, Instant)>,
+    /// Gesture events dispatched since the last heartbeat
+    beat_events: u64,
+    /// Made up by `gestures inject`, which acts on them without waiting for the activation key
+    injected: bool,
+    /// Reloads of the config seen so far, the state above refers to rules by their position in it
+    reloads: u32,
 }
 
 impl EventHandler {
-    pub fn new(config: Arc<RwLock<Config>>) -> Self {
-        Self {
+    pub fn new(
+        config: Arc<RwLock<Config>>,
+        stats: Arc<RwLock<Stats>>,
+        paused: Arc<AtomicBool>,
+    ) -> Self {
+        let mut handler = Self {
             config,
+            stats,
+            paused,
             event: Gesture::None,
             cache: GestureCache::new(),
-        }
+            swipe: SwipeState::default(),
+            pressed: None,
+            repeating: Vec::new(),
+            pinch: PinchState::default(),
+            repeats: HashMap::new(),
+            started: Vec::new(),
+            began: Instant::now(),
+            hold_position: None,
+            pressed_keys: Vec::new(),
+            power: Cell::new(None),
+            tablet_mode: false,
+            pending: Vec::new(),
+            delayed: Vec::new(),
+            armed: None,
+            cycles: HashMap::new(),
+            busy: HashMap::new(),
+            serials: HashMap::new(),
+            coalesced: None,
+            last_update: None,
+            hotkeys: None,
+            heartbeat: None,
+            beat_events: 0,
+            injected: false,
+            reloads: 0,
+        };
+        // Otherwise swipes only match once the cache goes stale a second later
+        handler.update_cache();
+        handler
     }
 
     pub fn init(&mut self, input: &mut Libinput) -> Result<()> {
         log::debug!("{:?}  {:?}", &self, &input);
-        self.init_ctx(input).expect("Could not initialize libinput");
+        self.init_ctx(input)
+            .map_err(|_| miette!("Could not initialize libinput"))?;
         if self.has_gesture_device(input) {
             Ok(())
         } else {
@@ -75,6 +395,10 @@ impl EventHandler {
     }
 
     fn init_ctx(&mut self, input: &mut Libinput) -> Result<(), ()> {
+        // Before the seat is assigned, to include what libinput says about the devices it finds
+        if let Some(priority) = self.config.read().libinput_log {
+            priority.apply(input);
+        }
         input.udev_assign_seat("seat0")?;
         Ok(())
     }
@@ -89,8 +413,16 @@ impl EventHandler {
         for event in &mut *input {
             if let Event::Device(e) = event {
                 log::debug!("Device: {:?}", &e);
-                if e.device().has_capability(DeviceCapability::Gesture) {
+                let mut device = e.device();
+                if device.has_capability(DeviceCapability::Gesture) {
+                    if self.config.read().ignores_device(device.name()) {
+                        log::debug!("Ignoring gesture device {}", device.name());
+                        continue;
+                    }
                     log::debug!("Found gesture device");
+                    if let Some(device_config) = &self.config.read().device_config {
+                        device_config.apply(&mut device);
+                    }
                     return true;
                 }
             }
@@ -101,12 +433,43 @@ impl EventHandler {
     }
 
     pub fn main_loop(&mut self, input: &mut Libinput, xdoh: &mut XDoHandler) -> Result<()> {
+        let mut failures = 0;
         loop {
-            let mut fds = [PollFd::new(input.as_fd(), PollFlags::POLLIN)];
-            match poll(&mut fds, PollTimeout::NONE) {
-                Ok(_) => {
-                    self.handle_event(input, xdoh)?;
-                }
+            // Wake up in time for the actions of swipes that didn't become a double and of rules
+            // with a delay, for the heartbeat, and to notice a pause while keys are held down
+            let timeout = self
+                .pending_timeout()
+                .into_iter()
+                .chain(self.holds_keys().then_some(PAUSE_POLL))
+                .min()
+                .map_or(PollTimeout::NONE, |t| {
+                    t.try_into().unwrap_or(PollTimeout::MAX)
+                });
+            let polled = {
+                let mut fds = vec![PollFd::new(input.as_fd(), PollFlags::POLLIN)];
+                let hotkeys = self.hotkeys.as_ref().filter(|h| !h.is_empty());
+                fds.extend(hotkeys.map(|h| PollFd::new(h.fd(), PollFlags::POLLIN)));
+                poll(&mut fds, timeout)
+            };
+            if let Err(e) = self.flush_pending(xdoh) {
+                log::error!("Failed to run the actions of a single swipe or delayed rule: {e}");
+            }
+            if let Err(e) = self.fire_hotkeys(xdoh) {
+                log::error!("Failed to run the actions of a hotkey: {e}");
+            }
+            self.beat();
+            match polled {
+                Ok(_) => match self.handle_event(input, xdoh) {
+                    Ok(()) => failures = 0,
+                    Err(e) => {
+                        failures += 1;
+                        log::error!("Failed to handle input events ({failures} in a row): {e}");
+                        if failures >= MAX_DISPATCH_FAILURES {
+                            self.reinit(input)?;
+                            failures = 0;
+                        }
+                    }
+                },
                 Err(e) => {
                     // Only break if it's not an interrupt
                     if e != nix::errno::Errno::EINTR {
@@ -117,45 +480,684 @@ impl EventHandler {
         }
     }
 
+    /// Replace `input` with a fresh libinput context, retrying with exponential backoff until a
+    /// gesture device shows up again. Fails once `MAX_REINIT_ATTEMPTS` attempts didn't find one.
+    fn reinit(&mut self, input: &mut Libinput) -> Result<()> {
+        let mut backoff = Duration::from_millis(500);
+        let mut attempt = 1;
+        loop {
+            log::warn!("Recreating libinput context");
+            *input = Libinput::new_with_udev(Interface);
+            match self.init(input) {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= MAX_REINIT_ATTEMPTS => {
+                    return Err(e.wrap_err(format!(
+                        "Gave up recreating the libinput context after {attempt} attempts"
+                    )));
+                }
+                Err(e) => {
+                    log::error!("{e}, retrying in {backoff:?}");
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(MAX_REINIT_BACKOFF);
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
     pub fn handle_event(&mut self, input: &mut Libinput, xdoh: &mut XDoHandler) -> Result<()> {
-        input.dispatch().unwrap();
+        input
+            .dispatch()
+            .map_err(|e| miette!("Failed to dispatch input events: {}", e))?;
+        if self.paused.load(Ordering::Relaxed) {
+            input.for_each(drop);
+            self.release_drag(xdoh);
+            self.event = Gesture::None;
+            return Ok(());
+        }
         for event in input {
-            if let Event::Gesture(e) = event {
-                match e {
-                    GestureEvent::Pinch(e) => self.handle_pinch_event(e)?,
-                    GestureEvent::Swipe(e) => self.handle_swipe_event(e, xdoh)?,
-                    GestureEvent::Hold(e) => self.handle_hold_event(e)?,
-                    _ => (),
+            match event {
+                Event::Gesture(e) => {
+                    if self.config.read().ignores_device(e.device().name()) {
+                        continue;
+                    }
+                    let time = e.time_usec();
+                    if let Some(e) = GestureInput::from_libinput(e) {
+                        let end = matches!(
+                            e,
+                            GestureInput::SwipeEnd { .. }
+                                | GestureInput::PinchEnd { .. }
+                                | GestureInput::HoldEnd { .. }
+                        );
+                        self.dispatch(e, xdoh)?;
+                        if end {
+                            self.record_latency(time);
+                        }
+                    }
+                }
+                Event::Device(e) => self.handle_device_event(e, xdoh),
+                Event::Switch(SwitchEvent::Toggle(e)) if e.switch() == Some(Switch::TabletMode) => {
+                    self.set_tablet_mode(e.switch_state() == SwitchState::On)
                 }
+                Event::Keyboard(KeyboardEvent::Key(e)) => {
+                    self.handle_key(e.key(), e.key_state() == KeyState::Pressed)
+                }
+                _ => (),
             }
         }
         Ok(())
     }
 
-    fn handle_hold_event(&mut self, event: GestureHoldEvent) -> Result<()> {
+    /// Record how long ago libinput reported an event at `event_usec`, on the monotonic clock it
+    /// timestamps events with
+    fn record_latency(&self, event_usec: u64) {
+        let Ok(now) = clock_gettime(ClockId::CLOCK_MONOTONIC) else {
+            return;
+        };
+        let now_usec = now.tv_sec() as u64 * 1_000_000 + now.tv_nsec() as u64 / 1000;
+        let latency = Duration::from_micros(now_usec.saturating_sub(event_usec));
+        log::debug!("Dispatched the end of the gesture {latency:?} after libinput reported it");
+        self.stats.write().record_latency(latency);
+    }
+
+    /// Forget what was kept about the rules of a config that was since reloaded, as their
+    /// positions now belong to other rules. A drag in progress keeps its button until it ends.
+    fn follow_reload(&mut self) {
+        let reloads = self.config.read().reloads;
+        if reloads == self.reloads {
+            return;
+        }
+        log::debug!("Config was reloaded, dropping the state of its previous rules");
+        self.reloads = reloads;
+        self.pending.clear();
+        self.delayed.clear();
+        self.armed = None;
+        self.repeats.clear();
+        self.started.clear();
+        self.swipe.overshoot_fired.clear();
+        self.swipe.settled.clear();
+        self.cycles.clear();
+        self.busy.clear();
+        // Queued commands still run, their queues end after them
+        self.serials.clear();
+        self.update_cache();
+        if let Some(hotkeys) = &mut self.hotkeys {
+            hotkeys.grab(&self.config.read().gestures);
+        }
+    }
+
+    /// Act on a single gesture event
+    pub fn dispatch(&mut self, event: GestureInput, xdoh: &mut XDoHandler) -> Result<()> {
+        self.follow_reload();
+        self.beat_events += 1;
+        let Some(event) = self.coalesce(event, xdoh)? else {
+            return Ok(());
+        };
+        if let GestureInput::SwipeBegin { .. } = event {
+            // A swipe whose end never came may still hold its button
+            self.release_drag(xdoh);
+        }
+        let ends_swipe = matches!(event, GestureInput::SwipeEnd { .. });
+        let result = self.act_on(event, xdoh);
+        if ends_swipe {
+            // Whether or not a rule released it, e.g. after a reload left no drag rule to match
+            self.release_drag(xdoh);
+        }
+        result
+    }
+
+    /// Hold back updates that come within `coalesce-updates` of the last one acted on, merged
+    /// into one. Returns the event to act on now, if any. Other events let the held back update
+    /// through first.
+    fn coalesce(
+        &mut self,
+        event: GestureInput,
+        xdoh: &mut XDoHandler,
+    ) -> Result<Option<GestureInput>> {
+        let is_update = matches!(
+            event,
+            GestureInput::SwipeUpdate { .. } | GestureInput::PinchUpdate { .. }
+        );
+        if !is_update || self.config.read().coalesce_updates.is_none() {
+            self.flush_coalesced(xdoh, true)?;
+            self.last_update = None;
+            return Ok(Some(event));
+        }
+        let merged = self
+            .coalesced
+            .as_mut()
+            .is_some_and(|held| held.merge(&event));
+        if !merged {
+            self.flush_coalesced(xdoh, true)?;
+            self.coalesced = Some(event);
+        }
+        self.flush_coalesced(xdoh, false)?;
+        Ok(None)
+    }
+
+    /// Act on the update held back by `coalesce-updates` once its window is over, or right away
+    /// with `now`
+    fn flush_coalesced(&mut self, xdoh: &mut XDoHandler, now: bool) -> Result<()> {
+        if !now && self.coalesce_due().is_some_and(|due| !due.is_zero()) {
+            return Ok(());
+        }
+        let Some(update) = self.coalesced.take() else {
+            return Ok(());
+        };
+        self.last_update = Some(Instant::now());
+        self.act_on(update, xdoh)
+    }
+
+    /// Time until the held back update is due, if there is one
+    fn coalesce_due(&self) -> Option<Duration> {
+        self.coalesced.as_ref()?;
+        let window = self.config.read().coalesce_updates.unwrap_or_default();
+        let window = Duration::from_millis(window);
+        let since = self.last_update.map(|at| at.elapsed());
+        Some(since.map_or(Duration::ZERO, |since| window.saturating_sub(since)))
+    }
+
+    /// Hand a gesture event to the dispatcher or to the rules
+    fn act_on(&mut self, event: GestureInput, xdoh: &mut XDoHandler) -> Result<()> {
+        let activation_key = self.config.read().activation_key;
+        let is_hotkey = matches!(event, GestureInput::Hotkey { .. });
+        let bypass = is_hotkey || self.injected;
+        if activation_key.is_some_and(|key| !bypass && !self.is_held(key)) {
+            if !matches!(self.event, Gesture::None) {
+                log::debug!("Activation key released, cancelling the gesture");
+                self.release_drag(xdoh);
+                self.started.clear();
+                self.event = Gesture::None;
+            }
+            return Ok(());
+        }
+        let begin = match event {
+            GestureInput::SwipeBegin { fingers } => Some(("swipe", fingers)),
+            GestureInput::PinchBegin { fingers } => Some(("pinch", fingers)),
+            GestureInput::HoldBegin { fingers } => Some(("hold", fingers)),
+            _ => None,
+        };
+        if let Some((confirmed, double)) = self.check_armed(&event) {
+            // The tap only confirms, no rule acts on it
+            self.event = Gesture::None;
+            return self.confirm(confirmed, double, xdoh);
+        }
+        if let Some((kind, fingers)) = begin {
+            if self.show_cheatsheet(kind, fingers)? {
+                // Without a gesture in progress its updates and end are ignored
+                self.event = Gesture::None;
+                return Ok(());
+            }
+        }
+        let dispatcher = self.config.read().dispatcher.clone();
+        if let Some(dispatcher) = dispatcher {
+            self.run_dispatcher(event, &dispatcher);
+            return Ok(());
+        }
+        match event {
+            GestureInput::SwipeBegin { fingers } => self.handle_swipe_begin(Fingers(fingers), xdoh),
+            GestureInput::SwipeUpdate { dx, dy } => {
+                let (dx, dy) = self.config.read().transform_swipe(dx, dy);
+                self.handle_swipe_update(dx, dy, xdoh)
+            }
+            GestureInput::SwipeEnd { fingers, cancelled } => {
+                self.handle_swipe_end(fingers.map(Fingers), cancelled, xdoh)
+            }
+            GestureInput::PinchBegin { .. }
+            | GestureInput::PinchUpdate { .. }
+            | GestureInput::PinchEnd { .. } => self.handle_pinch_event(event, xdoh),
+            GestureInput::HoldBegin { .. } | GestureInput::HoldEnd { .. } => {
+                self.handle_hold_event(event, xdoh)
+            }
+            GestureInput::Hotkey { rule } => self.handle_hotkey(rule, xdoh),
+        }
+    }
+
+    /// The armed rule that `event` confirms, if it is the tap of fingers that lift again without
+    /// moving in time. Any swipe or pinch in between disarms it.
+    fn check_armed(&mut self, event: &GestureInput) -> Option<(usize, bool)> {
+        let (idx, double, until) = self.armed?;
         match event {
-            GestureHoldEvent::Begin(e) => {
-                self.event = Gesture::Hold(Hold {
-                    fingers: e.finger_count(),
-                    action: None,
-                })
+            GestureInput::HoldEnd { cancelled: false } if Instant::now() <= until => {
+                self.armed = None;
+                Some((idx, double))
             }
-            GestureHoldEvent::End(_e) => {
+            GestureInput::SwipeBegin { .. } | GestureInput::PinchBegin { .. } => {
+                log::debug!("Rule {idx} was not confirmed, dropping its actions");
+                self.armed = None;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Run the actions of a rule that was confirmed with a tap, after its `delay` if it has one
+    fn confirm(&mut self, idx: usize, double: bool, xdoh: &mut XDoHandler) -> Result<()> {
+        log::debug!("Rule {idx} confirmed, running its actions");
+        let delay = match self.config.read().gestures.get(idx) {
+            Some(Gesture::Swipe(j)) => j.delay,
+            Some(Gesture::Pinch(j)) => j.delay,
+            Some(Gesture::Hold(j)) => j.delay,
+            _ => None,
+        };
+        self.delayed
+            .push((idx, double, due_in(delay.unwrap_or_default())));
+        self.flush_pending(xdoh)
+    }
+
+    /// Run the `dispatcher` for an event, with the gesture described by `GESTURE_*` variables in
+    /// its environment
+    fn run_dispatcher(&mut self, event: GestureInput, dispatcher: &str) {
+        let config = self.config.clone();
+        let config = config.read();
+        let (phase, cancelled) = match event {
+            GestureInput::SwipeBegin { .. }
+            | GestureInput::PinchBegin { .. }
+            | GestureInput::HoldBegin { .. } => ("begin", None),
+            GestureInput::SwipeUpdate { .. } | GestureInput::PinchUpdate { .. } => ("update", None),
+            GestureInput::SwipeEnd { cancelled, .. }
+            | GestureInput::PinchEnd { cancelled }
+            | GestureInput::HoldEnd { cancelled } => ("end", Some(cancelled)),
+            GestureInput::Hotkey { rule } => {
+                // The dispatcher is told about gestures, which a hotkey isn't
+                log::debug!("Ignoring the hotkey of rule {rule}, the dispatcher acts instead");
+                return;
+            }
+        };
+        let mut env = Vec::new();
+        match event {
+            GestureInput::SwipeBegin { fingers } => {
+                self.event = Gesture::Swipe(Swipe::new(Fingers(fingers)));
+                self.swipe = SwipeState::default();
+            }
+            GestureInput::SwipeUpdate { dx, dy } => {
+                let (dx, dy) = config.transform_swipe(dx, dy);
+                self.swipe.dx += dx;
+                self.swipe.dy += dy;
+                env.push(("GESTURE_DIRECTION", SwipeDir::dir(dx, dy).to_string()));
+                env.push(("GESTURE_DX", dx.to_string()));
+                env.push(("GESTURE_DY", dy.to_string()));
+            }
+            GestureInput::SwipeEnd { .. } => {
+                let (dx, dy) = (self.swipe.dx, self.swipe.dy);
+                env.push(("GESTURE_DIRECTION", SwipeDir::dir(dx, dy).to_string()));
+                env.push(("GESTURE_DX", dx.to_string()));
+                env.push(("GESTURE_DY", dy.to_string()));
+            }
+            GestureInput::PinchBegin { fingers } => {
+                self.event = Gesture::Pinch(Pinch::new(Fingers(fingers), PinchDir::Any));
+                self.pinch = PinchState::default();
+            }
+            GestureInput::PinchUpdate { scale, angle_delta } => {
+                let dir = PinchDir::dir(scale, angle_delta);
+                if let Gesture::Pinch(p) = &mut self.event {
+                    p.direction = dir.clone();
+                }
+                self.pinch.scale = Some(scale);
+                env.push(("GESTURE_DIRECTION", dir.to_string()));
+                env.push(("GESTURE_SCALE", scale.to_string()));
+                env.push(("GESTURE_ANGLE", angle_delta.to_string()));
+            }
+            GestureInput::PinchEnd { .. } => {
+                env.push(("GESTURE_DIRECTION", self.event.direction()));
+                let scale = self.pinch.scale.unwrap_or(1.0);
+                env.push(("GESTURE_SCALE", scale.to_string()));
+            }
+            GestureInput::HoldBegin { fingers } => {
+                self.event = Gesture::Hold(Hold::new(Fingers(fingers)));
+            }
+            GestureInput::HoldEnd { .. } | GestureInput::Hotkey { .. } => (),
+        }
+        let Some(fingers) = self.event.fingers() else {
+            return;
+        };
+        env.push(("GESTURE_TYPE", self.event.kind().to_string()));
+        env.push(("GESTURE_PHASE", phase.to_string()));
+        env.push(("GESTURE_FINGERS", fingers.to_string()));
+        if let Some(cancelled) = cancelled {
+            env.push(("GESTURE_CANCELLED", cancelled.to_string()));
+        }
+        exec_command_with_env(dispatcher, env, &config.runner());
+    }
+
+    pub fn handle_key(&mut self, key: u32, pressed: bool) {
+        self.pressed_keys.retain(|k| *k != key);
+        if pressed {
+            self.pressed_keys.push(key);
+        }
+    }
+
+    /// Act on the events of `gestures inject`, whose keyboards libinput doesn't see, regardless of
+    /// the activation key
+    pub fn set_injected(&mut self) {
+        self.injected = true;
+    }
+
+    /// Log a heartbeat `every` so often from the main loop, also while no gestures come
+    pub fn set_heartbeat(&mut self, every: Duration) {
+        self.heartbeat = Some((every, Instant::now()));
+    }
+
+    /// The heartbeat line if one is due, with the number of gesture events since the previous one
+    pub fn due_heartbeat(&mut self) -> Option<String> {
+        let (every, last) = self.heartbeat.as_mut()?;
+        if last.elapsed() < *every {
+            return None;
+        }
+        *last = Instant::now();
+        let events = std::mem::take(&mut self.beat_events);
+        let state = if self.paused.load(Ordering::Relaxed) {
+            "paused"
+        } else {
+            "polling"
+        };
+        Some(format!(
+            "Heartbeat: {state}, {events} gesture events in the last {every:?}"
+        ))
+    }
+
+    /// Log the heartbeat if one is due. It's asked for on the command line, so it goes straight
+    /// to the logger, past the verbosity and `log-level`.
+    fn beat(&mut self) {
+        if let Some(line) = self.due_heartbeat() {
+            log::logger().log(
+                &log::Record::builder()
+                    .level(log::Level::Info)
+                    .target(module_path!())
+                    .args(format_args!("{line}"))
+                    .build(),
+            );
+        }
+    }
+
+    /// The queue the commands of rule `idx` run on, if it has `serialize` and fired already
+    #[cfg(test)]
+    pub fn serial(&self, idx: usize) -> Option<&Serial> {
+        self.serials.get(&idx)
+    }
+
+    /// Grab the `hotkey`s of the rules on the X server, they fire their rules from then on
+    pub fn grab_hotkeys(&mut self) {
+        let Some(mut hotkeys) = Hotkeys::open() else {
+            log::warn!("Could not connect to the X server, hotkeys won't work");
+            return;
+        };
+        hotkeys.grab(&self.config.read().gestures);
+        self.hotkeys = Some(hotkeys);
+    }
+
+    /// Whether the device is in tablet mode, as reported by its tablet mode switch
+    pub fn set_tablet_mode(&mut self, on: bool) {
+        log::debug!("Tablet mode: {on}");
+        self.tablet_mode = on;
+    }
+
+    /// Time until the earliest waiting single swipe gives up on becoming a double, the delay of a
+    /// rule is up, or the next heartbeat is due
+    fn pending_timeout(&self) -> Option<Duration> {
+        let config = self.config.read();
+        let now = Instant::now();
+        let delayed = self
+            .delayed
+            .iter()
+            .map(|(_, _, due)| due.saturating_duration_since(now));
+        self.pending
+            .iter()
+            .filter_map(|(idx, at)| match config.gestures.get(*idx) {
+                Some(Gesture::Swipe(j)) => Some(j.double_within().saturating_sub(at.elapsed())),
+                _ => None,
+            })
+            .chain(delayed)
+            .chain(self.coalesce_due())
+            .chain(
+                self.heartbeat
+                    .map(|(every, last)| every.saturating_sub(last.elapsed())),
+            )
+            .min()
+    }
+
+    /// Run the actions of the swipes that were not repeated in time to make a double, and of the
+    /// rules whose delay is up. Acts on the update held back by `coalesce-updates` once it's due.
+    pub fn flush_pending(&mut self, xdoh: &mut XDoHandler) -> Result<()> {
+        self.follow_reload();
+        self.follow_pause(xdoh);
+        self.flush_coalesced(xdoh, false)?;
+        if self.pending.is_empty() && self.delayed.is_empty() {
+            return Ok(());
+        }
+        let config = self.config.clone();
+        let config = config.read();
+        let runner = &config.runner();
+        let screenshot_command = config.screenshot_command.as_deref();
+        let mut result = Ok(());
+        let mut tunes = Vec::new();
+        let mut delayed = std::mem::take(&mut self.delayed);
+        self.pending.retain(|(idx, at)| {
+            let Some(Gesture::Swipe(j)) = config.gestures.get(*idx) else {
+                // The config was reloaded in between
+                return false;
+            };
+            if at.elapsed() < j.double_within() {
+                return true;
+            }
+            log::debug!("Swipe of rule {idx} was not repeated, running its actions");
+            self.stats.write().record(*idx);
+            tunes.extend(j.set);
+            if let Some(ms) = j.confirm {
+                self.armed = Some((*idx, false, due_in(ms)));
+            } else if let Some(ms) = j.delay {
+                delayed.push((*idx, false, due_in(ms)));
+            } else if let Err(e) = Self::fire_swipe(
+                j,
+                false,
+                next_in_cycle(&mut self.cycles, *idx, &j.cycle),
+                serial(&mut self.serials, *idx, j.serialize).as_ref(),
+                screenshot_command,
+                runner,
+                xdoh,
+            ) {
+                result = Err(e);
+            }
+            false
+        });
+        let now = Instant::now();
+        delayed.retain(|(idx, double, due)| {
+            if *due > now {
+                return true;
+            }
+            log::debug!("Delay of rule {idx} is up, running its actions");
+            // The rule is gone when the config was reloaded in between
+            let fired = config.gestures.get(*idx).map_or(Ok(()), |gesture| {
+                let (cycles, serials) = (&mut self.cycles, &mut self.serials);
+                Self::fire(gesture, *idx, *double, cycles, serials, &config, xdoh)
+            });
+            if let Err(e) = fired {
+                result = Err(e);
+            }
+            false
+        });
+        self.delayed = delayed;
+        drop(config);
+        self.tune(tunes);
+        result
+    }
+
+    /// Wait for the swipes that may still become a double and the rules with a delay, then run
+    /// their actions
+    pub fn settle(&mut self, xdoh: &mut XDoHandler) -> Result<()> {
+        while let Some(timeout) = self.pending_timeout() {
+            thread::sleep(timeout);
+            self.flush_pending(xdoh)?;
+        }
+        Ok(())
+    }
+
+    /// Whether a rule that matches the gesture's kind, fingers and direction applies to it, given
+    /// the rule that had fired `last`
+    fn applies(&self, gesture: &Gesture, last: Option<(usize, Instant)>) -> bool {
+        gesture.when_power().is_none_or(|p| self.power() == Some(p))
+            && gesture
+                .when_tablet_mode()
+                .is_none_or(|t| t == self.tablet_mode)
+            && self.follows_previous(gesture, last)
+    }
+
+    /// Current power state, read again once the cached one is older than [`POWER_CACHE`]
+    fn power(&self) -> Option<Power> {
+        if let Some((power, read_at)) = self.power.get() {
+            if read_at.elapsed() < POWER_CACHE {
+                return power;
+            }
+        }
+        let power = Power::read(Path::new(POWER_SUPPLY_DIR));
+        log::debug!("Power state: {power:?}");
+        self.power.set(Some((power, Instant::now())));
+        power
+    }
+
+    /// Whether a rule with `requires-previous` comes soon enough after the rule it names, judged
+    /// by the rule that had fired `last` when the event came in
+    fn follows_previous(&self, gesture: &Gesture, last: Option<(usize, Instant)>) -> bool {
+        let Some((previous, within)) = gesture.requires_previous() else {
+            return true;
+        };
+        let Some((idx, at)) = last else {
+            return false;
+        };
+        // Callers iterate over the rules with the config locked already
+        let config = self.config.read_recursive();
+        at.elapsed() <= within && config.gestures.get(idx).and_then(Gesture::id) == Some(previous)
+    }
+
+    /// Whether either key of `modifier` is down
+    fn is_held(&self, modifier: Modifier) -> bool {
+        let keys = modifier.keys();
+        self.pressed_keys.iter().any(|k| keys.contains(k))
+    }
+
+    /// Run the cheatsheet command instead of the gesture if its modifier is held, returns whether
+    /// it ran
+    fn show_cheatsheet(&self, kind: &str, fingers: i32) -> Result<bool> {
+        let config = self.config.read();
+        let Some(cheatsheet) = &config.cheatsheet else {
+            return Ok(false);
+        };
+        if !self.is_held(cheatsheet.modifier) {
+            return Ok(false);
+        }
+        log::debug!("{:?} held, showing the cheatsheet", cheatsheet.modifier);
+        let command = cheatsheet
+            .command
+            .replace("{kind}", kind)
+            .replace("{fingers}", &fingers.to_string());
+        exec_command_from_string(&command, 0.0, 0.0, 0.0, 0.0, &config.runner(), None)?;
+        Ok(true)
+    }
+
+    /// The udev seat picks up hot-plugged devices by itself, their gestures arrive like any other
+    fn handle_device_event(&mut self, event: DeviceEvent, xdoh: &mut XDoHandler) {
+        let mut device = event.device();
+        if !device.has_capability(DeviceCapability::Gesture)
+            || self.config.read().ignores_device(device.name())
+        {
+            return;
+        }
+        match event {
+            DeviceEvent::Added(_) => {
+                log::info!("Gesture device added: {}", device.name());
+                if let Some(device_config) = &self.config.read().device_config {
+                    device_config.apply(&mut device);
+                }
+            }
+            DeviceEvent::Removed(_) => {
+                log::info!("Gesture device removed: {}", device.name());
+                // A gesture in progress on the device will never end
+                self.release_drag(xdoh);
+                self.event = Gesture::None;
+            }
+            _ => (),
+        }
+    }
+
+    /// Whether a drag's button or the keys of `repeat-key` rules are held down
+    fn holds_keys(&self) -> bool {
+        self.pressed.is_some() || !self.repeating.is_empty()
+    }
+
+    /// Let go of what the current gesture holds down once gestures are paused, without waiting
+    /// for its next event
+    fn follow_pause(&mut self, xdoh: &mut XDoHandler) {
+        if self.paused.load(Ordering::Relaxed) && self.holds_keys() {
+            log::debug!("Gestures were paused during a drag, letting go of it");
+            self.release_drag(xdoh);
+            self.event = Gesture::None;
+        }
+    }
+
+    /// Let go of the button of a drag in progress and of the keys of `repeat-key` rules, if any
+    fn release_drag(&mut self, xdoh: &mut XDoHandler) {
+        if let Some((button, modifiers)) = self.pressed.take() {
+            log::debug!("Releasing drag button {button}");
+            // Right away, a delayed release would be taken back by the next swipe pressing again
+            xdoh.mouse_up(button, modifiers.as_deref());
+        }
+        // Dropping the keys releases them
+        self.repeating.clear();
+    }
+
+    fn handle_hold_event(&mut self, event: GestureInput, xdoh: &mut XDoHandler) -> Result<()> {
+        let runner = self.config.read().runner();
+        let screenshot_command = self.config.read().screenshot_command.clone();
+        let tolerance = self.config.read().finger_tolerance.unwrap_or_default();
+        match event {
+            GestureInput::HoldBegin { fingers } => {
+                self.event = Gesture::Hold(Hold::new(Fingers(fingers)));
+                // libinput has no position for holds, use the pointer instead
+                self.hold_position = xdoh.pointer_location();
+            }
+            GestureInput::HoldEnd { .. } => {
                 if let Gesture::Hold(s) = &self.event {
                     log::debug!("Hold: {:?}", &s.fingers);
-                    for i in &self.config.clone().read().gestures {
+                    let mut matched = false;
+                    let last = self.stats.read().last_fired();
+                    let mut handled = false;
+                    let mut tunes = Vec::new();
+                    for (idx, i) in in_fallback_order(&self.config.clone().read().gestures) {
                         if let Gesture::Hold(j) = i {
-                            if j.fingers == s.fingers {
-                                exec_command_from_string(
-                                    &j.action.clone().unwrap_or_default(),
-                                    0.0,
-                                    0.0,
-                                    0.0,
-                                    0.0,
-                                )?;
+                            let in_region = match (j.region, self.hold_position) {
+                                (None, _) => true,
+                                (Some(region), Some((x, y))) => region.contains(x, y),
+                                (Some(_), None) => false,
+                            };
+                            if j.fingers.matches(s.fingers, tolerance)
+                                && in_region
+                                && !(j.fallback && matched)
+                                && self.applies(i, last)
+                            {
+                                matched |= !j.fallback;
+                                handled = true;
+                                self.stats.write().record(idx);
+                                tunes.extend(j.set);
+                                match (j.confirm, j.delay) {
+                                    (Some(ms), _) => self.armed = Some((idx, false, due_in(ms))),
+                                    (None, Some(ms)) => self.delayed.push((idx, false, due_in(ms))),
+                                    (None, None) => Self::fire_hold(
+                                        j,
+                                        next_in_cycle(&mut self.cycles, idx, &j.cycle),
+                                        serial(&mut self.serials, idx, j.serialize).as_ref(),
+                                        screenshot_command.as_deref(),
+                                        &runner,
+                                        xdoh,
+                                    )?,
+                                }
                             }
                         }
                     }
+                    self.tune(tunes);
+                    if !handled {
+                        let action = self.config.read().default_hold_action.clone();
+                        Self::run_default_action(action.as_deref(), &runner)?;
+                    }
                 }
             }
             _ => (),
@@ -163,39 +1165,60 @@ impl EventHandler {
         Ok(())
     }
 
-    fn handle_pinch_event(&mut self, event: GesturePinchEvent) -> Result<()> {
+    fn handle_pinch_event(&mut self, event: GestureInput, xdoh: &mut XDoHandler) -> Result<()> {
+        let runner = self.config.read().runner();
+        let tolerance = self.config.read().finger_tolerance.unwrap_or_default();
         match event {
-            GesturePinchEvent::Begin(e) => {
-                self.event = Gesture::Pinch(Pinch {
-                    fingers: e.finger_count(),
-                    direction: PinchDir::Any,
-                    update: None,
-                    start: None,
-                    end: None,
-                });
+            GestureInput::PinchBegin { fingers } => {
+                self.event = Gesture::Pinch(Pinch::new(Fingers(fingers), PinchDir::Any));
+                self.repeats.clear();
+                self.started.clear();
+                self.began = Instant::now();
+                self.pinch = PinchState::default();
                 if let Gesture::Pinch(s) = &self.event {
-                    for i in &self.config.clone().read().gestures {
+                    let mut matched = false;
+                    let last = self.stats.read().last_fired();
+                    for (idx, i) in in_fallback_order(&self.config.clone().read().gestures) {
                         if let Gesture::Pinch(j) = i {
                             if (j.direction == s.direction || j.direction == PinchDir::Any)
-                                && j.fingers == s.fingers
+                                && j.fingers.matches(s.fingers, tolerance)
+                                && !(j.fallback && matched)
+                                && self.applies(i, last)
                             {
-                                exec_command_from_string(
+                                matched |= !j.fallback;
+                                self.started.push(idx);
+                                exec_command_queued(
                                     &j.start.clone().unwrap_or_default(),
-                                    0.0,
-                                    0.0,
-                                    0.0,
-                                    0.0,
+                                    (0.0, 0.0, 0.0, 0.0),
+                                    &runner,
+                                    xdoh.held_keys(j.hold_modifiers.as_deref()),
+                                    serial(&mut self.serials, idx, j.serialize).as_ref(),
                                 )?;
                             }
                         }
                     }
                 }
             }
-            GesturePinchEvent::Update(e) => {
-                let scale = e.scale();
-                let delta_angle = e.angle_delta();
+            GestureInput::PinchUpdate {
+                scale,
+                angle_delta: delta_angle,
+            } => {
+                self.pinch.angle += delta_angle;
+                let dir = if self.config.read().pinch_lock {
+                    if self.pinch.kind.is_none() {
+                        self.pinch.kind = PinchKind::decide(scale, self.pinch.angle);
+                    }
+                    match self.pinch.kind {
+                        Some(kind) => PinchDir::dir_locked(scale, delta_angle, kind),
+                        None => PinchDir::Any,
+                    }
+                } else {
+                    PinchDir::dir(scale, delta_angle)
+                };
+                let scale_delta = scale - self.pinch.scale.replace(scale).unwrap_or(1.0);
+                let moved = scale_delta.abs() >= IDLE_SCALE || delta_angle.abs() >= IDLE_ANGLE;
+                let idle = self.pinch.idle.track(moved, self.began);
                 if let Gesture::Pinch(s) = &self.event {
-                    let dir = PinchDir::dir(scale, delta_angle);
                     log::debug!(
                         "Pinch: scale={:?} angle={:?} direction={:?} fingers={:?}",
                         &scale,
@@ -203,47 +1226,121 @@ impl EventHandler {
                         &dir,
                         &s.fingers
                     );
-                    for i in &self.config.clone().read().gestures {
+                    let mut matched = false;
+                    let last = self.stats.read().last_fired();
+                    for (idx, i) in in_fallback_order(&self.config.clone().read().gestures) {
                         if let Gesture::Pinch(j) = i {
-                            if (j.direction == dir || j.direction == PinchDir::Any)
-                                && j.fingers == s.fingers
+                            if j.matches_update(&dir, scale)
+                                && j.fingers.matches(s.fingers, tolerance)
+                                && !(j.fallback && matched)
+                                && self.applies(i, last)
                             {
-                                exec_command_from_string(
-                                    &j.update.clone().unwrap_or_default(),
-                                    0.0,
-                                    0.0,
-                                    delta_angle,
-                                    scale,
-                                )?;
+                                matched |= !j.fallback;
+                                if !self.started.contains(&idx) {
+                                    self.started.push(idx);
+                                    exec_command_queued(
+                                        &j.start.clone().unwrap_or_default(),
+                                        (0.0, 0.0, 0.0, 0.0),
+                                        &runner,
+                                        xdoh.held_keys(j.hold_modifiers.as_deref()),
+                                        serial(&mut self.serials, idx, j.serialize).as_ref(),
+                                    )?;
+                                }
+                                if j.idled(idle) {
+                                    log::debug!("Fingers rested for {idle:?}, skipping update");
+                                    continue;
+                                }
+                                let count = self.repeats.entry(idx).or_default();
+                                if j.max_repeats.is_some_and(|max| *count >= max) {
+                                    continue;
+                                }
+                                *count += 1;
+                                let cumulative = scale;
+                                let scale = match j.scale_mode.unwrap_or_default() {
+                                    ScaleMode::Cumulative => scale,
+                                    ScaleMode::Delta => scale_delta,
+                                };
+                                let update = j.update.as_deref().unwrap_or_default();
+                                let update = substitute_value(update, j.value(cumulative));
+                                let update = substitute_progress(&update, j.progress(cumulative));
+                                let angle = delta_angle * j.angle_scale.unwrap_or(1.0);
+                                let held_keys = xdoh.held_keys(j.hold_modifiers.as_deref());
+                                if j.skip_busy {
+                                    let busy = self.busy.entry(idx).or_default();
+                                    exec_command_unless_busy(
+                                        &update,
+                                        angle,
+                                        scale,
+                                        &runner,
+                                        held_keys,
+                                        busy,
+                                        serial(&mut self.serials, idx, j.serialize).as_ref(),
+                                    )?;
+                                } else {
+                                    exec_command_queued(
+                                        &update,
+                                        (0.0, 0.0, angle, scale),
+                                        &runner,
+                                        held_keys,
+                                        serial(&mut self.serials, idx, j.serialize).as_ref(),
+                                    )?;
+                                }
                             }
                         }
                     }
-                    self.event = Gesture::Pinch(Pinch {
-                        fingers: s.fingers,
-                        direction: dir,
-                        update: None,
-                        start: None,
-                        end: None,
-                    })
+                    self.event = Gesture::Pinch(Pinch::new(s.fingers, dir))
                 }
             }
-            GesturePinchEvent::End(_e) => {
+            GestureInput::PinchEnd { .. } => {
                 if let Gesture::Pinch(s) = &self.event {
-                    for i in &self.config.clone().read().gestures {
+                    let mut matched = false;
+                    let last = self.stats.read().last_fired();
+                    let mut handled = false;
+                    let started = std::mem::take(&mut self.started);
+                    let elapsed = self.began.elapsed();
+                    let scale = self.pinch.scale.unwrap_or(1.0);
+                    log::debug!(
+                        "Pinch ended after {elapsed:?}: scale {scale:.2}, rotated {:.1} degrees, \
+                         {:.2} scale and {:.1} degrees per second on average",
+                        self.pinch.angle,
+                        (scale - 1.0).abs() / elapsed.as_secs_f64(),
+                        self.pinch.angle.abs() / elapsed.as_secs_f64(),
+                    );
+                    let mut tunes = Vec::new();
+                    for (idx, i) in in_fallback_order(&self.config.clone().read().gestures) {
                         if let Gesture::Pinch(j) = i {
-                            if (j.direction == s.direction || j.direction == PinchDir::Any)
-                                && j.fingers == s.fingers
-                            {
-                                exec_command_from_string(
-                                    &j.end.clone().unwrap_or_default(),
-                                    0.0,
-                                    0.0,
-                                    0.0,
-                                    0.0,
-                                )?;
+                            let fire = if i.is_continuous() {
+                                started.contains(&idx)
+                            } else {
+                                (j.direction == s.direction || j.direction == PinchDir::Any)
+                                    && j.fingers.matches(s.fingers, tolerance)
+                                    && within(elapsed, j.max_duration)
+                                    && self.applies(i, last)
+                            };
+                            if fire && !(j.fallback && matched) {
+                                matched |= !j.fallback;
+                                handled = true;
+                                self.stats.write().record(idx);
+                                tunes.extend(j.set);
+                                match (j.confirm, j.delay) {
+                                    (Some(ms), _) => self.armed = Some((idx, false, due_in(ms))),
+                                    (None, Some(ms)) => self.delayed.push((idx, false, due_in(ms))),
+                                    (None, None) => Self::fire_pinch(
+                                        j,
+                                        next_in_cycle(&mut self.cycles, idx, &j.cycle),
+                                        serial(&mut self.serials, idx, j.serialize).as_ref(),
+                                        &runner,
+                                        xdoh,
+                                    )?,
+                                }
                             }
                         }
                     }
+                    self.tune(tunes);
+                    if !handled {
+                        let action = self.config.read().default_pinch_action.clone();
+                        Self::run_default_action(action.as_deref(), &runner)?;
+                    }
                 }
             }
             _ => (),
@@ -251,35 +1348,16 @@ impl EventHandler {
         Ok(())
     }
 
-    fn handle_swipe_event(
-        &mut self,
-        event: GestureSwipeEvent,
-        xdoh: &mut XDoHandler,
-    ) -> Result<()> {
-        match event {
-            GestureSwipeEvent::Begin(e) => self.handle_swipe_begin(e.finger_count(), xdoh),
-            GestureSwipeEvent::Update(e) => self.handle_swipe_update(e.dx(), e.dy(), xdoh),
-            GestureSwipeEvent::End(e) => {
-                if !e.cancelled() {
-                    self.handle_swipe_end(xdoh)
-                } else {
-                    Ok(())
-                }
-            }
-            _ => Ok(()),
-        }
-    }
-
     fn update_cache(&mut self) {
         let config = self.config.read(); // No need for unwrap()
-        let mut swipe_map: HashMap<i32, Vec<Gesture>> = HashMap::new();
+        let mut swipe_map: HashMap<Fingers, Vec<(usize, Gesture)>> = HashMap::new();
 
-        for gesture in &config.gestures {
+        for (idx, gesture) in config.gestures.iter().enumerate() {
             if let Gesture::Swipe(swipe) = gesture {
                 swipe_map
                     .entry(swipe.fingers)
                     .or_default()
-                    .push(gesture.clone());
+                    .push((idx, gesture.clone()));
             }
         }
 
@@ -289,133 +1367,856 @@ impl EventHandler {
 
     fn handle_matching_gesture<F>(
         &mut self,
-        fingers: i32,
+        fingers: Fingers,
         xdoh: &mut XDoHandler,
-        handler: F,
-    ) -> Result<()>
+        mut handler: F,
+    ) -> Result<bool>
     where
-        F: Fn(&Gesture, &mut XDoHandler) -> Result<()>,
+        F: FnMut(usize, &Gesture, &mut XDoHandler) -> Result<bool>,
     {
         // Update cache if needed
         if self.cache.last_update.elapsed() > std::time::Duration::from_secs(1) {
             self.update_cache();
         }
 
+        let tolerance = self.config.read().finger_tolerance.unwrap_or_default() as i32;
+        let mut handled = false;
         if let Gesture::Swipe(_) = &self.event {
-            if let Some(gestures) = self.cache.swipe_gestures.get(&fingers) {
-                for gesture in gestures {
-                    handler(gesture, xdoh)?;
+            // Rules for any finger count are kept under their own key, merge them back in order
+            let mut gestures: Vec<&(usize, Gesture)> = (fingers.0 - tolerance
+                ..=fingers.0 + tolerance)
+                .map(Fingers)
+                .chain([Fingers::ANY])
+                .filter_map(|f| self.cache.swipe_gestures.get(&f))
+                .flatten()
+                .collect();
+            gestures.sort_by_key(|(idx, g)| (g.fallback(), *idx));
+            gestures.dedup_by_key(|(idx, _)| *idx);
+            // Fallback rules only run when no other rule handled the event
+            let mut matched = false;
+            let last = self.stats.read().last_fired();
+            for (idx, gesture) in gestures {
+                if gesture.fallback() && matched {
+                    break;
                 }
+                if !self.applies(gesture, last) {
+                    continue;
+                }
+                let fired = handler(*idx, gesture, xdoh)?;
+                handled |= fired;
+                matched |= fired && !gesture.fallback();
             }
         }
+        Ok(handled)
+    }
+
+    /// Make the config changes of the rules that fired, once the rules are no longer borrowed
+    fn tune(&mut self, tunes: Vec<Tune>) {
+        if tunes.is_empty() {
+            return;
+        }
+        let mut config = self.config.write();
+        for tune in tunes {
+            tune.apply(&mut config);
+        }
+        drop(config);
+        // The cached swipe rules still have the old acceleration
+        self.update_cache();
+    }
+
+    /// Run the `default-*-action` for a gesture that no rule handled
+    fn run_default_action(action: Option<&str>, runner: &Runner) -> Result<()> {
+        if let Some(action) = action {
+            log::debug!("No rule handled the gesture, running the default action");
+            exec_command_from_string(action, 0.0, 0.0, 0.0, 0.0, runner, None)?;
+        }
         Ok(())
     }
 
     fn is_xorg_gesture(gesture: &Gesture, xdoh: &XDoHandler) -> bool {
         if let Gesture::Swipe(j) = gesture {
-            xdoh.is_xorg
-                && j.acceleration.is_some()
-                && j.mouse_up_delay.is_some()
-                && j.direction == SwipeDir::Any
+            xdoh.is_xorg && j.is_drag()
         } else {
             false
         }
     }
 
-    fn handle_swipe_begin(&mut self, fingers: i32, xdoh: &mut XDoHandler) -> Result<()> {
+    fn handle_swipe_begin(&mut self, fingers: Fingers, xdoh: &mut XDoHandler) -> Result<()> {
+        let runner = self.config.read().runner();
         self.event = Gesture::Swipe(Swipe::new(fingers));
+        self.swipe = SwipeState::default();
+        self.repeats.clear();
+        self.began = Instant::now();
 
-        self.handle_matching_gesture(fingers, xdoh, |gesture, xdoh| {
-            if Self::is_xorg_gesture(gesture, xdoh) {
-                log::debug!("Call libxdo api directly in Xorg env for better performance.");
-                xdoh.mouse_down(1);
-            } else if let Gesture::Swipe(j) = gesture {
-                if j.direction == SwipeDir::Any {
-                    exec_command_from_string(j.start.as_deref().unwrap_or(""), 0.0, 0.0, 0.0, 0.0)?;
+        let mut held = None;
+        let mut started = Vec::new();
+        let mut serials = std::mem::take(&mut self.serials);
+        let result = self.handle_matching_gesture(fingers, xdoh, |idx, gesture, xdoh| {
+            if let Gesture::Swipe(j) = gesture {
+                if Self::is_xorg_gesture(gesture, xdoh) {
+                    // With a threshold or delay the press is deferred until the swipe has moved
+                    // far enough or lasted long enough
+                    if j.drag_threshold.is_none()
+                        && j.drag_delay.is_none()
+                        && j.grab_speed.is_none()
+                    {
+                        log::debug!("Call libxdo api directly in Xorg env for better performance.");
+                        let button = j.button.unwrap_or(1);
+                        xdoh.mouse_down(button, j.drag_modifiers.as_deref());
+                        held = Some((button, j.drag_modifiers.clone()));
+                    }
+                    return Ok(true);
+                } else if !j.is_directional() && j.min_coherence.is_none() {
+                    started.push(idx);
+                    exec_command_queued(
+                        j.start.as_deref().unwrap_or(""),
+                        (0.0, 0.0, 0.0, 0.0),
+                        &runner,
+                        xdoh.held_keys(j.hold_modifiers.as_deref()),
+                        serial(&mut serials, idx, j.serialize).as_ref(),
+                    )?;
+                    return Ok(true);
                 }
             }
-            Ok(())
-        })
+            Ok(false)
+        });
+        self.pressed = held;
+        self.started = started;
+        self.serials = serials;
+        result.map(|_| ())
     }
 
     fn handle_swipe_update(&mut self, dx: f64, dy: f64, xdoh: &mut XDoHandler) -> Result<()> {
-        let swipe_dir = SwipeDir::dir(dx, dy);
-        let (fingers, current_dir) = if let Gesture::Swipe(s) = &self.event {
-            (s.fingers, swipe_dir.clone())
+        let runner = self.config.read().runner();
+        let hysteresis = self.config.read().swipe_hysteresis;
+        let (fingers, swipe_dir) = if let Gesture::Swipe(s) = &self.event {
+            let dir = match hysteresis {
+                Some(margin) => SwipeDir::dir_with_hysteresis(dx, dy, &s.direction, margin),
+                None => SwipeDir::dir(dx, dy),
+            };
+            (s.fingers, dir)
         } else {
             return Ok(());
         };
+        let current_dir = swipe_dir.clone();
 
         log::debug!("{:?} {:?}", &current_dir, &fingers);
 
-        let current_dir = current_dir.clone();
-        self.handle_matching_gesture(fingers, xdoh, move |gesture, xdoh| {
+        self.swipe.dx += dx;
+        self.swipe.dy += dy;
+        self.swipe.track_velocity(dx.hypot(dy));
+        self.swipe.track_direction(&current_dir);
+        let (total_dx, total_dy) = (self.swipe.dx, self.swipe.dy);
+        let distance = total_dx.hypot(total_dy);
+        if distance > self.swipe.farthest.0.hypot(self.swipe.farthest.1) {
+            self.swipe.farthest = (total_dx, total_dy);
+        }
+        let elapsed = self.began.elapsed();
+        let moved = dx.hypot(dy) >= IDLE_DISTANCE;
+        let idle = self.swipe.idle.track(moved, self.began);
+        let mut held = self.pressed.take();
+        let mut repeating = std::mem::take(&mut self.repeating);
+        let mut overshoot_fired = std::mem::take(&mut self.swipe.overshoot_fired);
+        let mut repeats = std::mem::take(&mut self.repeats);
+        let mut started = std::mem::take(&mut self.started);
+        let mut settled = std::mem::take(&mut self.swipe.settled);
+        let mut serials = std::mem::take(&mut self.serials);
+        let mut drag_step = self.swipe.drag_step;
+        self.swipe.updates += 1;
+        let updates = self.swipe.updates;
+        let mut flicked = self.swipe.flicked;
+        let mut past_threshold = self.swipe.past_threshold;
+        let dir_since = self
+            .swipe
+            .direction
+            .as_ref()
+            .map_or(self.began, |(_, since)| *since);
+
+        let result = self.handle_matching_gesture(fingers, xdoh, |idx, gesture, xdoh| {
             if let Gesture::Swipe(j) = gesture {
+                if let (Some(overshoot), Some(max)) = (&j.overshoot, j.overshoot_distance) {
+                    if distance >= max
+                        && j.matches_direction(&current_dir, total_dx, total_dy)
+                        && !overshoot_fired.contains(&idx)
+                    {
+                        overshoot_fired.push(idx);
+                        exec_command_queued(
+                            overshoot,
+                            (total_dx, total_dy, 0.0, 0.0),
+                            &runner,
+                            xdoh.held_keys(j.hold_modifiers.as_deref()),
+                            serial(&mut serials, idx, j.serialize).as_ref(),
+                        )?;
+                    }
+                }
                 if Self::is_xorg_gesture(gesture, xdoh) {
-                    let acceleration = j.acceleration.unwrap_or_default() as f64 / 10.0;
-                    xdoh.move_mouse_relative(
-                        (dx * acceleration) as i32,
-                        (dy * acceleration) as i32,
-                    );
-                } else if j.direction == current_dir || j.direction == SwipeDir::Any {
-                    exec_command_from_string(j.update.as_deref().unwrap_or(""), dx, dy, 0.0, 0.0)?;
+                    if held.is_none() && j.grab_speed.is_some() {
+                        if flicked {
+                            return Ok(false);
+                        }
+                        if updates < GRAB_UPDATES {
+                            // Too early to tell a grab from a flick
+                            return Ok(true);
+                        }
+                        if !j.grabs(distance, elapsed) {
+                            log::debug!("Swipe moved {distance:.1} in {elapsed:?}, a flick");
+                            flicked = true;
+                            return Ok(false);
+                        }
+                    }
+                    if held.is_none()
+                        && distance >= j.drag_threshold.unwrap_or_default()
+                        && elapsed >= Duration::from_millis(j.drag_delay.unwrap_or_default())
+                    {
+                        log::debug!("Swipe moved {distance:.1} in {elapsed:?}, starting drag");
+                        let button = j.button.unwrap_or(1);
+                        xdoh.mouse_down(button, j.drag_modifiers.as_deref());
+                        held = Some((button, j.drag_modifiers.clone()));
+                        past_threshold |= j.drag_threshold.is_some();
+                    }
+                    if held.is_some() {
+                        let acceleration = j.acceleration.unwrap_or_default() as f64 / 10.0;
+                        let (dx, dy) = j.smooth((dx, dy), drag_step);
+                        drag_step = Some((dx, dy));
+                        xdoh.move_mouse_relative(
+                            (dx * acceleration) as i32,
+                            (dy * acceleration) as i32,
+                        );
+                    }
+                    return Ok(true);
+                }
+                let dir = match j.direction_debounce {
+                    Some(ms) => {
+                        let settled = settled.entry(idx).or_insert_with(|| current_dir.clone());
+                        let lasted = dir_since.elapsed() >= Duration::from_millis(ms);
+                        if *settled != current_dir && lasted {
+                            *settled = current_dir.clone();
+                        }
+                        settled.clone()
+                    }
+                    None => current_dir.clone(),
+                };
+                let matches = j.matches_direction(&dir, dx, dy) && j.is_coherent(fingers, distance);
+                let repeats_key = repeating.iter().any(|(i, _)| *i == idx);
+                if let Some(key) = &j.repeat_key {
+                    if matches && !repeats_key {
+                        log::debug!("Holding {key} down while the swipe goes {dir:?}");
+                        // Outside of Xorg there is nothing to hold, don't try again on every update
+                        let mut keys = xdoh.held_keys(Some(key));
+                        if let Some(keys) = &mut keys {
+                            keys.press();
+                        }
+                        repeating.push((idx, keys));
+                    } else if !matches && repeats_key {
+                        repeating.retain(|(i, _)| *i != idx);
+                    }
+                }
+                if matches {
+                    if !started.contains(&idx) {
+                        started.push(idx);
+                        exec_command_queued(
+                            j.start.as_deref().unwrap_or(""),
+                            (0.0, 0.0, 0.0, 0.0),
+                            &runner,
+                            xdoh.held_keys(j.hold_modifiers.as_deref()),
+                            serial(&mut serials, idx, j.serialize).as_ref(),
+                        )?;
+                    }
+                    if j.idled(idle) {
+                        log::debug!("Fingers rested for {idle:?}, skipping update");
+                        return Ok(true);
+                    }
+                    let count = repeats.entry(idx).or_default();
+                    if j.max_repeats.is_some_and(|max| *count >= max) {
+                        return Ok(true);
+                    }
+                    *count += 1;
+                    let update = j.update.as_deref().unwrap_or("");
+                    exec_command_queued(
+                        &substitute_progress(update, j.progress(distance)),
+                        (dx, dy, 0.0, 0.0),
+                        &runner,
+                        xdoh.held_keys(j.hold_modifiers.as_deref()),
+                        serial(&mut serials, idx, j.serialize).as_ref(),
+                    )?;
+                    return Ok(true);
                 }
             }
-            Ok(())
-        })?;
+            Ok(false)
+        });
 
+        // Even when a command failed, so the button is still let go of at the end
+        self.pressed = held;
+        self.repeating = repeating;
+        self.swipe.overshoot_fired = overshoot_fired;
+        self.swipe.settled = settled;
+        self.swipe.drag_step = drag_step;
+        self.swipe.flicked = flicked;
+        self.swipe.past_threshold = past_threshold;
+        self.repeats = repeats;
+        self.started = started;
+        self.serials = serials;
         self.event = Gesture::Swipe(Swipe::with_direction(fingers, swipe_dir));
-        Ok(())
+        result.map(|_| ())
+    }
+
+    /// Send the click of a rule with `modifiers` held down
+    fn click(
+        click: Click,
+        button: Option<i32>,
+        interval_ms: Option<u64>,
+        modifiers: Option<&str>,
+        xdoh: &mut XDoHandler,
+    ) {
+        // The keys are queued around the click, so they are released after it
+        let _held_keys = xdoh.held_keys(modifiers).map(|mut keys| {
+            keys.press();
+            keys
+        });
+        xdoh.click(
+            button.unwrap_or(1),
+            click.count(),
+            interval_ms.unwrap_or(DEFAULT_CLICK_INTERVAL_MS),
+        );
     }
 
-    fn handle_swipe_end(&mut self, xdoh: &mut XDoHandler) -> Result<()> {
+    /// Rules with an update action end whenever they started, the others when the swipe matches
+    /// them. A cancelled swipe ends none of them.
+    fn handle_swipe_end(
+        &mut self,
+        end_fingers: Option<Fingers>,
+        cancelled: bool,
+        xdoh: &mut XDoHandler,
+    ) -> Result<()> {
+        let runner = self.config.read().runner();
+        let screenshot_command = self.config.read().screenshot_command.clone();
         let (fingers, direction) = if let Gesture::Swipe(s) = &self.event {
             (s.fingers, s.direction.clone())
         } else {
             return Ok(());
         };
-        self.handle_matching_gesture(fingers, xdoh, |gesture, xdoh| {
+        let end_fingers = end_fingers.unwrap_or(fingers);
+        let stats = self.stats.clone();
+        let armed = self.pressed.is_some();
+        let past_threshold = self.swipe.past_threshold;
+        let mut released = false;
+        let (total_dx, total_dy) = (self.swipe.dx, self.swipe.dy);
+        let (far_dx, far_dy) = self.swipe.farthest;
+        let velocity = self.swipe.end_velocity();
+        let outward = SwipeDir::dir(far_dx, far_dy);
+        let started = std::mem::take(&mut self.started);
+        let elapsed = self.began.elapsed();
+        let distance = total_dx.hypot(total_dy);
+        log::debug!(
+            "Swipe ended after {elapsed:?}: moved ({total_dx:.1}, {total_dy:.1}), {distance:.1} in \
+             total, {:.1} per second on average and {velocity:.1} at the end",
+            distance / elapsed.as_secs_f64(),
+        );
+        // Singles whose time is up can't become a double anymore
+        self.flush_pending(xdoh)?;
+        let mut pending = std::mem::take(&mut self.pending);
+        let mut delayed = std::mem::take(&mut self.delayed);
+        let mut cycles = std::mem::take(&mut self.cycles);
+        let mut serials = std::mem::take(&mut self.serials);
+        let mut to_confirm = None;
+        let mut tunes = Vec::new();
+        let handled = self.handle_matching_gesture(fingers, xdoh, |idx, gesture, xdoh| {
             if let Gesture::Swipe(j) = gesture {
                 if Self::is_xorg_gesture(gesture, xdoh) {
-                    xdoh.mouse_up_delay(1, j.mouse_up_delay.unwrap_or_default());
-                } else if j.direction == direction || j.direction == SwipeDir::Any {
-                    exec_command_from_string(
-                        j.end.as_deref().unwrap_or(""),
-                        0.0,
-                        0.0,
-                        0.0,
-                        0.0,
-                    )?;
+                    if armed {
+                        stats.write().record(idx);
+                        if let Some(sound) = &j.sound {
+                            play_sound(sound, &runner);
+                        }
+                        xdoh.mouse_up_delay(
+                            j.button.unwrap_or(1),
+                            j.release_delay(velocity),
+                            j.drag_modifiers.as_deref(),
+                        );
+                        released = true;
+                    }
+                    return Ok(armed);
+                }
+                if !gesture.is_continuous()
+                    && j.returned(far_dx.hypot(far_dy), total_dx.hypot(total_dy))
+                {
+                    // Judged by the way out, the way back would not match the rule
+                    let abort = !cancelled
+                        && (!j.is_directional() || !past_threshold)
+                        && j.matches_direction(&outward, far_dx, far_dy);
+                    if abort {
+                        log::debug!("Swipe returned to where it began, cancelling rule {idx}");
+                        exec_command_queued(
+                            j.cancel.as_deref().unwrap_or(""),
+                            (total_dx, total_dy, 0.0, 0.0),
+                            &runner,
+                            None,
+                            serial(&mut serials, idx, j.serialize).as_ref(),
+                        )?;
+                    }
+                    return Ok(abort);
+                }
+                let fire = !cancelled
+                    && if gesture.is_continuous() {
+                        started.contains(&idx)
+                    } else {
+                        (!j.is_directional() || !past_threshold)
+                            && j.matches_direction(&direction, total_dx, total_dy)
+                            && within(elapsed, j.max_duration)
+                            && j.is_coherent(fingers, total_dx.hypot(total_dy))
+                            && j.end_fingers.is_none_or(|f| f.matches(end_fingers, 0))
+                    };
+                let double = fire && j.double.is_some() && !gesture.is_continuous();
+                if double {
+                    let Some(i) = pending.iter().position(|(p, _)| *p == idx) else {
+                        pending.push((idx, Instant::now()));
+                        return Ok(true);
+                    };
+                    log::debug!("Swipe of rule {idx} repeated, running its double");
+                    pending.remove(i);
+                }
+                if fire {
+                    stats.write().record(idx);
+                    tunes.extend(j.set);
+                    match (j.confirm, j.delay) {
+                        (Some(ms), _) => to_confirm = Some((idx, double, due_in(ms))),
+                        (None, Some(ms)) => delayed.push((idx, double, due_in(ms))),
+                        (None, None) => {
+                            let cycle = next_in_cycle(&mut cycles, idx, &j.cycle);
+                            let serial = serial(&mut serials, idx, j.serialize);
+                            let screenshot_command = screenshot_command.as_deref();
+                            Self::fire_swipe(
+                                j,
+                                double,
+                                cycle,
+                                serial.as_ref(),
+                                screenshot_command,
+                                &runner,
+                                xdoh,
+                            )?
+                        }
+                    }
+                    return Ok(true);
                 }
             }
-            Ok(())
-        })
+            Ok(false)
+        });
+        self.pending = pending;
+        self.delayed = delayed;
+        self.cycles = cycles;
+        self.serials = serials;
+        if to_confirm.is_some() {
+            self.armed = to_confirm;
+        }
+        self.tune(tunes);
+        if released {
+            self.pressed = None;
+        }
+        let handled = handled?;
+        if !handled && !cancelled {
+            let action = self.config.read().default_swipe_action.clone();
+            Self::run_default_action(action.as_deref(), &runner)?;
+        }
+        Ok(())
+    }
+
+    /// Hand the presses of the grabbed hotkeys to [`EventHandler::dispatch`]
+    fn fire_hotkeys(&mut self, xdoh: &mut XDoHandler) -> Result<()> {
+        let Some(hotkeys) = &mut self.hotkeys else {
+            return Ok(());
+        };
+        let pressed = hotkeys.pressed();
+        if pressed.is_empty() || self.paused.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let mut result = Ok(());
+        for rule in pressed {
+            if let Err(e) = self.dispatch(GestureInput::Hotkey { rule }, xdoh) {
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    /// Run the rule whose hotkey was pressed as if its gesture had matched, if its conditions hold
+    fn handle_hotkey(&mut self, idx: usize, xdoh: &mut XDoHandler) -> Result<()> {
+        let config = self.config.clone();
+        let config = config.read();
+        let Some(gesture) = config.gestures.get(idx) else {
+            return Ok(());
+        };
+        if !self.applies(gesture, self.stats.read().last_fired()) {
+            log::debug!("Hotkey of rule {idx} was pressed, but the rule doesn't apply now");
+            return Ok(());
+        }
+        log::debug!("Hotkey of rule {idx} was pressed");
+        self.stats.write().record(idx);
+        let (set, confirm, delay) = match gesture {
+            Gesture::Swipe(j) => (j.set, j.confirm, j.delay),
+            Gesture::Pinch(j) => (j.set, j.confirm, j.delay),
+            Gesture::Hold(j) => (j.set, j.confirm, j.delay),
+            Gesture::Group(_) | Gesture::None => (None, None, None),
+        };
+        let fired = match (confirm, delay) {
+            (Some(ms), _) => {
+                self.armed = Some((idx, false, due_in(ms)));
+                Ok(())
+            }
+            (None, Some(ms)) => {
+                self.delayed.push((idx, false, due_in(ms)));
+                Ok(())
+            }
+            (None, None) => {
+                let (cycles, serials) = (&mut self.cycles, &mut self.serials);
+                Self::fire(gesture, idx, false, cycles, serials, &config, xdoh)
+            }
+        };
+        drop(config);
+        self.tune(set.into_iter().collect());
+        fired
+    }
+
+    /// Run the actions of rule `idx`, whatever kind of gesture it is for. `double` runs the
+    /// `double` of a swipe rule instead of its `end`.
+    fn fire(
+        gesture: &Gesture,
+        idx: usize,
+        double: bool,
+        cycles: &mut HashMap<usize, usize>,
+        serials: &mut HashMap<usize, Serial>,
+        config: &Config,
+        xdoh: &mut XDoHandler,
+    ) -> Result<()> {
+        let runner = &config.runner();
+        let screenshot_command = config.screenshot_command.as_deref();
+        match gesture {
+            Gesture::Swipe(j) => {
+                let cycle = next_in_cycle(cycles, idx, &j.cycle);
+                let serial = serial(serials, idx, j.serialize);
+                let serial = serial.as_ref();
+                Self::fire_swipe(j, double, cycle, serial, screenshot_command, runner, xdoh)
+            }
+            Gesture::Pinch(j) => {
+                let cycle = next_in_cycle(cycles, idx, &j.cycle);
+                let serial = serial(serials, idx, j.serialize);
+                Self::fire_pinch(j, cycle, serial.as_ref(), runner, xdoh)
+            }
+            Gesture::Hold(j) => {
+                let cycle = next_in_cycle(cycles, idx, &j.cycle);
+                let serial = serial(serials, idx, j.serialize);
+                Self::fire_hold(j, cycle, serial.as_ref(), screenshot_command, runner, xdoh)
+            }
+            Gesture::Group(_) | Gesture::None => Ok(()),
+        }
+    }
+
+    /// Run the actions of a swipe rule that fired as its swipe ended, with its `double` instead of
+    /// its `end` when the swipe was repeated
+    fn fire_swipe(
+        j: &Swipe,
+        double: bool,
+        cycle: Option<&str>,
+        serial: Option<&Serial>,
+        screenshot_command: Option<&str>,
+        runner: &Runner,
+        xdoh: &mut XDoHandler,
+    ) -> Result<()> {
+        if let Some(sound) = &j.sound {
+            play_sound(sound, runner);
+        }
+        if let Some(action) = j.window {
+            xdoh.window_action(action);
+        }
+        if let Some(key) = j.media {
+            xdoh.media_key(key);
+        }
+        if let Some(text) = &j.text {
+            xdoh.type_text(text);
+        }
+        if let Some(area) = j.screenshot {
+            area.take(screenshot_command, runner, xdoh);
+        }
+        if let Some(call) = &j.dbus {
+            call_dbus(call, runner);
+        }
+        if j.center_cursor {
+            xdoh.center_cursor();
+        }
+        if let Some(click) = j.click {
+            Self::click(
+                click,
+                j.click_button,
+                j.click_interval,
+                j.hold_modifiers.as_deref(),
+                xdoh,
+            );
+        }
+        if let Some(command) = cycle {
+            let held_keys = xdoh.held_keys(j.hold_modifiers.as_deref());
+            exec_command_queued(command, (0.0, 0.0, 0.0, 0.0), runner, held_keys, serial)?;
+        }
+        let end = if double { &j.double } else { &j.end };
+        exec_command_then(
+            end.as_deref().unwrap_or(""),
+            j.then.as_deref(),
+            j.output_timeout,
+            runner,
+            xdoh.held_keys(j.hold_modifiers.as_deref()),
+            serial,
+        )
+    }
+
+    /// Run the actions of a pinch rule that fired as its pinch ended
+    fn fire_pinch(
+        j: &Pinch,
+        cycle: Option<&str>,
+        serial: Option<&Serial>,
+        runner: &Runner,
+        xdoh: &mut XDoHandler,
+    ) -> Result<()> {
+        if let Some(sound) = &j.sound {
+            play_sound(sound, runner);
+        }
+        if let Some(command) = cycle {
+            let held_keys = xdoh.held_keys(j.hold_modifiers.as_deref());
+            exec_command_queued(command, (0.0, 0.0, 0.0, 0.0), runner, held_keys, serial)?;
+        }
+        exec_command_then(
+            j.end.as_deref().unwrap_or(""),
+            j.then.as_deref(),
+            j.output_timeout,
+            runner,
+            xdoh.held_keys(j.hold_modifiers.as_deref()),
+            serial,
+        )
+    }
+
+    /// Run the actions of a hold rule that fired as its hold ended
+    fn fire_hold(
+        j: &Hold,
+        cycle: Option<&str>,
+        serial: Option<&Serial>,
+        screenshot_command: Option<&str>,
+        runner: &Runner,
+        xdoh: &mut XDoHandler,
+    ) -> Result<()> {
+        if let Some(sound) = &j.sound {
+            play_sound(sound, runner);
+        }
+        if let Some(action) = j.window {
+            xdoh.window_action(action);
+        }
+        if let Some(key) = j.media {
+            xdoh.media_key(key);
+        }
+        if let Some(text) = &j.text {
+            xdoh.type_text(text);
+        }
+        if let Some(area) = j.screenshot {
+            area.take(screenshot_command, runner, xdoh);
+        }
+        if let Some(call) = &j.dbus {
+            call_dbus(call, runner);
+        }
+        if j.center_cursor {
+            xdoh.center_cursor();
+        }
+        if let Some(click) = j.click {
+            Self::click(
+                click,
+                j.click_button,
+                j.click_interval,
+                j.hold_modifiers.as_deref(),
+                xdoh,
+            );
+        }
+        if let Some(command) = cycle {
+            let held_keys = xdoh.held_keys(j.hold_modifiers.as_deref());
+            exec_command_queued(command, (0.0, 0.0, 0.0, 0.0), runner, held_keys, serial)?;
+        }
+        exec_command_then(
+            j.action.as_deref().unwrap_or(""),
+            j.then.as_deref(),
+            j.output_timeout,
+            runner,
+            xdoh.held_keys(j.hold_modifiers.as_deref()),
+            serial,
+        )
     }
 }
 
+/// Command queue of rule `idx` if it has `serialize` set, made on first use
+fn serial(serials: &mut HashMap<usize, Serial>, idx: usize, serialize: bool) -> Option<Serial> {
+    serialize.then(|| serials.entry(idx).or_insert_with(Serial::new).clone())
+}
+
+/// When the actions of a rule with a `delay` of `ms` that fires now are due
+fn due_in(ms: u64) -> Instant {
+    Instant::now() + Duration::from_millis(ms)
+}
+
+/// The command of `cycle` that is due as rule `idx` fires, moving the rule on to the one after it
+fn next_in_cycle<'a>(
+    cycles: &mut HashMap<usize, usize>,
+    idx: usize,
+    cycle: &'a [String],
+) -> Option<&'a str> {
+    if cycle.is_empty() {
+        return None;
+    }
+    let position = cycles.entry(idx).or_default();
+    let command = &cycle[*position % cycle.len()];
+    *position = (*position + 1) % cycle.len();
+    Some(command)
+}
+
+/// Whether a gesture that took `elapsed` is quick enough for a rule's `max_duration`
+fn within(elapsed: Duration, max_duration: Option<u64>) -> bool {
+    max_duration.is_none_or(|max| elapsed <= Duration::from_millis(max))
+}
+
 // Add this helper impl
 impl Swipe {
-    fn new(fingers: i32) -> Self {
+    fn new(fingers: Fingers) -> Self {
+        Self::with_direction(fingers, SwipeDir::Any)
+    }
+
+    fn with_direction(fingers: Fingers, direction: SwipeDir) -> Self {
         Self {
-            direction: SwipeDir::Any,
+            direction,
             fingers,
             update: None,
             start: None,
             end: None,
             acceleration: None,
             mouse_up_delay: None,
+            mouse_up_delay_min: None,
+            flick_velocity: None,
+            velocity_curve: None,
+            drag_threshold: None,
+            drag_delay: None,
+            grab_speed: None,
+            drag_smoothing: None,
+            button: None,
+            drag_modifiers: None,
+            min_coherence: None,
+            end_fingers: None,
+            return_radius: None,
+            cancel: None,
+            overshoot_distance: None,
+            overshoot: None,
+            exclude_directions: None,
+            angle: None,
+            tolerance: None,
+            direction_debounce: None,
+            max_duration: None,
+            max_repeats: None,
+            idle_timeout: None,
+            progress_max: None,
+            serialize: false,
+            double: None,
+            double_within: None,
+            then: None,
+            output_timeout: None,
+            cycle: Vec::new(),
+            window: None,
+            media: None,
+            text: None,
+            screenshot: None,
+            repeat_key: None,
+            dbus: None,
+            center_cursor: false,
+            click: None,
+            click_button: None,
+            click_interval: None,
+            hold_modifiers: None,
+            id: None,
+            hotkey: None,
+            when_power: None,
+            when_tablet_mode: None,
+            requires_previous: None,
+            previous_within: None,
+            delay: None,
+            confirm: None,
+            sound: None,
+            set: None,
+            fallback: false,
         }
     }
+}
 
-    fn with_direction(fingers: i32, direction: SwipeDir) -> Self {
+impl Pinch {
+    fn new(fingers: Fingers, direction: PinchDir) -> Self {
         Self {
-            direction,
             fingers,
+            direction,
             update: None,
             start: None,
             end: None,
-            acceleration: None,
-            mouse_up_delay: None,
+            scale_deadzone: None,
+            scale_mode: None,
+            scale_min: None,
+            scale_max: None,
+            value_min: None,
+            value_max: None,
+            value_step: None,
+            angle_scale: None,
+            max_duration: None,
+            max_repeats: None,
+            idle_timeout: None,
+            progress_max: None,
+            serialize: false,
+            skip_busy: false,
+            then: None,
+            output_timeout: None,
+            cycle: Vec::new(),
+            hold_modifiers: None,
+            id: None,
+            hotkey: None,
+            when_power: None,
+            when_tablet_mode: None,
+            requires_previous: None,
+            previous_within: None,
+            delay: None,
+            confirm: None,
+            sound: None,
+            set: None,
+            fallback: false,
+        }
+    }
+}
+
+impl Hold {
+    fn new(fingers: Fingers) -> Self {
+        Self {
+            fingers,
+            action: None,
+            region: None,
+            then: None,
+            output_timeout: None,
+            cycle: Vec::new(),
+            serialize: false,
+            window: None,
+            media: None,
+            text: None,
+            screenshot: None,
+            dbus: None,
+            center_cursor: false,
+            click: None,
+            click_button: None,
+            click_interval: None,
+            hold_modifiers: None,
+            id: None,
+            hotkey: None,
+            when_power: None,
+            when_tablet_mode: None,
+            requires_previous: None,
+            previous_within: None,
+            delay: None,
+            confirm: None,
+            sound: None,
+            set: None,
+            fallback: false,
         }
     }
 }
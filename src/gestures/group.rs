@@ -0,0 +1,66 @@
+use knuffel::Decode;
+
+use super::Gesture;
+
+/// Several triggers sharing one set of actions, e.g. a swipe up with either 3 or 4 fingers
+#[derive(Decode, Debug, Clone, PartialEq)]
+pub struct Group {
+    #[knuffel(property)]
+    pub update: Option<String>,
+    #[knuffel(property)]
+    pub start: Option<String>,
+    #[knuffel(property)]
+    pub end: Option<String>,
+    /// Action for hold triggers
+    #[knuffel(property)]
+    pub action: Option<String>,
+    #[knuffel(property)]
+    pub sound: Option<String>,
+    #[knuffel(children)]
+    pub triggers: Vec<Gesture>,
+}
+
+impl Group {
+    /// One rule per trigger, with the shared actions filled in where the trigger has none
+    pub fn expand(self) -> Vec<Gesture> {
+        let fill = |own: &mut Option<String>, shared: &Option<String>| {
+            if own.is_none() {
+                own.clone_from(shared);
+            }
+        };
+        let mut rules = Vec::new();
+        for trigger in self.triggers {
+            match trigger {
+                Gesture::Swipe(mut s) => {
+                    fill(&mut s.start, &self.start);
+                    fill(&mut s.update, &self.update);
+                    fill(&mut s.end, &self.end);
+                    fill(&mut s.sound, &self.sound);
+                    rules.push(Gesture::Swipe(s));
+                }
+                Gesture::Pinch(mut p) => {
+                    fill(&mut p.start, &self.start);
+                    fill(&mut p.update, &self.update);
+                    fill(&mut p.end, &self.end);
+                    fill(&mut p.sound, &self.sound);
+                    rules.push(Gesture::Pinch(p));
+                }
+                Gesture::Hold(mut h) => {
+                    fill(&mut h.action, &self.action);
+                    fill(&mut h.sound, &self.sound);
+                    rules.push(Gesture::Hold(h));
+                }
+                Gesture::Group(mut g) => {
+                    fill(&mut g.start, &self.start);
+                    fill(&mut g.update, &self.update);
+                    fill(&mut g.end, &self.end);
+                    fill(&mut g.action, &self.action);
+                    fill(&mut g.sound, &self.sound);
+                    rules.extend(g.expand());
+                }
+                Gesture::None => (),
+            }
+        }
+        rules
+    }
+}
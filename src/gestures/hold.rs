@@ -1,9 +1,137 @@
-use knuffel::Decode;
+use knuffel::{Decode, DecodeScalar};
+use schemars::JsonSchema;
+use serde::Serialize;
 
-#[derive(Decode, Debug, Clone, PartialEq, Eq)]
+use super::{Click, Fingers};
+use crate::dbus::DBusCall;
+use crate::media::MediaKey;
+use crate::power::Power;
+use crate::screenshot::Screenshot;
+use crate::tune::Tune;
+use crate::window::WindowAction;
+
+#[derive(Decode, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 pub struct Hold {
     #[knuffel(property)]
-    pub fingers: i32,
+    pub fingers: Fingers,
     #[knuffel(property)]
     pub action: Option<String>,
+    /// Part of the screen the pointer has to be in when the hold starts
+    #[knuffel(property)]
+    pub region: Option<Region>,
+    /// Command run after `action` with `{output}` replaced by what `action` printed
+    #[knuffel(property)]
+    pub then: Option<String>,
+    /// Milliseconds to wait for the output of `action` before giving up on `then`
+    #[knuffel(property)]
+    #[schemars(default = "super::default_output_timeout")]
+    pub output_timeout: Option<u64>,
+    /// Commands of which the next one runs each time the rule fires, starting over after the last
+    #[knuffel(child, unwrap(arguments), default)]
+    #[schemars(default)]
+    pub cycle: Vec<String>,
+    /// Run the rule's commands one after the other in the order they fired, instead of all at once
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub serialize: bool,
+    /// Window manager action applied to the active window when the rule fires
+    #[knuffel(property)]
+    pub window: Option<WindowAction>,
+    /// Media key pressed when the rule fires, e.g. `play-pause` or `next`
+    #[knuffel(property)]
+    pub media: Option<MediaKey>,
+    /// Text typed when the rule fires, e.g. a signature
+    #[knuffel(property(name = "type"))]
+    #[serde(rename = "type")]
+    pub text: Option<String>,
+    /// Take a screenshot of the `full` screen, the active `window` or a `region` drawn with the
+    /// mouse when the rule fires
+    #[knuffel(property)]
+    pub screenshot: Option<Screenshot>,
+    /// D-Bus method called when the rule fires
+    #[knuffel(child)]
+    pub dbus: Option<Box<DBusCall>>,
+    /// Move the pointer to the center of the focused window when the rule fires
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub center_cursor: bool,
+    /// Mouse click sent when the rule fires, `single`, `double` or `triple`
+    #[knuffel(property)]
+    pub click: Option<Click>,
+    /// Mouse button clicked, 1 (left) by default
+    #[knuffel(property)]
+    #[schemars(default = "super::default_button")]
+    pub click_button: Option<i32>,
+    /// Milliseconds between the clicks of a double or triple click
+    #[knuffel(property)]
+    #[schemars(default = "super::default_click_interval")]
+    pub click_interval: Option<u64>,
+    /// Keys held down while the rule's commands run, e.g. `ctrl` for a ctrl-click
+    #[knuffel(property)]
+    pub hold_modifiers: Option<String>,
+    /// Only match while the machine runs on this power source, `ac` or `battery`
+    #[knuffel(property)]
+    pub when_power: Option<Power>,
+    /// Only match while a 2-in-1 is (`true`) or isn't (`false`) in tablet mode
+    #[knuffel(property)]
+    pub when_tablet_mode: Option<bool>,
+    /// Name that `requires-previous` of other rules refers to
+    #[knuffel(property)]
+    pub id: Option<String>,
+    /// Key combination like `super+alt+Left` that fires the rule just like its gesture, on Xorg
+    #[knuffel(property)]
+    pub hotkey: Option<String>,
+    /// Only match shortly after the rule with this `id` fired, for two step combinations
+    #[knuffel(property)]
+    pub requires_previous: Option<String>,
+    /// Milliseconds after the `requires-previous` rule fired that this one may still match, 1000
+    /// by default
+    #[knuffel(property)]
+    #[schemars(default = "super::default_previous_within")]
+    pub previous_within: Option<u64>,
+    /// Milliseconds between the rule firing and its actions, e.g. `action`, running
+    #[knuffel(property)]
+    pub delay: Option<u64>,
+    /// Milliseconds after the rule fires to tap and confirm it, its actions only run once tapped
+    #[knuffel(property)]
+    pub confirm: Option<u64>,
+    /// Sound file played when the rule fires
+    #[knuffel(property)]
+    pub sound: Option<String>,
+    /// Config value changed when the rule fires, e.g. `acceleration +2`
+    #[knuffel(property)]
+    pub set: Option<Tune>,
+    /// Only fire when no other rule matched the gesture
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub fallback: bool,
+}
+
+/// A cell of a 3x3 grid over the screen
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Region {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Region {
+    /// Whether `(x, y)`, given as fractions of the screen size, lies in this region
+    pub fn contains(self, x: f64, y: f64) -> bool {
+        let cell = |v: f64| (v * 3.0).floor().clamp(0.0, 2.0) as usize;
+        let regions = [
+            [Self::TopLeft, Self::Top, Self::TopRight],
+            [Self::Left, Self::Center, Self::Right],
+            [Self::BottomLeft, Self::Bottom, Self::BottomRight],
+        ];
+        regions[cell(y)][cell(x)] == self
+    }
 }
@@ -1,17 +1,413 @@
+pub mod group;
 pub mod hold;
 pub mod pinch;
 pub mod swipe;
 
-use knuffel::Decode;
+use std::{fmt, time::Duration};
 
+use knuffel::{
+    ast::{Literal, TypeName},
+    decode::{Context, Kind},
+    errors::DecodeError,
+    span::Spanned,
+    traits::ErrorSpan,
+    Decode, DecodeScalar,
+};
+
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Serialize, Serializer};
+
+use crate::power::Power;
+use crate::screenshot::Screenshot;
+use group::Group;
 use hold::Hold;
 use pinch::Pinch;
 use swipe::Swipe;
 
-#[derive(Decode, Debug, Clone, PartialEq)]
+// Rules are parsed once and kept in the config, their size doesn't matter
+#[allow(clippy::large_enum_variant)]
+#[derive(Decode, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
 pub enum Gesture {
     Swipe(Swipe),
     Pinch(Pinch),
     Hold(Hold),
+    /// Only exists while parsing, [`Group::expand`] replaces it with its triggers
+    #[serde(skip)]
+    Group(Group),
+    #[serde(skip)]
     None,
 }
+
+/// Number of fingers a rule applies to, `0` or `"any"` match every finger count
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingers(pub i32);
+
+impl Fingers {
+    pub const ANY: Fingers = Fingers(0);
+
+    /// Whether a rule for `self` fingers applies to a gesture with `other` fingers, allowing the
+    /// count to be up to `tolerance` off
+    pub fn matches(self, other: Fingers, tolerance: u32) -> bool {
+        self == Self::ANY || self.0.abs_diff(other.0) <= tolerance
+    }
+}
+
+impl fmt::Display for Fingers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Self::ANY {
+            write!(f, "any")
+        } else {
+            write!(f, "{}", self.0)
+        }
+    }
+}
+
+impl Serialize for Fingers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if *self == Self::ANY {
+            serializer.serialize_str("any")
+        } else {
+            serializer.serialize_i32(self.0)
+        }
+    }
+}
+
+impl JsonSchema for Fingers {
+    fn schema_name() -> String {
+        "Fingers".to_string()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        let schema = serde_json::json!({
+            "oneOf": [{ "type": "integer", "minimum": 0 }, { "const": "any" }]
+        });
+        serde_json::from_value(schema).unwrap()
+    }
+}
+
+impl<S: ErrorSpan> DecodeScalar<S> for Fingers {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>, ctx: &mut Context<S>) {
+        <i32 as DecodeScalar<S>>::type_check(type_name, ctx)
+    }
+
+    fn raw_decode(
+        value: &Spanned<Literal, S>,
+        ctx: &mut Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        match &**value {
+            Literal::String(s) if s.eq_ignore_ascii_case("any") => Ok(Self::ANY),
+            Literal::Int(_) => Ok(Self(i32::raw_decode(value, ctx)?)),
+            _ => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::Int, value));
+                Ok(Self::ANY)
+            }
+        }
+    }
+}
+
+/// Mouse clicks a rule sends instead of, or before, its command
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Click {
+    Single,
+    Double,
+    Triple,
+}
+
+impl Click {
+    pub fn count(self) -> u32 {
+        match self {
+            Click::Single => 1,
+            Click::Double => 2,
+            Click::Triple => 3,
+        }
+    }
+}
+
+/// Default for `previous-within`
+const DEFAULT_PREVIOUS_WITHIN_MS: u64 = 1000;
+
+// Defaults of the properties that several kinds of rules have, as `gestures schema` shows them
+
+fn default_button() -> Option<i32> {
+    Some(1)
+}
+
+fn default_click_interval() -> Option<u64> {
+    Some(crate::event_handler::DEFAULT_CLICK_INTERVAL_MS)
+}
+
+fn default_output_timeout() -> Option<u64> {
+    Some(crate::utils::DEFAULT_OUTPUT_TIMEOUT_MS)
+}
+
+fn default_previous_within() -> Option<u64> {
+    Some(DEFAULT_PREVIOUS_WITHIN_MS)
+}
+
+impl Gesture {
+    /// Name of the gesture type as written in the config
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Gesture::Swipe(_) => "swipe",
+            Gesture::Pinch(_) => "pinch",
+            Gesture::Hold(_) => "hold",
+            Gesture::Group(_) => "group",
+            Gesture::None => "none",
+        }
+    }
+
+    pub fn fingers(&self) -> Option<Fingers> {
+        match self {
+            Gesture::Swipe(s) => Some(s.fingers),
+            Gesture::Pinch(p) => Some(p.fingers),
+            Gesture::Hold(h) => Some(h.fingers),
+            Gesture::Group(_) | Gesture::None => None,
+        }
+    }
+
+    /// Whether the rule drives the mouse or keyboard, or looks up the pointer, through xdo
+    pub fn needs_xdo(&self) -> bool {
+        match self {
+            Gesture::Swipe(s) => {
+                s.is_drag()
+                    || s.click.is_some()
+                    || s.center_cursor
+                    || s.window.is_some()
+                    || s.media.is_some()
+                    || s.text.is_some()
+                    || s.screenshot.is_some_and(Screenshot::needs_xdo)
+                    || s.repeat_key.is_some()
+                    || s.hold_modifiers.is_some()
+            }
+            Gesture::Pinch(p) => p.hold_modifiers.is_some(),
+            Gesture::Hold(h) => {
+                // The region is found from the pointer location
+                h.region.is_some()
+                    || h.click.is_some()
+                    || h.center_cursor
+                    || h.window.is_some()
+                    || h.media.is_some()
+                    || h.text.is_some()
+                    || h.screenshot.is_some_and(Screenshot::needs_xdo)
+                    || h.hold_modifiers.is_some()
+            }
+            Gesture::Group(_) | Gesture::None => false,
+        }
+    }
+
+    /// The shell commands of the rule, for checking that the programs they run are installed
+    pub fn commands(&self) -> Vec<&str> {
+        let (commands, cycle) = match self {
+            Gesture::Swipe(s) => (
+                vec![
+                    &s.start,
+                    &s.update,
+                    &s.end,
+                    &s.overshoot,
+                    &s.cancel,
+                    &s.double,
+                    &s.then,
+                ],
+                s.cycle.as_slice(),
+            ),
+            Gesture::Pinch(p) => (
+                vec![&p.start, &p.update, &p.end, &p.then],
+                p.cycle.as_slice(),
+            ),
+            Gesture::Hold(h) => (vec![&h.action, &h.then], h.cycle.as_slice()),
+            Gesture::Group(_) | Gesture::None => (Vec::new(), &[][..]),
+        };
+        commands
+            .into_iter()
+            .flatten()
+            .chain(cycle)
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// The shell commands of the rule, for substituting `vars` into them
+    pub fn commands_mut(&mut self) -> Vec<&mut String> {
+        let (commands, cycle) = match self {
+            Gesture::Swipe(s) => (
+                vec![
+                    &mut s.start,
+                    &mut s.update,
+                    &mut s.end,
+                    &mut s.overshoot,
+                    &mut s.cancel,
+                    &mut s.double,
+                    &mut s.then,
+                ],
+                s.cycle.iter_mut(),
+            ),
+            Gesture::Pinch(p) => (
+                vec![&mut p.start, &mut p.update, &mut p.end, &mut p.then],
+                p.cycle.iter_mut(),
+            ),
+            Gesture::Hold(h) => (vec![&mut h.action, &mut h.then], h.cycle.iter_mut()),
+            Gesture::Group(_) | Gesture::None => (Vec::new(), [].iter_mut()),
+        };
+        commands.into_iter().flatten().chain(cycle).collect()
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Gesture::Swipe(s) => s.id.as_deref(),
+            Gesture::Pinch(p) => p.id.as_deref(),
+            Gesture::Hold(h) => h.id.as_deref(),
+            Gesture::Group(_) | Gesture::None => None,
+        }
+    }
+
+    /// Power source the rule is limited to
+    pub fn when_power(&self) -> Option<Power> {
+        match self {
+            Gesture::Swipe(s) => s.when_power,
+            Gesture::Pinch(p) => p.when_power,
+            Gesture::Hold(h) => h.when_power,
+            Gesture::Group(_) | Gesture::None => None,
+        }
+    }
+
+    /// Key combination that fires the rule too
+    pub fn hotkey(&self) -> Option<&str> {
+        match self {
+            Gesture::Swipe(s) => s.hotkey.as_deref(),
+            Gesture::Pinch(p) => p.hotkey.as_deref(),
+            Gesture::Hold(h) => h.hotkey.as_deref(),
+            Gesture::Group(_) | Gesture::None => None,
+        }
+    }
+
+    /// Tablet mode the rule is limited to
+    pub fn when_tablet_mode(&self) -> Option<bool> {
+        match self {
+            Gesture::Swipe(s) => s.when_tablet_mode,
+            Gesture::Pinch(p) => p.when_tablet_mode,
+            Gesture::Hold(h) => h.when_tablet_mode,
+            Gesture::Group(_) | Gesture::None => None,
+        }
+    }
+
+    /// `id` of the rule that has to fire first and how long before at most
+    pub fn requires_previous(&self) -> Option<(&str, Duration)> {
+        let (previous, within) = match self {
+            Gesture::Swipe(s) => (s.requires_previous.as_deref()?, s.previous_within),
+            Gesture::Pinch(p) => (p.requires_previous.as_deref()?, p.previous_within),
+            Gesture::Hold(h) => (h.requires_previous.as_deref()?, h.previous_within),
+            Gesture::Group(_) | Gesture::None => return None,
+        };
+        let within = within.unwrap_or(DEFAULT_PREVIOUS_WITHIN_MS);
+        Some((previous, Duration::from_millis(within)))
+    }
+
+    /// Direction as written in the config, empty for gestures without one
+    pub fn direction(&self) -> String {
+        match self {
+            Gesture::Swipe(s) => s.direction.to_string(),
+            Gesture::Pinch(p) => p.direction.to_string(),
+            Gesture::Hold(_) | Gesture::Group(_) | Gesture::None => String::new(),
+        }
+    }
+
+    /// The commands of the rule as `name=command` pairs
+    pub fn actions(&self) -> Vec<(&'static str, &str)> {
+        let actions = match self {
+            Gesture::Swipe(s) => vec![
+                ("start", &s.start),
+                ("update", &s.update),
+                ("end", &s.end),
+                ("overshoot", &s.overshoot),
+            ],
+            Gesture::Pinch(p) => vec![("start", &p.start), ("update", &p.update), ("end", &p.end)],
+            Gesture::Hold(h) => vec![("action", &h.action)],
+            Gesture::Group(g) => vec![
+                ("start", &g.start),
+                ("update", &g.update),
+                ("end", &g.end),
+                ("action", &g.action),
+            ],
+            Gesture::None => vec![],
+        };
+        actions
+            .into_iter()
+            .filter_map(|(name, cmd)| Some((name, cmd.as_deref()?)))
+            .filter(|(_, cmd)| !cmd.trim().is_empty())
+            .collect()
+    }
+
+    /// Whether the rule has an `update` action. Its `start` and `end` then bracket the updates:
+    /// each fires exactly once, `start` before the first update and `end` when the gesture ends.
+    pub fn is_continuous(&self) -> bool {
+        let update = match self {
+            Gesture::Swipe(s) => &s.update,
+            Gesture::Pinch(p) => &p.update,
+            Gesture::Hold(_) | Gesture::Group(_) | Gesture::None => &None,
+        };
+        update.as_deref().is_some_and(|u| !u.trim().is_empty())
+    }
+
+    /// The columns of `gestures list` for the `--json` output
+    pub fn listing(&self, index: usize) -> Listing<'_> {
+        Listing {
+            index,
+            kind: self.kind(),
+            fingers: self.fingers(),
+            direction: self.direction(),
+            actions: self.actions(),
+            fallback: self.fallback(),
+        }
+    }
+
+    pub fn fallback(&self) -> bool {
+        match self {
+            Gesture::Swipe(s) => s.fallback,
+            Gesture::Pinch(p) => p.fallback,
+            Gesture::Hold(h) => h.fallback,
+            Gesture::Group(_) | Gesture::None => false,
+        }
+    }
+}
+
+/// `amount` as a whole percentage of `max`, at most 100
+pub fn progress(amount: f64, max: f64) -> f64 {
+    (amount / max * 100.0).clamp(0.0, 100.0).round()
+}
+
+/// Why `progress-max` can't be used, checked when the config is loaded
+pub fn progress_problem(max: Option<f64>) -> Option<String> {
+    max.filter(|max| *max <= 0.0)
+        .map(|max| format!("progress-max has to be above 0, got {max}"))
+}
+
+/// Iterate over `gestures` together with their index in the config, fallback rules last
+pub fn in_fallback_order(gestures: &[Gesture]) -> impl Iterator<Item = (usize, &Gesture)> {
+    let (rules, fallbacks): (Vec<_>, Vec<_>) = gestures
+        .iter()
+        .enumerate()
+        .partition(|(_, g)| !g.fallback());
+    rules.into_iter().chain(fallbacks)
+}
+
+/// A row of `gestures list --json`, see [`Gesture::listing`]
+#[derive(Serialize)]
+pub struct Listing<'a> {
+    pub index: usize,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub fingers: Option<Fingers>,
+    pub direction: String,
+    /// Object of the rule's actions in the order they fire
+    #[serde(serialize_with = "ordered_map")]
+    pub actions: Vec<(&'static str, &'a str)>,
+    pub fallback: bool,
+}
+
+fn ordered_map<S: Serializer>(
+    pairs: &[(&'static str, &str)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(pairs.iter().copied())
+}
@@ -1,9 +1,18 @@
+use std::{fmt, str::FromStr, time::Duration};
+
 use knuffel::{Decode, DecodeScalar};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use super::{progress, Fingers};
+use crate::power::Power;
+use crate::tune::Tune;
 
-#[derive(Decode, Debug, Clone, PartialEq, Eq)]
+#[derive(Decode, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 pub struct Pinch {
     #[knuffel(property)]
-    pub fingers: i32,
+    pub fingers: Fingers,
     #[knuffel(property)]
     pub direction: PinchDir,
     #[knuffel(property)]
@@ -12,10 +21,217 @@ pub struct Pinch {
     pub start: Option<String>,
     #[knuffel(property)]
     pub end: Option<String>,
+    /// How far the scale has to move away from 1.0 before `update` runs for `in` or `out`
+    #[knuffel(property)]
+    pub scale_deadzone: Option<f64>,
+    /// Whether `$scale` in `update` is the scale since the start of the pinch or its change
+    /// since the previous update
+    #[knuffel(property)]
+    pub scale_mode: Option<ScaleMode>,
+    /// Scale at which `$value` in `update` reaches `value-min`, 0.5 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_scale_min")]
+    pub scale_min: Option<f64>,
+    /// Scale at which `$value` reaches `value-max`, 2.0 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_scale_max")]
+    pub scale_max: Option<f64>,
+    /// `$value` at `scale-min` or any smaller scale, 0 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_value_min")]
+    pub value_min: Option<f64>,
+    /// `$value` at `scale-max` or any larger scale, 100 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_value_max")]
+    pub value_max: Option<f64>,
+    /// `$value` is rounded to a multiple of this, 1 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_value_step")]
+    pub value_step: Option<f64>,
+    /// Factor `$delta_angle` in `update` is multiplied by, above 1 to turn small rotations into
+    /// large ones and below 1 for finer control
+    #[knuffel(property)]
+    #[schemars(default = "default_angle_scale")]
+    pub angle_scale: Option<f64>,
+    /// Milliseconds the gesture may take at most for `end` to fire
+    #[knuffel(property)]
+    pub max_duration: Option<u64>,
+    /// Maximum number of times `update` runs during a single gesture
+    #[knuffel(property)]
+    pub max_repeats: Option<u32>,
+    /// Milliseconds the fingers may rest during the gesture, `update` stops running once they
+    /// rested that long
+    #[knuffel(property)]
+    pub idle_timeout: Option<u64>,
+    /// Change of the scale, in or out, for `{progress}` in `update` to go from 0 to 100, 0.5 by
+    /// default
+    #[knuffel(property)]
+    #[schemars(default = "default_progress_max")]
+    pub progress_max: Option<f64>,
+    /// Skip `update` while the command of the previous update is still running
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub skip_busy: bool,
+    /// Run the rule's commands one after the other in the order they fired, instead of all at once
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub serialize: bool,
+    /// Command run after `end` with `{output}` replaced by what `end` printed
+    #[knuffel(property)]
+    pub then: Option<String>,
+    /// Milliseconds to wait for the output of `end` before giving up on `then`
+    #[knuffel(property)]
+    #[schemars(default = "super::default_output_timeout")]
+    pub output_timeout: Option<u64>,
+    /// Commands of which the next one runs each time the rule fires, starting over after the last
+    #[knuffel(child, unwrap(arguments), default)]
+    #[schemars(default)]
+    pub cycle: Vec<String>,
+    /// Keys held down while the rule's commands run, e.g. `ctrl` for a ctrl-click
+    #[knuffel(property)]
+    pub hold_modifiers: Option<String>,
+    /// Only match while the machine runs on this power source, `ac` or `battery`
+    #[knuffel(property)]
+    pub when_power: Option<Power>,
+    /// Only match while a 2-in-1 is (`true`) or isn't (`false`) in tablet mode
+    #[knuffel(property)]
+    pub when_tablet_mode: Option<bool>,
+    /// Name that `requires-previous` of other rules refers to
+    #[knuffel(property)]
+    pub id: Option<String>,
+    /// Key combination like `super+alt+Left` that fires the rule just like its gesture, on Xorg
+    #[knuffel(property)]
+    pub hotkey: Option<String>,
+    /// Only match shortly after the rule with this `id` fired, for two step combinations
+    #[knuffel(property)]
+    pub requires_previous: Option<String>,
+    /// Milliseconds after the `requires-previous` rule fired that this one may still match, 1000
+    /// by default
+    #[knuffel(property)]
+    #[schemars(default = "super::default_previous_within")]
+    pub previous_within: Option<u64>,
+    /// Milliseconds between the rule firing and its actions, e.g. `end`, running
+    #[knuffel(property)]
+    pub delay: Option<u64>,
+    /// Milliseconds after the rule fires to tap and confirm it, its actions only run once tapped
+    #[knuffel(property)]
+    pub confirm: Option<u64>,
+    /// Sound file played when the rule fires
+    #[knuffel(property)]
+    pub sound: Option<String>,
+    /// Config value changed when the rule fires, e.g. `acceleration +2`
+    #[knuffel(property)]
+    pub set: Option<Tune>,
+    /// Only fire when no other rule matched the gesture
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub fallback: bool,
+}
+
+/// Defaults of the ranges that [`Pinch::value`] maps between
+const DEFAULT_SCALE_RANGE: (f64, f64) = (0.5, 2.0);
+const DEFAULT_VALUE_RANGE: (f64, f64) = (0.0, 100.0);
+
+/// Default for [`Pinch::progress`]
+const DEFAULT_PROGRESS_MAX: f64 = 0.5;
+
+fn default_scale_min() -> Option<f64> {
+    Some(DEFAULT_SCALE_RANGE.0)
+}
+
+fn default_scale_max() -> Option<f64> {
+    Some(DEFAULT_SCALE_RANGE.1)
+}
+
+fn default_value_min() -> Option<f64> {
+    Some(DEFAULT_VALUE_RANGE.0)
+}
+
+fn default_value_max() -> Option<f64> {
+    Some(DEFAULT_VALUE_RANGE.1)
+}
+
+fn default_value_step() -> Option<f64> {
+    Some(1.0)
+}
+
+fn default_angle_scale() -> Option<f64> {
+    Some(1.0)
+}
+
+fn default_progress_max() -> Option<f64> {
+    Some(DEFAULT_PROGRESS_MAX)
+}
+
+impl Pinch {
+    /// Map the scale since the start of the pinch onto the rule's value range, clamped to it and
+    /// rounded to `value-step`
+    pub fn value(&self, scale: f64) -> f64 {
+        let (scale_min, scale_max) = (
+            self.scale_min.unwrap_or(DEFAULT_SCALE_RANGE.0),
+            self.scale_max.unwrap_or(DEFAULT_SCALE_RANGE.1),
+        );
+        let (value_min, value_max) = (
+            self.value_min.unwrap_or(DEFAULT_VALUE_RANGE.0),
+            self.value_max.unwrap_or(DEFAULT_VALUE_RANGE.1),
+        );
+        let step = self.value_step.unwrap_or(1.0);
+        let t = ((scale - scale_min) / (scale_max - scale_min)).clamp(0.0, 1.0);
+        let value = ((value_min + t * (value_max - value_min)) / step).round() * step;
+        value.clamp(value_min.min(value_max), value_min.max(value_max))
+    }
+
+    /// `{progress}` of a pinch at `scale` since its start
+    pub fn progress(&self, scale: f64) -> f64 {
+        let max = self.progress_max.unwrap_or(DEFAULT_PROGRESS_MAX);
+        progress((scale - 1.0).abs(), max)
+    }
+
+    /// Why `$value` can't be computed, checked when the config is loaded
+    pub fn value_problem(&self) -> Option<String> {
+        let scale_min = self.scale_min.unwrap_or(DEFAULT_SCALE_RANGE.0);
+        let scale_max = self.scale_max.unwrap_or(DEFAULT_SCALE_RANGE.1);
+        if scale_min >= scale_max {
+            return Some(format!(
+                "scale-min ({scale_min}) has to be smaller than scale-max ({scale_max})"
+            ));
+        }
+        match self.value_step {
+            Some(step) if step <= 0.0 => Some(format!("value-step has to be positive, got {step}")),
+            _ => None,
+        }
+    }
+
+    /// Whether `update` stops because the fingers rested for `idle` during the pinch
+    pub fn idled(&self, idle: Duration) -> bool {
+        self.idle_timeout
+            .is_some_and(|ms| idle >= Duration::from_millis(ms))
+    }
+
+    /// Whether an update classified as `dir` with `scale` matches this rule
+    pub fn matches_update(&self, dir: &PinchDir, scale: f64) -> bool {
+        let in_deadzone = matches!(dir, PinchDir::In | PinchDir::Out)
+            && self
+                .scale_deadzone
+                .is_some_and(|deadzone| (scale - 1.0).abs() <= deadzone);
+        !in_deadzone && (self.direction == *dir || self.direction == PinchDir::Any)
+    }
+}
+
+/// Value substituted for `$scale` in a pinch's `update`
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScaleMode {
+    /// Relative to the size at the start of the pinch, 1.0 being unchanged
+    #[default]
+    Cumulative,
+    /// Signed change since the previous update
+    Delta,
 }
 
 /// Direction of pinch gestures
-#[derive(DecodeScalar, Debug, Clone, PartialEq, Eq)]
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
 pub enum PinchDir {
     In,
     Out,
@@ -41,3 +257,73 @@ impl PinchDir {
         }
     }
 }
+
+/// Scale a pinch has to reach, or degrees it has to rotate, before `pinch-lock` commits to it
+const LOCK_SCALE: f64 = 0.05;
+const LOCK_ANGLE: f64 = 5.0;
+
+/// Whether a pinch with `pinch-lock` is a pinch or a rotation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinchKind {
+    Scale,
+    Rotate,
+}
+
+impl PinchKind {
+    /// Commit to whichever motion went further past its threshold, `None` while both are below it
+    pub fn decide(scale: f64, angle: f64) -> Option<Self> {
+        let scaled = (scale - 1.0).abs() / LOCK_SCALE;
+        let rotated = angle.abs() / LOCK_ANGLE;
+        if scaled.max(rotated) < 1.0 {
+            None
+        } else if scaled >= rotated {
+            Some(Self::Scale)
+        } else {
+            Some(Self::Rotate)
+        }
+    }
+}
+
+impl PinchDir {
+    /// Like [`PinchDir::dir`], but only considers the motion the pinch was committed to
+    pub fn dir_locked(scale: f64, delta_angle: f64, kind: PinchKind) -> Self {
+        match kind {
+            PinchKind::Scale if scale > 1.0 => Self::Out,
+            PinchKind::Scale => Self::In,
+            PinchKind::Rotate if delta_angle > 0.0 => Self::Clockwise,
+            PinchKind::Rotate if delta_angle < 0.0 => Self::CounterClockwise,
+            PinchKind::Rotate => Self::Any,
+        }
+    }
+}
+
+impl fmt::Display for PinchDir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Same spelling as in the config
+        let s = match self {
+            Self::In => "in",
+            Self::Out => "out",
+            Self::Clockwise => "clockwise",
+            Self::CounterClockwise => "counter-clockwise",
+            Self::Any => "any",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromStr for PinchDir {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "in" => Ok(Self::In),
+            "out" => Ok(Self::Out),
+            "clockwise" => Ok(Self::Clockwise),
+            "counter-clockwise" => Ok(Self::CounterClockwise),
+            "any" => Ok(Self::Any),
+            _ => Err(format!(
+                "unknown pinch direction {s:?}, expected in, out, clockwise, counter-clockwise or any"
+            )),
+        }
+    }
+}
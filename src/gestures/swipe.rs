@@ -1,11 +1,36 @@
-use knuffel::{Decode, DecodeScalar};
+use std::{
+    f64::consts::{FRAC_PI_4, FRAC_PI_8, PI},
+    fmt,
+    str::FromStr,
+    time::Duration,
+};
 
-#[derive(Decode, Debug, Clone, PartialEq, Eq)]
+use knuffel::{
+    ast::{Literal, TypeName},
+    decode::{Context, Kind},
+    errors::DecodeError,
+    span::Spanned,
+    traits::ErrorSpan,
+    Decode, DecodeScalar,
+};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Serialize, Serializer};
+
+use super::{progress, Click, Fingers};
+use crate::dbus::DBusCall;
+use crate::media::MediaKey;
+use crate::power::Power;
+use crate::screenshot::Screenshot;
+use crate::tune::Tune;
+use crate::window::WindowAction;
+
+#[derive(Decode, Serialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(rename_all = "kebab-case")]
 pub struct Swipe {
     #[knuffel(property)]
     pub direction: SwipeDir,
     #[knuffel(property)]
-    pub fingers: i32,
+    pub fingers: Fingers,
     #[knuffel(property)]
     pub update: Option<String>,
     #[knuffel(property)]
@@ -16,6 +41,341 @@ pub struct Swipe {
     pub acceleration: Option<i8>,
     #[knuffel(property)]
     pub mouse_up_delay: Option<i64>,
+    /// Delay for a drag that ends at rest, `mouse-up-delay` then only applies to fast ones
+    #[knuffel(property)]
+    pub mouse_up_delay_min: Option<i64>,
+    /// Speed, in units per second, from which a drag gets the full `mouse-up-delay`
+    #[knuffel(property)]
+    #[schemars(default = "default_flick_velocity")]
+    pub flick_velocity: Option<f64>,
+    /// Exponent of the curve between the two delays, above 1.0 keeps slow drags closer to the
+    /// minimum
+    #[knuffel(property)]
+    pub velocity_curve: Option<f64>,
+    /// Distance the swipe has to travel before the drag presses the mouse button
+    #[knuffel(property)]
+    pub drag_threshold: Option<f64>,
+    /// Milliseconds the swipe has to last before the drag presses the mouse button
+    #[knuffel(property)]
+    pub drag_delay: Option<u64>,
+    /// Speed, in units per second, that the first updates of the swipe may not exceed for the drag
+    /// to press the button. Faster starts are flicks, left to the directional rules.
+    #[knuffel(property)]
+    pub grab_speed: Option<f64>,
+    /// How much of the previous movement carries into each step of the drag, from 0.0 (none) up
+    /// to below 1.0, to steady a jittery pointer at the cost of some lag
+    #[knuffel(property)]
+    pub drag_smoothing: Option<f64>,
+    /// Mouse button held during the drag, 1 (left) by default
+    #[knuffel(property)]
+    #[schemars(default = "super::default_button")]
+    pub button: Option<i32>,
+    /// Keys held during the drag, e.g. `shift` or `ctrl+shift`
+    #[knuffel(property)]
+    pub drag_modifiers: Option<String>,
+    /// Direction in degrees, counterclockwise from east, matched instead of `direction`
+    #[knuffel(property)]
+    pub angle: Option<f64>,
+    /// Directions the rule never matches, e.g. `"n"` or `"n ne nw"`
+    #[knuffel(property)]
+    pub exclude_directions: Option<SwipeDirs>,
+    /// Degrees a swipe may deviate from `angle` and still match, 22.5 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_tolerance")]
+    pub tolerance: Option<f64>,
+    /// Milliseconds a continuous swipe has to keep a new direction before `update` follows it
+    #[knuffel(property)]
+    pub direction_debounce: Option<u64>,
+    /// Distance per finger the swipe has to travel before the rule matches, so that a swipe of
+    /// mostly resting fingers, whose average barely moves, is ignored
+    #[knuffel(property)]
+    pub min_coherence: Option<f64>,
+    /// Only fire when this many fingers are still down as the swipe ends
+    #[knuffel(property)]
+    pub end_fingers: Option<Fingers>,
+    /// Distance from where the swipe began within which a swipe that went further out and came
+    /// back runs `cancel` instead of `end`
+    #[knuffel(property)]
+    pub return_radius: Option<f64>,
+    /// Command run instead of `end` for a swipe that returned to where it began
+    #[knuffel(property)]
+    pub cancel: Option<String>,
+    /// Distance after which `overshoot` fires, once per swipe
+    #[knuffel(property)]
+    pub overshoot_distance: Option<f64>,
+    /// Command run when the swipe travels further than `overshoot_distance`
+    #[knuffel(property)]
+    pub overshoot: Option<String>,
+    /// Milliseconds the gesture may take at most for `end` to fire
+    #[knuffel(property)]
+    pub max_duration: Option<u64>,
+    /// Maximum number of times `update` runs during a single gesture
+    #[knuffel(property)]
+    pub max_repeats: Option<u32>,
+    /// Milliseconds the fingers may rest during the gesture, `update` stops running once they
+    /// rested that long
+    #[knuffel(property)]
+    pub idle_timeout: Option<u64>,
+    /// Distance the swipe travels for `{progress}` in `update` to go from 0 to 100, 300 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_progress_max")]
+    pub progress_max: Option<f64>,
+    /// Run the rule's commands one after the other in the order they fired, instead of all at once
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub serialize: bool,
+    /// Command run instead of `end` when the swipe is repeated within `double-within`, the other
+    /// actions run as usual. The actions of a single swipe wait that long for a second one.
+    #[knuffel(property)]
+    pub double: Option<String>,
+    /// Milliseconds between two swipes that make a `double`, 300 by default
+    #[knuffel(property)]
+    #[schemars(default = "default_double_within")]
+    pub double_within: Option<u64>,
+    /// Command run after `end` with `{output}` replaced by what `end` printed
+    #[knuffel(property)]
+    pub then: Option<String>,
+    /// Milliseconds to wait for the output of `end` before giving up on `then`
+    #[knuffel(property)]
+    #[schemars(default = "super::default_output_timeout")]
+    pub output_timeout: Option<u64>,
+    /// Commands of which the next one runs each time the rule fires, starting over after the last
+    #[knuffel(child, unwrap(arguments), default)]
+    #[schemars(default)]
+    pub cycle: Vec<String>,
+    /// Window manager action applied to the active window when the rule fires
+    #[knuffel(property)]
+    pub window: Option<WindowAction>,
+    /// Media key pressed when the rule fires, e.g. `play-pause` or `next`
+    #[knuffel(property)]
+    pub media: Option<MediaKey>,
+    /// Text typed when the rule fires, e.g. a signature
+    #[knuffel(property(name = "type"))]
+    #[serde(rename = "type")]
+    pub text: Option<String>,
+    /// Take a screenshot of the `full` screen, the active `window` or a `region` drawn with the
+    /// mouse when the rule fires
+    #[knuffel(property)]
+    pub screenshot: Option<Screenshot>,
+    /// Key held down while the swipe goes the rule's way, so that it autorepeats, e.g. `Down`
+    #[knuffel(property)]
+    pub repeat_key: Option<String>,
+    /// D-Bus method called when the rule fires
+    #[knuffel(child)]
+    pub dbus: Option<Box<DBusCall>>,
+    /// Move the pointer to the center of the focused window when the rule fires
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub center_cursor: bool,
+    /// Mouse click sent when the rule fires, `single`, `double` or `triple`
+    #[knuffel(property)]
+    pub click: Option<Click>,
+    /// Mouse button clicked, 1 (left) by default
+    #[knuffel(property)]
+    #[schemars(default = "super::default_button")]
+    pub click_button: Option<i32>,
+    /// Milliseconds between the clicks of a double or triple click
+    #[knuffel(property)]
+    #[schemars(default = "super::default_click_interval")]
+    pub click_interval: Option<u64>,
+    /// Keys held down while the rule's commands run, e.g. `ctrl` for a ctrl-click
+    #[knuffel(property)]
+    pub hold_modifiers: Option<String>,
+    /// Only match while the machine runs on this power source, `ac` or `battery`
+    #[knuffel(property)]
+    pub when_power: Option<Power>,
+    /// Only match while a 2-in-1 is (`true`) or isn't (`false`) in tablet mode
+    #[knuffel(property)]
+    pub when_tablet_mode: Option<bool>,
+    /// Name that `requires-previous` of other rules refers to
+    #[knuffel(property)]
+    pub id: Option<String>,
+    /// Key combination like `super+alt+Left` that fires the rule just like its gesture, on Xorg
+    #[knuffel(property)]
+    pub hotkey: Option<String>,
+    /// Only match shortly after the rule with this `id` fired, for two step combinations
+    #[knuffel(property)]
+    pub requires_previous: Option<String>,
+    /// Milliseconds after the `requires-previous` rule fired that this one may still match, 1000
+    /// by default
+    #[knuffel(property)]
+    #[schemars(default = "super::default_previous_within")]
+    pub previous_within: Option<u64>,
+    /// Milliseconds between the rule firing and its actions, e.g. `end`, running
+    #[knuffel(property)]
+    pub delay: Option<u64>,
+    /// Milliseconds after the rule fires to tap and confirm it, its actions only run once tapped
+    #[knuffel(property)]
+    pub confirm: Option<u64>,
+    /// Sound file played when the rule fires
+    #[knuffel(property)]
+    pub sound: Option<String>,
+    /// Config value changed when the rule fires, e.g. `acceleration +2`
+    #[knuffel(property)]
+    pub set: Option<Tune>,
+    /// Only fire when no other rule matched the gesture
+    #[knuffel(property, default)]
+    #[schemars(default)]
+    pub fallback: bool,
+}
+
+/// Default for [`Swipe::double_within`]
+const DEFAULT_DOUBLE_WITHIN_MS: u64 = 300;
+
+/// Default for [`Swipe::flick_velocity`]
+const DEFAULT_FLICK_VELOCITY: f64 = 1000.0;
+
+/// Default for [`Swipe::tolerance`], the same width as the buckets of [`SwipeDir`]
+const DEFAULT_TOLERANCE: f64 = 22.5;
+
+/// Default for [`Swipe::progress_max`]
+const DEFAULT_PROGRESS_MAX: f64 = 300.0;
+
+fn default_double_within() -> Option<u64> {
+    Some(DEFAULT_DOUBLE_WITHIN_MS)
+}
+
+fn default_flick_velocity() -> Option<f64> {
+    Some(DEFAULT_FLICK_VELOCITY)
+}
+
+fn default_tolerance() -> Option<f64> {
+    Some(DEFAULT_TOLERANCE)
+}
+
+fn default_progress_max() -> Option<f64> {
+    Some(DEFAULT_PROGRESS_MAX)
+}
+
+/// Drag settings inherited by the swipe rules for `fingers` that don't set them
+#[derive(Decode, Debug, Clone, PartialEq)]
+pub struct SwipeDefaults {
+    #[knuffel(property)]
+    pub fingers: Fingers,
+    #[knuffel(property)]
+    pub acceleration: Option<i8>,
+    #[knuffel(property)]
+    pub mouse_up_delay: Option<i64>,
+    #[knuffel(property)]
+    pub drag_threshold: Option<f64>,
+    #[knuffel(property)]
+    pub drag_delay: Option<u64>,
+    #[knuffel(property)]
+    pub button: Option<i32>,
+    #[knuffel(property)]
+    pub drag_modifiers: Option<String>,
+}
+
+impl Swipe {
+    /// Fill the drag settings the rule leaves unset from `defaults`
+    pub fn inherit(&mut self, defaults: &SwipeDefaults) {
+        self.acceleration = self.acceleration.or(defaults.acceleration);
+        self.mouse_up_delay = self.mouse_up_delay.or(defaults.mouse_up_delay);
+        self.drag_threshold = self.drag_threshold.or(defaults.drag_threshold);
+        self.drag_delay = self.drag_delay.or(defaults.drag_delay);
+        self.button = self.button.or(defaults.button);
+        if self.drag_modifiers.is_none() {
+            self.drag_modifiers.clone_from(&defaults.drag_modifiers);
+        }
+    }
+
+    /// Milliseconds to keep the button down after a drag that ended moving at `velocity`. Without
+    /// `mouse-up-delay-min` this is always `mouse-up-delay`.
+    pub fn release_delay(&self, velocity: f64) -> i64 {
+        let max = self.mouse_up_delay.unwrap_or_default();
+        let Some(min) = self.mouse_up_delay_min else {
+            return max;
+        };
+        let flick = self.flick_velocity.unwrap_or(DEFAULT_FLICK_VELOCITY);
+        let t = (velocity / flick)
+            .clamp(0.0, 1.0)
+            .powf(self.velocity_curve.unwrap_or(1.0));
+        min + ((max - min) as f64 * t).round() as i64
+    }
+
+    /// Movement of a drag step after smoothing `(dx, dy)` into the movement of the previous step
+    pub fn smooth(&self, (dx, dy): (f64, f64), previous: Option<(f64, f64)>) -> (f64, f64) {
+        match (self.drag_smoothing, previous) {
+            (Some(weight), Some((px, py))) => (
+                weight * px + (1.0 - weight) * dx,
+                weight * py + (1.0 - weight) * dy,
+            ),
+            _ => (dx, dy),
+        }
+    }
+
+    /// Why `drag-smoothing` can't be used, checked when the config is loaded
+    pub fn smoothing_problem(&self) -> Option<String> {
+        self.drag_smoothing
+            .filter(|s| !(0.0..1.0).contains(s))
+            .map(|s| format!("drag-smoothing has to be at least 0.0 and below 1.0, got {s}"))
+    }
+
+    /// `{progress}` of a swipe that traveled `distance` so far
+    pub fn progress(&self, distance: f64) -> f64 {
+        progress(distance, self.progress_max.unwrap_or(DEFAULT_PROGRESS_MAX))
+    }
+
+    /// Whether `update` stops because the fingers rested for `idle` during the swipe
+    pub fn idled(&self, idle: Duration) -> bool {
+        self.idle_timeout
+            .is_some_and(|ms| idle >= Duration::from_millis(ms))
+    }
+
+    /// Whether a swipe that travelled `distance` in `elapsed` started slowly enough to grab
+    pub fn grabs(&self, distance: f64, elapsed: Duration) -> bool {
+        self.grab_speed
+            .is_none_or(|max| distance <= max * elapsed.as_secs_f64())
+    }
+
+    /// Whether a swipe with `fingers` that travelled `distance` moved enough for this rule
+    pub fn is_coherent(&self, fingers: Fingers, distance: f64) -> bool {
+        self.min_coherence
+            .is_none_or(|min| distance >= min * f64::from(fingers.0.max(1)))
+    }
+
+    /// How long the actions of a single swipe wait for a second one that makes a `double`
+    pub fn double_within(&self) -> Duration {
+        Duration::from_millis(self.double_within.unwrap_or(DEFAULT_DOUBLE_WITHIN_MS))
+    }
+
+    /// Whether a swipe that got `farthest` away from where it began and ended `distance` away from
+    /// it came back within `return_radius`
+    pub fn returned(&self, farthest: f64, distance: f64) -> bool {
+        self.return_radius
+            .is_some_and(|radius| farthest > radius && distance <= radius)
+    }
+
+    /// Whether the rule drags the pointer on Xorg rather than running commands
+    pub fn is_drag(&self) -> bool {
+        self.acceleration.is_some() && self.mouse_up_delay.is_some() && !self.is_directional()
+    }
+
+    /// Whether this rule only matches swipes in some direction
+    pub fn is_directional(&self) -> bool {
+        self.direction != SwipeDir::Any || self.angle.is_some() || self.exclude_directions.is_some()
+    }
+
+    /// Whether a swipe that moved by `(x, y)`, classified as `dir`, matches this rule
+    pub fn matches_direction(&self, dir: &SwipeDir, x: f64, y: f64) -> bool {
+        if self
+            .exclude_directions
+            .as_ref()
+            .is_some_and(|d| d.0.contains(dir))
+        {
+            return false;
+        }
+        match self.angle {
+            Some(angle) => {
+                if x == 0.0 && y == 0.0 {
+                    return false;
+                }
+                let diff = (SwipeDir::angle(x, y) - angle).rem_euclid(360.0);
+                diff.min(360.0 - diff) <= self.tolerance.unwrap_or(DEFAULT_TOLERANCE)
+            }
+            None => self.direction == *dir || self.direction == SwipeDir::Any,
+        }
+    }
 }
 
 /// Direction of swipe gestures
@@ -23,7 +383,8 @@ pub struct Swipe {
 /// NW  N  NE
 /// W   C   E
 /// SW  S  SE
-#[derive(DecodeScalar, Debug, Clone, PartialEq, Eq)]
+#[derive(Serialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
 pub enum SwipeDir {
     Any,
     N,
@@ -45,15 +406,176 @@ impl SwipeDir {
         let angle = y.atan2(x); // Range: -π to π
 
         match angle {
-            a if a < -2.7489 => SwipeDir::W,  // -π to -7π/8
-            a if a < -1.9635 => SwipeDir::NW, // -7π/8 to -5π/8
-            a if a < -1.1781 => SwipeDir::N,  // -5π/8 to -3π/8
-            a if a < -0.3927 => SwipeDir::NE, // -3π/8 to -π/8
-            a if a < 0.3927 => SwipeDir::E,   // -π/8 to π/8
-            a if a < 1.1781 => SwipeDir::SE,  // π/8 to 3π/8
-            a if a < 1.9635 => SwipeDir::S,   // 3π/8 to 5π/8
-            a if a < 2.7489 => SwipeDir::SW,  // 5π/8 to 7π/8
-            _ => SwipeDir::W,                 // 7π/8 to π
+            a if a < -2.7489 => SwipeDir::W,     // -π to -7π/8
+            a if a < -1.9635 => SwipeDir::NW,    // -7π/8 to -5π/8
+            a if a < -1.1781 => SwipeDir::N,     // -5π/8 to -3π/8
+            a if a < -FRAC_PI_8 => SwipeDir::NE, // -3π/8 to -π/8
+            a if a < FRAC_PI_8 => SwipeDir::E,   // -π/8 to π/8
+            a if a < 1.1781 => SwipeDir::SE,     // π/8 to 3π/8
+            a if a < 1.9635 => SwipeDir::S,      // 3π/8 to 5π/8
+            a if a < 2.7489 => SwipeDir::SW,     // 5π/8 to 7π/8
+            _ => SwipeDir::W,                    // 7π/8 to π
         }
     }
+
+    /// Angle of a movement in degrees in `[0, 360)`, counterclockwise from east
+    pub fn angle(x: f64, y: f64) -> f64 {
+        // y grows downwards
+        (-y).atan2(x).to_degrees().rem_euclid(360.0)
+    }
+
+    /// Like [`SwipeDir::dir`], but keeps `previous` until the swipe is more than `margin` degrees
+    /// past the edge of its bucket, so swipes along a boundary don't flicker between directions.
+    pub fn dir_with_hysteresis(x: f64, y: f64, previous: &SwipeDir, margin: f64) -> SwipeDir {
+        let dir = Self::dir(x, y);
+        let center = match previous.center() {
+            Some(c) if dir != SwipeDir::Any && dir != *previous => c,
+            _ => return dir,
+        };
+
+        // Smallest angle between the swipe and the center of the previous bucket
+        let diff = (y.atan2(x) - center).rem_euclid(2.0 * PI);
+        let diff = diff.min(2.0 * PI - diff);
+        if diff < FRAC_PI_8 + margin.to_radians() {
+            previous.clone()
+        } else {
+            dir
+        }
+    }
+
+    /// Unit vector pointing in this direction, in the same frame as the swipe deltas
+    pub fn vector(&self) -> Option<(f64, f64)> {
+        self.center().map(|a| (a.cos(), a.sin()))
+    }
+
+    /// Angle at the center of this direction's bucket, in the same frame as [`SwipeDir::dir`]
+    fn center(&self) -> Option<f64> {
+        match self {
+            SwipeDir::Any => None,
+            SwipeDir::E => Some(0.0),
+            SwipeDir::SE => Some(FRAC_PI_4),
+            SwipeDir::S => Some(2.0 * FRAC_PI_4),
+            SwipeDir::SW => Some(3.0 * FRAC_PI_4),
+            SwipeDir::W => Some(PI),
+            SwipeDir::NW => Some(-3.0 * FRAC_PI_4),
+            SwipeDir::N => Some(-2.0 * FRAC_PI_4),
+            SwipeDir::NE => Some(-FRAC_PI_4),
+        }
+    }
+}
+
+/// Case-insensitive, accepts the short forms (`n`, `ne`), compass names (`north`, `north-east`)
+/// and screen directions (`up`, `up-right`)
+impl FromStr for SwipeDir {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| !matches!(c, '-' | '_' | ' '))
+            .collect::<String>()
+            .to_lowercase();
+        match name.as_str() {
+            "any" => Ok(SwipeDir::Any),
+            "n" | "north" | "up" => Ok(SwipeDir::N),
+            "s" | "south" | "down" => Ok(SwipeDir::S),
+            "e" | "east" | "right" => Ok(SwipeDir::E),
+            "w" | "west" | "left" => Ok(SwipeDir::W),
+            "ne" | "northeast" | "upright" => Ok(SwipeDir::NE),
+            "nw" | "northwest" | "upleft" => Ok(SwipeDir::NW),
+            "se" | "southeast" | "downright" => Ok(SwipeDir::SE),
+            "sw" | "southwest" | "downleft" => Ok(SwipeDir::SW),
+            _ => Err(format!(
+                "unknown swipe direction {s:?}, expected any, n, ne, e, se, s, sw, w or nw"
+            )),
+        }
+    }
+}
+
+/// Several directions written in one string, separated by spaces or commas
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwipeDirs(pub Vec<SwipeDir>);
+
+impl FromStr for SwipeDirs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split([' ', ','])
+            .filter(|d| !d.is_empty())
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for SwipeDirs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dirs: Vec<String> = self.0.iter().map(SwipeDir::to_string).collect();
+        write!(f, "{}", dirs.join(" "))
+    }
+}
+
+impl Serialize for SwipeDirs {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl JsonSchema for SwipeDirs {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "SwipeDirs".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl<S: ErrorSpan> DecodeScalar<S> for SwipeDirs {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>, ctx: &mut Context<S>) {
+        <String as DecodeScalar<S>>::type_check(type_name, ctx)
+    }
+
+    fn raw_decode(
+        value: &Spanned<Literal, S>,
+        ctx: &mut Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        match &**value {
+            Literal::String(s) => s.parse().map_err(|e| DecodeError::conversion(value, e)),
+            _ => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::String, value));
+                Ok(SwipeDirs(Vec::new()))
+            }
+        }
+    }
+}
+
+impl<S: ErrorSpan> DecodeScalar<S> for SwipeDir {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>, ctx: &mut Context<S>) {
+        <String as DecodeScalar<S>>::type_check(type_name, ctx)
+    }
+
+    fn raw_decode(
+        value: &Spanned<Literal, S>,
+        ctx: &mut Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        match &**value {
+            Literal::String(s) => s.parse().map_err(|e| DecodeError::conversion(value, e)),
+            _ => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::String, value));
+                Ok(SwipeDir::Any)
+            }
+        }
+    }
+}
+
+impl fmt::Display for SwipeDir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Same spelling as in the config
+        write!(f, "{}", format!("{self:?}").to_lowercase())
+    }
 }
@@ -0,0 +1,160 @@
+use std::ffi::{c_int, c_uint, CString};
+use std::os::fd::{BorrowedFd, RawFd};
+use std::ptr;
+
+use x11::xlib::{self, Display};
+
+use crate::gestures::Gesture;
+
+/// Modifiers that don't change which hotkey was pressed, a grab is made with each combination of
+/// them so that Caps Lock or Num Lock being on doesn't keep it from matching
+const LOCKS: [c_uint; 2] = [xlib::LockMask, xlib::Mod2Mask];
+
+/// Modifier mask and keysym name of a combination like `super+alt+Left`
+pub fn parse(hotkey: &str) -> Result<(c_uint, &str), String> {
+    let mut parts: Vec<&str> = hotkey.split('+').map(str::trim).collect();
+    let key = parts.pop().filter(|key| !key.is_empty());
+    let Some(key) = key else {
+        return Err(format!("hotkey {hotkey:?} has no key"));
+    };
+    let mut mask = 0;
+    for modifier in parts {
+        mask |= match modifier.to_lowercase().as_str() {
+            "ctrl" | "control" => xlib::ControlMask,
+            "shift" => xlib::ShiftMask,
+            "alt" => xlib::Mod1Mask,
+            "super" => xlib::Mod4Mask,
+            _ => {
+                return Err(format!(
+                    "hotkey {hotkey:?} has an unknown modifier {modifier:?}, expected ctrl, shift, alt or super"
+                ))
+            }
+        };
+    }
+    Ok((mask, key))
+}
+
+/// Keys grabbed on the X server for the rules with a `hotkey`, over a connection of their own
+#[derive(Debug)]
+pub struct Hotkeys {
+    display: *mut Display,
+    /// Keycode and modifiers of each grab, with the rule it fires
+    grabs: Vec<(c_uint, c_uint, usize)>,
+}
+
+// The connection is only ever used by the thread of the handler that owns it
+unsafe impl Send for Hotkeys {}
+
+impl Hotkeys {
+    /// Connect to the X server, `None` when there is none to connect to
+    pub fn open() -> Option<Self> {
+        let display = unsafe { xlib::XOpenDisplay(ptr::null()) };
+        if display.is_null() {
+            return None;
+        }
+        // Xlib exits the process on errors by default, and a key another program grabbed
+        // already is one
+        unsafe { xlib::XSetErrorHandler(Some(log_error)) };
+        Some(Self {
+            display,
+            grabs: Vec::new(),
+        })
+    }
+
+    /// Replace the grabs with the hotkeys of `gestures`
+    pub fn grab(&mut self, gestures: &[Gesture]) {
+        unsafe {
+            let root = xlib::XDefaultRootWindow(self.display);
+            xlib::XUngrabKey(self.display, xlib::AnyKey, xlib::AnyModifier, root);
+            self.grabs.clear();
+            for (idx, hotkey) in gestures
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, g)| Some((idx, g.hotkey()?)))
+            {
+                let Ok((mask, key)) = parse(hotkey) else {
+                    continue;
+                };
+                let keycode = CString::new(key)
+                    .ok()
+                    .map(|name| xlib::XStringToKeysym(name.as_ptr()))
+                    .filter(|&keysym| keysym != 0)
+                    .map(|keysym| xlib::XKeysymToKeycode(self.display, keysym))
+                    .filter(|&keycode| keycode != 0);
+                let Some(keycode) = keycode else {
+                    log::warn!("No key is called {key:?}, the hotkey of rule {idx} is ignored");
+                    continue;
+                };
+                let keycode = keycode as c_uint;
+                for locks in [0, LOCKS[0], LOCKS[1], LOCKS[0] | LOCKS[1]] {
+                    xlib::XGrabKey(
+                        self.display,
+                        keycode as c_int,
+                        mask | locks,
+                        root,
+                        xlib::False,
+                        xlib::GrabModeAsync,
+                        xlib::GrabModeAsync,
+                    );
+                }
+                log::debug!("Grabbed {hotkey:?} for rule {idx}");
+                self.grabs.push((keycode, mask, idx));
+            }
+            xlib::XSync(self.display, xlib::False);
+        }
+    }
+
+    /// Whether any key is grabbed at all
+    pub fn is_empty(&self) -> bool {
+        self.grabs.is_empty()
+    }
+
+    /// The connection to poll for key presses
+    pub fn fd(&self) -> BorrowedFd<'_> {
+        let fd: RawFd = unsafe { xlib::XConnectionNumber(self.display) };
+        unsafe { BorrowedFd::borrow_raw(fd) }
+    }
+
+    /// Rules of the hotkeys pressed since the last call
+    pub fn pressed(&mut self) -> Vec<usize> {
+        let mut rules = Vec::new();
+        unsafe {
+            while xlib::XPending(self.display) > 0 {
+                let mut event: xlib::XEvent = std::mem::zeroed();
+                xlib::XNextEvent(self.display, &mut event);
+                if event.get_type() != xlib::KeyPress {
+                    continue;
+                }
+                let key = event.key;
+                let state = key.state & !(LOCKS[0] | LOCKS[1]);
+                rules.extend(
+                    self.grabs
+                        .iter()
+                        .filter(|(keycode, mask, _)| *keycode == key.keycode && *mask == state)
+                        .map(|(_, _, idx)| *idx),
+                );
+            }
+        }
+        rules
+    }
+}
+
+impl Drop for Hotkeys {
+    fn drop(&mut self) {
+        unsafe { xlib::XCloseDisplay(self.display) };
+    }
+}
+
+unsafe extern "C" fn log_error(_: *mut Display, event: *mut xlib::XErrorEvent) -> c_int {
+    let event = &*event;
+    if event.error_code == xlib::BadAccess {
+        log::warn!("A hotkey is grabbed by another program already");
+    } else {
+        log::warn!(
+            "X request {} failed with error {}",
+            event.request_code,
+            event.error_code
+        );
+    }
+    0
+}
@@ -1,11 +1,28 @@
-use parking_lot::RwLock;
+use log::LevelFilter;
+use parking_lot::{Mutex, RwLock};
 use std::env;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Write};
 use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
+use std::time::Duration;
 
-use crate::config::Config;
+use crate::config::{Config, ConfigError};
+use crate::event_handler::{EventHandler, GestureInput};
+use crate::stats::Stats;
+use crate::xdo_handler::{start_for_config, XDoHandler};
+
+/// Handler for the gestures of `inject`, kept for the lifetime of the daemon so that the delayed
+/// release of an injected drag isn't cancelled when the connection closes
+type Injector = Arc<Mutex<Option<(EventHandler, XDoHandler)>>>;
+
+/// Attempts to bind the socket before giving up on IPC, the runtime directory may not exist yet
+/// early during boot
+const BIND_ATTEMPTS: u32 = 6;
+/// Delay before the second attempt, doubled after every further failure
+const BIND_BACKOFF: Duration = Duration::from_millis(250);
 
 struct IpcListener(UnixListener);
 
@@ -17,13 +34,28 @@ impl Drop for IpcListener {
     }
 }
 
-pub fn create_socket(config: Arc<RwLock<Config>>) {
+/// `$XDG_RUNTIME_DIR/gestures.sock`, falling back to `/tmp`
+pub fn socket_path() -> String {
     let socket_dir = env::var("XDG_RUNTIME_DIR").unwrap_or("/tmp".to_string());
-    let socket_path = format!("{}/gestures.sock", socket_dir);
-    if std::path::Path::new(&socket_path).exists() {
-        std::fs::remove_file(&socket_path).expect("Could not remove existing socket file");
-    }
-    let listener = UnixListener::bind(&socket_path).unwrap();
+    format!("{}/gestures.sock", socket_dir)
+}
+
+/// Whether another daemon is listening on the socket, a leftover socket file doesn't count
+pub fn daemon_running() -> bool {
+    UnixStream::connect(socket_path()).is_ok()
+}
+
+pub fn create_socket(
+    config: Arc<RwLock<Config>>,
+    stats: Arc<RwLock<Stats>>,
+    paused: Arc<AtomicBool>,
+    is_wayland: bool,
+) {
+    let socket_path = socket_path();
+    let Some(listener) = bind(&socket_path) else {
+        log::error!("Giving up on {socket_path}, IPC commands and reloads through it won't work");
+        return;
+    };
 
     {
         // let listener = listener.clone();
@@ -34,12 +66,19 @@ pub fn create_socket(config: Arc<RwLock<Config>>) {
         .unwrap();
     }
 
+    let injector: Injector = Default::default();
+
     // for stream in listener.read().unwrap().0.incoming() {
-    for stream in listener.incoming() {
+    for stream in listener.0.incoming() {
         match stream {
             Ok(stream) => {
                 let config = config.clone();
-                thread::spawn(|| handle_connection(stream, config));
+                let stats = stats.clone();
+                let paused = paused.clone();
+                let injector = injector.clone();
+                thread::spawn(move || {
+                    handle_connection(stream, config, stats, paused, injector, is_wayland)
+                });
             }
             Err(err) => {
                 eprintln!("Got error while handling IPC connection: {err}");
@@ -49,16 +88,158 @@ pub fn create_socket(config: Arc<RwLock<Config>>) {
     }
 }
 
-fn handle_connection(stream: UnixStream, config: Arc<RwLock<Config>>) {
+/// Bind the socket at `path`, retrying with backoff while it fails
+fn bind(path: &str) -> Option<IpcListener> {
+    let mut backoff = BIND_BACKOFF;
+    for attempt in 1..=BIND_ATTEMPTS {
+        if std::path::Path::new(path).exists() {
+            if let Err(e) = std::fs::remove_file(path) {
+                log::warn!("Could not remove existing socket file {path}: {e}");
+            }
+        }
+        match UnixListener::bind(path) {
+            Ok(listener) => return Some(IpcListener(listener)),
+            Err(e) if attempt < BIND_ATTEMPTS => {
+                log::warn!("Could not bind {path}: {e}, retrying in {backoff:?}");
+                thread::sleep(backoff);
+                backoff *= 2;
+            }
+            Err(e) => log::error!("Could not bind {path}: {e}"),
+        }
+    }
+    None
+}
+
+pub fn handle_connection(
+    stream: UnixStream,
+    config: Arc<RwLock<Config>>,
+    stats: Arc<RwLock<Stats>>,
+    paused: Arc<AtomicBool>,
+    injector: Injector,
+    is_wayland: bool,
+) {
+    let mut writer = stream.try_clone().expect("Could not clone IPC stream");
     let stream = BufReader::new(stream);
 
     for line in stream.lines() {
-        if line.unwrap().contains("reload") {
-            let mut c = config.write();
-            *c = Config::read_default_config().unwrap_or_else(|_| {
-                log::error!("Could not read configuration file, using empty config!");
-                Config::default()
-            });
+        let line = line.unwrap();
+        match line.trim() {
+            "stats" => {
+                let report = stats.read().report(&config.read());
+                let _ = writer.write_all(report.as_bytes());
+            }
+            "stats json" => {
+                let report = stats.read().report_json(&config.read());
+                let _ = writer.write_all(format!("{report}\n").as_bytes());
+            }
+            "stats reset" => stats.write().reset(),
+            "stats latency" => {
+                let report = stats.read().latency_report();
+                let _ = writer.write_all(report.as_bytes());
+            }
+            "stats latency json" => {
+                let report = stats.read().latency_json();
+                let _ = writer.write_all(format!("{report}\n").as_bytes());
+            }
+            "pause" => {
+                log::info!("Pausing gestures");
+                paused.store(true, Ordering::Relaxed);
+            }
+            "resume" => {
+                log::info!("Resuming gestures");
+                paused.store(false, Ordering::Relaxed);
+            }
+            "status" => {
+                let status = if paused.load(Ordering::Relaxed) {
+                    "paused\n"
+                } else {
+                    "running\n"
+                };
+                let _ = writer.write_all(status.as_bytes());
+            }
+            "log-level" => {
+                let _ = writer.write_all(format!("{}\n", log::max_level()).as_bytes());
+            }
+            l if l.starts_with("log-level ") => {
+                let reply = match l["log-level ".len()..].trim().parse::<LevelFilter>() {
+                    Ok(level) => {
+                        log::set_max_level(level);
+                        log::info!("Log level set to {level}");
+                        format!("{level}\n")
+                    }
+                    Err(e) => format!("{e}\n"),
+                };
+                let _ = writer.write_all(reply.as_bytes());
+            }
+            l if l.starts_with("inject ") => {
+                if paused.load(Ordering::Relaxed) {
+                    log::info!("Not injecting {l:?}, gestures are paused");
+                    let _ = writer.write_all(b"gestures are paused\n");
+                    continue;
+                }
+                let mut injector = injector.lock();
+                if injector.is_none() {
+                    match start_for_config(&config.read(), !is_wayland) {
+                        Ok(xdoh) => {
+                            let mut eh =
+                                EventHandler::new(config.clone(), stats.clone(), paused.clone());
+                            eh.set_injected();
+                            *injector = Some((eh, xdoh));
+                        }
+                        Err(e) => {
+                            let _ = writer.write_all(format!("{e}\n").as_bytes());
+                            continue;
+                        }
+                    }
+                }
+                let (eh, xdoh) = injector.as_mut().expect("injector was just set");
+                let result = l["inject ".len()..]
+                    .parse::<GestureInput>()
+                    .and_then(|event| eh.dispatch(event, xdoh))
+                    // Nothing wakes the injector up later to run the actions of a single swipe
+                    .and_then(|()| eh.settle(xdoh));
+                if let Err(e) = result {
+                    log::warn!("Could not inject {l:?}: {e}");
+                    let _ = writer.write_all(format!("{e}\n").as_bytes());
+                }
+            }
+            l if l.contains("reload") => {
+                let reply = match reload_config(&config) {
+                    Ok(()) => "ok\n".to_string(),
+                    Err(e) => format!("error: {e}\n"),
+                };
+                let _ = writer.write_all(reply.as_bytes());
+            }
+            l => log::warn!("Unknown IPC command {l:?}"),
         }
     }
 }
+
+/// Swap in the config read again from where the running one came from, used by the IPC `reload`
+/// command and SIGHUP. Only the rules change, the handler keeps its libxdo connection and any
+/// button a drag holds down. When the config can't be read the running one is kept and the error
+/// returned.
+pub fn reload_config(config: &RwLock<Config>) -> Result<(), ConfigError> {
+    let file = config.read().file.clone();
+    let fresh = match file {
+        Some(file) if file == Path::new("-") => Err(ConfigError::Stdin),
+        Some(file) => Config::read_from_file(&file),
+        None => Config::read_default_config(),
+    };
+    swap_config(config, fresh)
+}
+
+/// Swap in `fresh` as [`reload_config`] does, unless it could not be read
+pub fn swap_config(
+    config: &RwLock<Config>,
+    fresh: Result<Config, ConfigError>,
+) -> Result<(), ConfigError> {
+    let mut fresh = fresh.inspect_err(|e| {
+        log::error!("Could not read configuration file, keeping the running config: {e}");
+    })?;
+    let mut c = config.write();
+    fresh.reloads = c.reloads.wrapping_add(1);
+    fresh.file = c.file.take();
+    *c = fresh;
+    Ok(())
+}
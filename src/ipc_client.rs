@@ -1,20 +1,151 @@
-use std::env;
-use std::io::Write;
+use miette::{bail, IntoDiagnostic, Result};
+use std::io::{ErrorKind, Read, Write};
+use std::net::Shutdown;
 use std::os::unix::net::UnixStream;
+use std::time::Duration;
 
-use crate::Commands;
+use crate::event_handler::GestureInput;
+use crate::gestures::pinch::PinchDir;
+use crate::ipc::socket_path;
+use crate::{Commands, InjectGesture};
 
-pub fn handle_command(cmd: Commands) {
-    let socket_dir = env::var("XDG_RUNTIME_DIR").unwrap_or("/tmp".to_string());
-    let mut stream = match UnixStream::connect(format!("{socket_dir}/gestures.sock")) {
+/// Number of updates of an injected swipe or pinch
+const INJECT_STEPS: u32 = 10;
+
+pub fn handle_command(cmd: Commands) -> Result<()> {
+    let mut stream = match UnixStream::connect(socket_path()) {
         Ok(s) => s,
         Err(e) => panic!("Got this while trying to connect to ipc: {e} \nPerhaps the main program is not running"),
     };
-    #[allow(clippy::single_match)]
     match cmd {
-        Commands::Reload => {
-            stream.write_all(b"reload").map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+        Commands::Reload { timeout } => {
+            stream.write_all(b"reload\n").map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let timeout = Duration::from_secs(timeout);
+            stream.set_read_timeout(Some(timeout)).into_diagnostic()?;
+            let mut response = String::new();
+            match stream.read_to_string(&mut response) {
+                Ok(_) => (),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    bail!("The daemon did not confirm the reload within {timeout:?}")
+                }
+                Err(e) => bail!("Could not read the daemon's reply: {e}"),
+            }
+            match response.trim() {
+                "ok" => (),
+                // Daemons from before the reply was added close the connection without one
+                "" => bail!("The daemon did not confirm the reload"),
+                reply => bail!("Reload failed, {}", reply.trim_start_matches("error: ")),
+            }
+        }
+        Commands::Stats { reset, json, latency } => {
+            let msg: &[u8] = match (reset, json, latency) {
+                (true, _, _) => b"stats reset\n",
+                (false, true, true) => b"stats latency json\n",
+                (false, false, true) => b"stats latency\n",
+                (false, true, false) => b"stats json\n",
+                (false, false, false) => b"stats\n",
+            };
+            stream.write_all(msg).map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+            // Closing our end lets the daemon finish the connection once it has replied
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            print!("{response}");
+        }
+        Commands::Pause => {
+            stream.write_all(b"pause\n").map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+        }
+        Commands::Resume => {
+            stream.write_all(b"resume\n").map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+        }
+        Commands::Status { json } => {
+            stream.write_all(b"status\n").map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            if json {
+                let status = serde_json::json!({ "status": response.trim() });
+                println!("{status}");
+            } else {
+                print!("{response}");
+            }
+        }
+        Commands::LogLevel { level } => {
+            let msg = match level {
+                Some(level) => format!("log-level {level}\n"),
+                None => "log-level\n".to_string(),
+            };
+            stream.write_all(msg.as_bytes()).map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            print!("{response}");
+        }
+        Commands::Inject { gesture } => {
+            let msg: String = synthetic_events(&gesture)
+                .iter()
+                .map(|event| format!("inject {event}\n"))
+                .collect();
+            stream.write_all(msg.as_bytes()).map_err(|e| panic!("Failed to write to socket: {e}")).unwrap();
+            stream.shutdown(Shutdown::Write).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            eprint!("{response}");
         }
         _ => (),
     }
+    Ok(())
+}
+
+/// The events of a gesture moving steadily in its direction: 100 units for a swipe, to half or
+/// twice the size for a pinch and 20 degrees for a rotation
+pub fn synthetic_events(gesture: &InjectGesture) -> Vec<GestureInput> {
+    let steps = 1..=INJECT_STEPS;
+    let progress = |step: u32| f64::from(step) / f64::from(INJECT_STEPS);
+    let (begin, updates, end) = match gesture {
+        InjectGesture::Swipe { fingers, dir } => {
+            let (x, y) = dir.vector().unwrap_or_default();
+            let step = 100.0 / f64::from(INJECT_STEPS);
+            (
+                GestureInput::SwipeBegin { fingers: *fingers },
+                steps
+                    .map(|_| GestureInput::SwipeUpdate {
+                        dx: x * step,
+                        dy: y * step,
+                    })
+                    .collect(),
+                GestureInput::SwipeEnd {
+                    fingers: None,
+                    cancelled: false,
+                },
+            )
+        }
+        InjectGesture::Pinch { fingers, dir } => {
+            let update = |step| {
+                let (scale, angle_delta) = match dir {
+                    PinchDir::In => (1.0 - 0.5 * progress(step), 0.0),
+                    PinchDir::Out => (1.0 + progress(step), 0.0),
+                    PinchDir::Clockwise => (1.0, 2.0),
+                    PinchDir::CounterClockwise => (1.0, -2.0),
+                    PinchDir::Any => (1.0, 0.0),
+                };
+                GestureInput::PinchUpdate { scale, angle_delta }
+            };
+            (
+                GestureInput::PinchBegin { fingers: *fingers },
+                steps.map(update).collect(),
+                GestureInput::PinchEnd { cancelled: false },
+            )
+        }
+        InjectGesture::Hold { fingers } => (
+            GestureInput::HoldBegin { fingers: *fingers },
+            Vec::new(),
+            GestureInput::HoldEnd { cancelled: false },
+        ),
+    };
+    let mut events = vec![begin];
+    events.extend(updates);
+    events.push(end);
+    events
 }
@@ -0,0 +1,154 @@
+use crate::config::Config;
+use crate::gestures::{hold::Region, Fingers, Gesture};
+
+/// Something about a rule that is probably not what the config means, by the rules' positions
+#[derive(Debug, PartialEq)]
+pub enum Lint {
+    /// A fallback rule that never fires, because the other rule `by` matches every gesture it does
+    Shadowed { rule: usize, by: usize },
+    /// Two rules that both fire on some of the same gestures
+    Overlap { rule: usize, with: usize },
+}
+
+impl Lint {
+    pub fn message(&self, gestures: &[Gesture]) -> String {
+        let describe = |idx: usize| describe(idx, &gestures[idx]);
+        match *self {
+            Lint::Shadowed { rule, by } => format!(
+                "{} never fires: it is a fallback and {} matches every gesture it does",
+                describe(rule),
+                describe(by)
+            ),
+            Lint::Overlap { rule, with } => format!(
+                "{} and {} both fire on the same gestures",
+                describe(with),
+                describe(rule)
+            ),
+        }
+    }
+}
+
+/// e.g. `rule 3 (swipe n, 3 fingers)`
+fn describe(idx: usize, gesture: &Gesture) -> String {
+    let direction = match gesture.direction() {
+        dir if dir.is_empty() => String::new(),
+        dir => format!(" {dir}"),
+    };
+    let fingers = gesture.fingers().map(|f| f.to_string()).unwrap_or_default();
+    format!(
+        "rule {idx} ({}{direction}, {fingers} fingers)",
+        gesture.kind()
+    )
+}
+
+/// Find the fallback rules that can never fire and the rules that fire together. Every rule that
+/// matches a gesture fires, so two of them matching the same gestures both fire, and fallback
+/// rules only fire when no other rule matched. Rules with an `angle` and drag rules aren't
+/// compared.
+pub fn lint(config: &Config) -> Vec<Lint> {
+    let tolerance = config.finger_tolerance.unwrap_or_default();
+    let rules: Vec<(usize, &Gesture)> = config
+        .gestures
+        .iter()
+        .enumerate()
+        .filter(|(_, g)| g.fingers().is_some() && !has_angle(g) && !drags(g))
+        .collect();
+    let mut lints = Vec::new();
+    for (i, &(rule, gesture)) in rules.iter().enumerate() {
+        if gesture.fallback() {
+            let by = rules.iter().find(|(_, other)| {
+                !other.fallback() && other.kind() == gesture.kind() && covers(other, gesture)
+            });
+            if let Some(&(by, _)) = by {
+                lints.push(Lint::Shadowed { rule, by });
+                continue;
+            }
+        }
+        let with = rules[..i].iter().find(|(_, other)| {
+            other.fallback() == gesture.fallback()
+                && other.kind() == gesture.kind()
+                && overlaps(other, gesture, tolerance)
+        });
+        if let Some(&(with, _)) = with {
+            lints.push(Lint::Overlap { rule, with });
+        }
+    }
+    lints
+}
+
+fn has_angle(gesture: &Gesture) -> bool {
+    matches!(gesture, Gesture::Swipe(s) if s.angle.is_some())
+}
+
+/// Whether the rule drags the pointer, or waits for a threshold, delay or speed before pressing
+/// the button. Whether it does anything depends on the session and on how the swipe moves.
+fn drags(gesture: &Gesture) -> bool {
+    matches!(gesture, Gesture::Swipe(s) if s.is_drag()
+        || s.drag_threshold.is_some()
+        || s.drag_delay.is_some()
+        || s.grab_speed.is_some())
+}
+
+/// Whether some gesture matches both `a` and `b`
+fn overlaps(a: &Gesture, b: &Gesture, tolerance: u32) -> bool {
+    let (Some(fa), Some(fb)) = (a.fingers(), b.fingers()) else {
+        return false;
+    };
+    // With a tolerance each rule matches a range of finger counts
+    let fingers = fa == Fingers::ANY || fb == Fingers::ANY || fa.0.abs_diff(fb.0) <= 2 * tolerance;
+    fingers
+        && (a.direction() == "any" || b.direction() == "any" || a.direction() == b.direction())
+        && region(a).is_none_or(|r| region(b).is_none_or(|other| r == other))
+        && compatible(a.when_power(), b.when_power())
+        && compatible(a.when_tablet_mode(), b.when_tablet_mode())
+        && compatible(previous(a), previous(b))
+}
+
+/// Whether `a` matches every gesture that `b` matches
+fn covers(a: &Gesture, b: &Gesture) -> bool {
+    let (Some(fa), Some(fb)) = (a.fingers(), b.fingers()) else {
+        return false;
+    };
+    (fa == Fingers::ANY || fa == fb)
+        && (a.direction() == "any" || a.direction() == b.direction())
+        && region(a).is_none_or(|r| region(b) == Some(r))
+        && !narrowed(a)
+        && a.when_power().is_none_or(|p| b.when_power() == Some(p))
+        && a.when_tablet_mode()
+            .is_none_or(|t| b.when_tablet_mode() == Some(t))
+        && a.requires_previous().is_none()
+}
+
+/// Whether two conditions of rules can hold at once, a rule without one always matches
+fn compatible<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+    a.is_none() || b.is_none() || a == b
+}
+
+/// `id` of the rule that `requires-previous` names
+fn previous(gesture: &Gesture) -> Option<&str> {
+    gesture.requires_previous().map(|(id, _)| id)
+}
+
+fn region(gesture: &Gesture) -> Option<Region> {
+    match gesture {
+        Gesture::Hold(h) => h.region,
+        _ => None,
+    }
+}
+
+/// Whether the rule has settings beyond fingers, direction and conditions that keep it from
+/// matching some of those gestures
+fn narrowed(gesture: &Gesture) -> bool {
+    match gesture {
+        Gesture::Swipe(s) => {
+            s.exclude_directions.is_some()
+                || s.min_coherence.is_some()
+                || s.end_fingers.is_some()
+                || s.max_duration.is_some()
+                || s.direction_debounce.is_some()
+                || s.return_radius.is_some()
+        }
+        Gesture::Pinch(p) => p.scale_deadzone.is_some() || p.max_duration.is_some(),
+        Gesture::Hold(_) | Gesture::Group(_) | Gesture::None => false,
+    }
+}
@@ -1,9 +1,21 @@
+mod autostart;
+mod bench;
 mod config;
+mod dbus;
 mod event_handler;
 mod gestures;
+mod hotkey;
 mod ipc;
 mod ipc_client;
+mod lint;
+mod media;
+mod power;
+mod replay;
+mod screenshot;
+mod stats;
+mod tune;
 mod utils;
+mod window;
 mod xdo_handler;
 
 #[cfg(test)]
@@ -11,42 +23,62 @@ mod tests;
 
 use parking_lot::RwLock;
 use std::{
+    fs::OpenOptions,
     path::PathBuf,
-    sync::Arc,
+    sync::{atomic::AtomicBool, Arc},
     thread::{self, JoinHandle},
+    time::Duration,
 };
 
 use clap::{Parser, Subcommand};
-use env_logger::Builder;
+use env_logger::{Builder, Target};
 use log::LevelFilter;
-use miette::Result;
+use miette::{bail, IntoDiagnostic, Result, WrapErr};
+use nix::sys::signal::{SigSet, Signal};
 
 use crate::config::*;
-use crate::xdo_handler::start_handler;
+use crate::gestures::{pinch::PinchDir, swipe::SwipeDir, Gesture};
+use crate::stats::Stats;
+use crate::xdo_handler::start_for_config;
 
 fn main() -> Result<()> {
     let app = App::parse();
 
     {
-        let mut l = Builder::from_default_env();
+        let mut level = match app.verbose {
+            // Whatever RUST_LOG asks for, error only without it
+            0 => Builder::from_default_env().build().filter(),
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::max(),
+        };
 
-        if app.verbose > 0 {
-            l.filter_level(match app.verbose {
-                1 => LevelFilter::Info,
-                2 => LevelFilter::Debug,
-                _ => LevelFilter::max(),
-            });
+        if app.debug {
+            level = LevelFilter::Debug;
         }
 
-        if app.debug {
-            l.filter_level(LevelFilter::Debug);
+        // The logger lets everything through, `log-level` adjusts the global maximum at runtime
+        let mut l = Builder::from_default_env();
+        l.filter_level(LevelFilter::max());
+
+        if let Some(path) = &app.log_file {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Could not open log file {}", path.display()))?;
+            l.target(Target::Pipe(Box::new(file)));
         }
 
         l.init();
+        log::set_max_level(level);
     }
 
-    let c = if let Some(p) = app.conf {
-        Config::read_from_file(&p)?
+    let c = if let Some(p) = &app.conf {
+        let mut c = Config::read_from_file(p)?;
+        c.file = Some(p.clone());
+        c
     } else {
         config::Config::read_default_config().unwrap_or_else(|_| {
             log::error!("Could not read configuration file, using empty config!");
@@ -56,29 +88,186 @@ fn main() -> Result<()> {
     log::debug!("{:#?}", &c);
 
     match app.command {
-        c @ Commands::Reload => {
-            ipc_client::handle_command(c);
+        c @ (Commands::Reload { .. }
+        | Commands::Stats { .. }
+        | Commands::Pause
+        | Commands::Resume
+        | Commands::Status { .. }
+        | Commands::LogLevel { .. }
+        | Commands::Inject { .. }) => {
+            ipc_client::handle_command(c)?;
+        }
+        Commands::Start { heartbeat } => {
+            let heartbeat = heartbeat.map(Duration::from_secs);
+            run_eh(Arc::new(RwLock::new(c)), app.wayland_disp, heartbeat)?
+        }
+        Commands::List { json: false } => list_gestures(&c),
+        Commands::List { json: true } => {
+            let gestures = c.gestures.iter().enumerate();
+            let gestures: Vec<_> = gestures.map(|(i, gesture)| gesture.listing(i)).collect();
+            println!("{}", serde_json::to_string(&gestures).into_diagnostic()?);
+        }
+        Commands::DumpConfig { json: false } => {
+            print!("{}", toml::to_string(&c).into_diagnostic()?)
+        }
+        Commands::DumpConfig { json: true } => {
+            println!("{}", serde_json::to_string_pretty(&c).into_diagnostic()?)
+        }
+        Commands::Schema => {
+            let schema = serde_json::to_string_pretty(&schemars::schema_for!(Config));
+            println!("{}", schema.into_diagnostic()?)
+        }
+        Commands::Lint => {
+            let lints = lint::lint(&c);
+            for lint in &lints {
+                println!("{}", lint.message(&c.gestures));
+            }
+            if !lints.is_empty() {
+                bail!("Found {} questionable rules", lints.len());
+            }
+        }
+        Commands::Record => {
+            // The handler is only needed to find the gesture device
+            let mut eh = event_handler::EventHandler::new(
+                Arc::new(RwLock::new(c)),
+                Default::default(),
+                Default::default(),
+            );
+            let mut interface = input::Libinput::new_with_udev(event_handler::Interface);
+            eh.init(&mut interface)?;
+            replay::record(&mut interface)?;
+        }
+        Commands::Replay { file } => {
+            replay::replay(&file, Arc::new(RwLock::new(c)), app.wayland_disp)?
+        }
+        Commands::Bench { rounds } => {
+            let (events, elapsed) = bench::bench(c, rounds)?;
+            println!(
+                "{events} events in {elapsed:.2?}, {:.0} events per second",
+                events as f64 / elapsed.as_secs_f64()
+            );
+        }
+        Commands::InstallAutostart { force } => {
+            let path = autostart::install(app.conf.as_deref(), app.wayland_disp, force)?;
+            println!("Installed {}", path.display());
         }
-        Commands::Start => run_eh(Arc::new(RwLock::new(c)), app.wayland_disp)?,
     }
 
     Ok(())
 }
 
-fn run_eh(config: Arc<RwLock<Config>>, is_wayland: bool) -> Result<()> {
-    let eh_thread = spawn_event_handler(config.clone(), is_wayland);
-    ipc::create_socket(config);
+/// Print a table of the configured gestures
+fn list_gestures(config: &Config) {
+    println!(
+        "{:<4}{:<7}{:<9}{:<19}ACTION",
+        "#", "TYPE", "FINGERS", "DIRECTION"
+    );
+    for (i, gesture) in config.gestures.iter().enumerate() {
+        let mut action = gesture
+            .actions()
+            .iter()
+            .map(|(name, cmd)| format!("{name}={cmd}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        if let Gesture::Swipe(s) = gesture {
+            if s.acceleration.is_some() && s.mouse_up_delay.is_some() {
+                action = format!("drag {action}");
+            }
+        }
+        let fingers = gesture.fingers().map(|f| f.to_string()).unwrap_or_default();
+        println!(
+            "{:<4}{:<7}{:<9}{:<19}{}",
+            i,
+            gesture.kind(),
+            fingers,
+            gesture.direction(),
+            truncate(action.trim(), 60)
+        );
+    }
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    if s.chars().count() <= max {
+        s.to_string()
+    } else {
+        format!("{}…", s.chars().take(max - 1).collect::<String>())
+    }
+}
+
+fn run_eh(
+    config: Arc<RwLock<Config>>,
+    is_wayland: bool,
+    heartbeat: Option<Duration>,
+) -> Result<()> {
+    // The socket doubles as a lock against two daemons handling the same touchpad
+    if ipc::daemon_running() {
+        bail!(
+            "gestures is already running, it answers on {}",
+            ipc::socket_path()
+        );
+    }
+    let stats = Arc::new(RwLock::new(Stats::default()));
+    let paused = Arc::new(AtomicBool::new(false));
+    spawn_sighup_handler(config.clone());
+    let eh_thread = spawn_event_handler(
+        config.clone(),
+        stats.clone(),
+        paused.clone(),
+        is_wayland,
+        heartbeat,
+    );
+    ipc::create_socket(config, stats, paused, is_wayland);
     eh_thread.join().unwrap()?;
     Ok(())
 }
 
-fn spawn_event_handler(config: Arc<RwLock<Config>>, is_wayland: bool) -> JoinHandle<Result<()>> {
+/// Reload the config on SIGHUP. Has to run before any other thread is spawned so that they all
+/// inherit the blocked signal mask.
+fn spawn_sighup_handler(config: Arc<RwLock<Config>>) {
+    let mut mask = SigSet::empty();
+    mask.add(Signal::SIGHUP);
+    if let Err(e) = mask.thread_block() {
+        log::error!("Could not block SIGHUP, reloading on SIGHUP is disabled: {e}");
+        return;
+    }
+    thread::spawn(move || loop {
+        match mask.wait() {
+            Ok(_) => {
+                log::info!("Got SIGHUP, reloading config");
+                let _ = ipc::reload_config(&config);
+            }
+            Err(e) => {
+                log::error!("Failed waiting for SIGHUP: {e}");
+                break;
+            }
+        }
+    });
+}
+
+fn spawn_event_handler(
+    config: Arc<RwLock<Config>>,
+    stats: Arc<RwLock<Stats>>,
+    paused: Arc<AtomicBool>,
+    is_wayland: bool,
+    heartbeat: Option<Duration>,
+) -> JoinHandle<Result<()>> {
     thread::spawn(move || {
         log::debug!("Starting event handler in new thread");
-        let mut eh = event_handler::EventHandler::new(config);
+        let mut xdoh = start_for_config(&config.read(), !is_wayland)?;
+        let mut eh = event_handler::EventHandler::new(config, stats, paused);
+        if !is_wayland {
+            eh.grab_hotkeys();
+        }
+        if let Some(every) = heartbeat {
+            eh.set_heartbeat(every);
+        }
         let mut interface = input::Libinput::new_with_udev(event_handler::Interface);
         eh.init(&mut interface)?;
-        let _ = eh.main_loop(&mut interface, &mut start_handler(!is_wayland));
+        if let Err(e) = eh.main_loop(&mut interface, &mut xdoh) {
+            // The main thread is busy with IPC for good, so nothing else would report this
+            log::error!("{e:?}");
+            std::process::exit(1);
+        }
         Ok(())
     })
 }
@@ -96,7 +285,10 @@ struct App {
     /// (default: Xorg, will use xdotool api directly for better 3-finger-drag performance)
     #[arg(short, long)]
     wayland_disp: bool,
-    /// Path to config file
+    /// Append log messages to this file instead of writing them to stderr
+    #[arg(long, value_name = "FILE")]
+    log_file: Option<PathBuf>,
+    /// Path to config file, `-` reads it from stdin
     #[arg(short, long, value_name = "FILE")]
     conf: Option<PathBuf>,
     #[command(subcommand)]
@@ -105,8 +297,107 @@ struct App {
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
-    /// Reload the configuration
-    Reload,
+    /// Reload the configuration, failing if the daemon could not read it
+    Reload {
+        /// Seconds to wait for the daemon to confirm the reload
+        #[arg(long, default_value_t = 5)]
+        timeout: u64,
+    },
     /// Start the program
-    Start,
+    Start {
+        /// Log a heartbeat every this many seconds, to show that the daemon is still polling
+        #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+        heartbeat: Option<u64>,
+    },
+    /// List the configured gestures without starting the program
+    List {
+        /// Print the gestures as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the configuration as the program sees it, with includes, defaults and vars applied
+    DumpConfig {
+        /// Print the configuration as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a JSON Schema of the configuration as `dump-config --json` prints it
+    Schema,
+    /// Report fallback rules that never fire and rules that fire together on the same gestures
+    Lint,
+    /// Start the program at login through an XDG autostart entry
+    InstallAutostart {
+        /// Overwrite an existing autostart entry
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show how often each configured gesture fired
+    Stats {
+        /// Reset the counters
+        #[arg(long)]
+        reset: bool,
+        /// Print the counters as JSON
+        #[arg(long, conflicts_with = "reset")]
+        json: bool,
+        /// Show how long gestures took from libinput reporting their end to running their actions
+        #[arg(long, conflicts_with = "reset")]
+        latency: bool,
+    },
+    /// Stop acting on gestures until resumed
+    Pause,
+    /// Act on gestures again after a pause
+    Resume,
+    /// Change how much the running program logs without restarting it, or show the current level
+    LogLevel {
+        /// `off`, `error`, `warn`, `info`, `debug` or `trace`
+        level: Option<LevelFilter>,
+    },
+    /// Show whether gestures are paused
+    Status {
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the touchpad's gesture events without acting on them, for `replay`
+    Record,
+    /// Act on gesture events printed by `record` as if they happened now
+    Replay {
+        /// File with the recorded events
+        file: PathBuf,
+    },
+    /// Time how fast made up gestures are matched against the configured rules, without running
+    /// their actions
+    #[command(hide = true)]
+    Bench {
+        /// How often to repeat the made up gestures
+        #[arg(long, default_value_t = 10_000)]
+        rounds: u32,
+    },
+    /// Make the running program act on a made up gesture as if the touchpad reported it
+    Inject {
+        #[command(subcommand)]
+        gesture: InjectGesture,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum InjectGesture {
+    Swipe {
+        #[arg(long)]
+        fingers: i32,
+        /// Direction of the swipe, e.g. `n` or `north-east`
+        #[arg(long, default_value = "any")]
+        dir: SwipeDir,
+    },
+    Pinch {
+        #[arg(long)]
+        fingers: i32,
+        /// `in`, `out`, `clockwise` or `counter-clockwise`
+        #[arg(long, default_value = "any")]
+        dir: PinchDir,
+    },
+    Hold {
+        #[arg(long)]
+        fingers: i32,
+    },
 }
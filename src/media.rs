@@ -0,0 +1,34 @@
+use knuffel::DecodeScalar;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Media keys a rule can press without a tool like `playerctl`
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MediaKey {
+    PlayPause,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+}
+
+impl MediaKey {
+    /// X keysym of the key
+    pub fn keysym(self) -> &'static str {
+        match self {
+            // There is no separate toggle, players treat the play key as one
+            MediaKey::PlayPause => "XF86AudioPlay",
+            MediaKey::Pause => "XF86AudioPause",
+            MediaKey::Stop => "XF86AudioStop",
+            MediaKey::Next => "XF86AudioNext",
+            MediaKey::Previous => "XF86AudioPrev",
+            MediaKey::VolumeUp => "XF86AudioRaiseVolume",
+            MediaKey::VolumeDown => "XF86AudioLowerVolume",
+            MediaKey::Mute => "XF86AudioMute",
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::path::Path;
+
+use knuffel::DecodeScalar;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Where the kernel lists batteries and power adapters
+pub const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// Whether the machine runs on mains power or its battery
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Power {
+    Ac,
+    Battery,
+}
+
+impl Power {
+    /// Read the power state from the power supplies in `dir`. A machine without batteries, or
+    /// with an adapter that is plugged in, runs on AC. `None` if `dir` can't be read.
+    pub fn read(dir: &Path) -> Option<Self> {
+        let read = |supply: &Path, file: &str| {
+            fs::read_to_string(supply.join(file))
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default()
+        };
+        let mut battery = false;
+        for entry in fs::read_dir(dir).ok()?.flatten() {
+            let supply = entry.path();
+            match read(&supply, "type").as_str() {
+                "Mains" | "USB" if read(&supply, "online") == "1" => return Some(Power::Ac),
+                // Batteries of mice and keyboards report a scope of Device
+                "Battery" if read(&supply, "scope") != "Device" => battery = true,
+                _ => (),
+            }
+        }
+        Some(if battery { Power::Battery } else { Power::Ac })
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    fmt, fs,
+    path::Path,
+    str::{FromStr, SplitWhitespace},
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use input::{event::Event, Libinput};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use nix::poll::{poll, PollFd, PollFlags, PollTimeout};
+use parking_lot::RwLock;
+use std::os::fd::AsFd;
+
+use crate::config::Config;
+use crate::event_handler::{EventHandler, GestureInput};
+use crate::stats::Stats;
+use crate::xdo_handler::start_for_config;
+
+/// Time given to the actions of the last events to start before `replay` returns
+const REPLAY_GRACE: Duration = Duration::from_millis(200);
+
+/// Print the gesture events of the touchpad, one per line prefixed with the milliseconds since the
+/// first one, in the format [`replay`] reads. No actions are run.
+pub fn record(input: &mut Libinput) -> Result<()> {
+    let mut start = None;
+    loop {
+        let mut fds = [PollFd::new(input.as_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, PollTimeout::NONE) {
+            Ok(_) => {}
+            Err(nix::errno::Errno::EINTR) => continue,
+            Err(e) => return Err(miette!("Poll error: {}", e)),
+        }
+        input
+            .dispatch()
+            .map_err(|e| miette!("Failed to dispatch input events: {}", e))?;
+        for event in &mut *input {
+            if let Event::Gesture(e) = event {
+                if let Some(e) = GestureInput::from_libinput(e) {
+                    let start = *start.get_or_insert_with(Instant::now);
+                    println!("{} {e}", start.elapsed().as_millis());
+                }
+            }
+        }
+    }
+}
+
+/// Feed the events recorded in `file` through the handler with their original timing
+pub fn replay(file: &Path, config: Arc<RwLock<Config>>, is_wayland: bool) -> Result<()> {
+    let text = fs::read_to_string(file)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Could not read {}", file.display()))?;
+    let stats = Arc::new(RwLock::new(Stats::default()));
+    let mut xdoh = start_for_config(&config.read(), !is_wayland)?;
+    let mut eh = EventHandler::new(config, stats, Arc::new(AtomicBool::new(false)));
+    let start = Instant::now();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (time, event) = line
+            .split_once(' ')
+            .ok_or_else(|| miette!("{}:{}: expected a time and an event", file.display(), n + 1))?;
+        let time: u64 = time
+            .parse()
+            .into_diagnostic()
+            .wrap_err_with(|| format!("{}:{}: invalid time {time:?}", file.display(), n + 1))?;
+        let event: GestureInput = event
+            .parse()
+            .wrap_err_with(|| format!("{}:{}", file.display(), n + 1))?;
+        if let Some(wait) = Duration::from_millis(time).checked_sub(start.elapsed()) {
+            thread::sleep(wait);
+        }
+        log::debug!("Replaying {event}");
+        eh.flush_pending(&mut xdoh)?;
+        eh.dispatch(event, &mut xdoh)?;
+    }
+    eh.settle(&mut xdoh)?;
+    thread::sleep(REPLAY_GRACE);
+    Ok(())
+}
+
+impl fmt::Display for GestureInput {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cancelled = |cancelled: bool| if cancelled { " cancelled" } else { "" };
+        match self {
+            Self::SwipeBegin { fingers } => write!(f, "swipe-begin {fingers}"),
+            Self::SwipeUpdate { dx, dy } => write!(f, "swipe-update {dx} {dy}"),
+            Self::SwipeEnd {
+                fingers,
+                cancelled: c,
+            } => {
+                write!(f, "swipe-end")?;
+                if let Some(fingers) = fingers {
+                    write!(f, " {fingers}")?;
+                }
+                write!(f, "{}", cancelled(*c))
+            }
+            Self::PinchBegin { fingers } => write!(f, "pinch-begin {fingers}"),
+            Self::PinchUpdate { scale, angle_delta } => {
+                write!(f, "pinch-update {scale} {angle_delta}")
+            }
+            Self::PinchEnd { cancelled: c } => write!(f, "pinch-end{}", cancelled(*c)),
+            Self::HoldBegin { fingers } => write!(f, "hold-begin {fingers}"),
+            Self::HoldEnd { cancelled: c } => write!(f, "hold-end{}", cancelled(*c)),
+            Self::Hotkey { rule } => write!(f, "hotkey {rule}"),
+        }
+    }
+}
+
+impl FromStr for GestureInput {
+    type Err = miette::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut words = s.split_whitespace();
+        let kind = words.next().ok_or_else(|| miette!("Missing event"))?;
+        let event = match kind {
+            "swipe-begin" => Self::SwipeBegin {
+                fingers: arg(&mut words, "fingers")?,
+            },
+            "swipe-update" => Self::SwipeUpdate {
+                dx: arg(&mut words, "dx")?,
+                dy: arg(&mut words, "dy")?,
+            },
+            "swipe-end" => {
+                // Recordings from before the end finger count was kept don't have it
+                let fingers = words.clone().next().and_then(|w| w.parse().ok());
+                if fingers.is_some() {
+                    words.next();
+                }
+                Self::SwipeEnd {
+                    fingers,
+                    cancelled: cancelled(&mut words)?,
+                }
+            }
+            "pinch-begin" => Self::PinchBegin {
+                fingers: arg(&mut words, "fingers")?,
+            },
+            "pinch-update" => Self::PinchUpdate {
+                scale: arg(&mut words, "scale")?,
+                angle_delta: arg(&mut words, "angle delta")?,
+            },
+            "pinch-end" => Self::PinchEnd {
+                cancelled: cancelled(&mut words)?,
+            },
+            "hold-begin" => Self::HoldBegin {
+                fingers: arg(&mut words, "fingers")?,
+            },
+            "hold-end" => Self::HoldEnd {
+                cancelled: cancelled(&mut words)?,
+            },
+            "hotkey" => Self::Hotkey {
+                rule: arg(&mut words, "rule")?,
+            },
+            _ => return Err(miette!("Unknown event {kind:?}")),
+        };
+        match words.next() {
+            Some(extra) => Err(miette!("Unexpected {extra:?} after {kind}")),
+            None => Ok(event),
+        }
+    }
+}
+
+fn arg<T: FromStr>(words: &mut SplitWhitespace, name: &str) -> Result<T> {
+    let word = words.next().ok_or_else(|| miette!("Missing {name}"))?;
+    word.parse().map_err(|_| miette!("Invalid {name} {word:?}"))
+}
+
+fn cancelled(words: &mut SplitWhitespace) -> Result<bool> {
+    match words.next() {
+        None => Ok(false),
+        Some("cancelled") => Ok(true),
+        Some(word) => Err(miette!("Expected \"cancelled\", got {word:?}")),
+    }
+}
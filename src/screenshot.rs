@@ -0,0 +1,97 @@
+use std::fmt;
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use knuffel::DecodeScalar;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::utils::{capture_output, run_command, Runner};
+use crate::xdo_handler::XDoHandler;
+
+/// Part of the screen a rule takes a screenshot of
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Screenshot {
+    /// The screen the pointer is on
+    Full,
+    /// The active window
+    Window,
+    /// A rectangle drawn with the mouse through `slop`
+    Region,
+}
+
+/// Screenshot tool used without `screenshot-command`
+pub const DEFAULT_SCREENSHOT_COMMAND: &str =
+    "maim -g \"$1\" ~/Pictures/screenshot-$(date +%Y%m%d-%H%M%S).png";
+
+/// How long `slop` waits for a region to be drawn
+const REGION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An area of the screen, displayed as an X geometry like `800x600+10+20`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}+{}+{}", self.width, self.height, self.x, self.y)
+    }
+}
+
+/// The shell running the screenshot `template` with `geometry` as `$1`
+pub fn command(template: &str, geometry: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(template).arg("sh").arg(geometry);
+    cmd
+}
+
+impl Screenshot {
+    /// Whether libxdo finds the area
+    pub fn needs_xdo(self) -> bool {
+        self != Screenshot::Region
+    }
+
+    /// Run the screenshot command for the area in the background. The full screen and the window
+    /// are looked up through libxdo right away, a region once it has been drawn. Returns the
+    /// thread taking it, unless the area could not be found.
+    pub fn take(
+        self,
+        template: Option<&str>,
+        runner: &Runner,
+        xdoh: &XDoHandler,
+    ) -> Option<JoinHandle<()>> {
+        let template = template.unwrap_or(DEFAULT_SCREENSHOT_COMMAND).to_string();
+        let geometry = match self {
+            Screenshot::Full => xdoh.screen_geometry(),
+            Screenshot::Window => xdoh.window_geometry(),
+            Screenshot::Region => None,
+        };
+        if self.needs_xdo() && geometry.is_none() {
+            log::warn!("Could not find the area of the {self:?} screenshot");
+            return None;
+        }
+        let runner = runner.clone();
+        Some(thread::spawn(move || {
+            let geometry = match geometry {
+                Some(geometry) => geometry.to_string(),
+                None => {
+                    let mut slop = Command::new("slop");
+                    slop.args(["-f", "%g"]);
+                    match capture_output(slop, &runner, REGION_TIMEOUT) {
+                        Some(region) if !region.trim().is_empty() => region.trim().to_string(),
+                        // Cancelled with a key press or right click
+                        _ => return,
+                    }
+                }
+            };
+            log::debug!("Taking a screenshot of {geometry} with {template:?}");
+            run_command(command(&template, &geometry), &runner);
+        }))
+    }
+}
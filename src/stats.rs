@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Config;
+
+/// How often each configured rule fired, keyed by its index in the config
+#[derive(Debug, Default)]
+pub struct Stats {
+    rules: BTreeMap<usize, RuleStats>,
+    /// The rule that fired most recently and when, for `requires-previous`
+    last: Option<(usize, Instant)>,
+    latency: Latency,
+}
+
+/// Time from libinput reporting the end of a gesture until the handler dispatched its actions
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Latency {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl Latency {
+    pub fn average(&self) -> Duration {
+        self.total
+            .checked_div(self.count.try_into().unwrap_or(u32::MAX))
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleStats {
+    pub count: u64,
+    pub last_fired: DateTime<Local>,
+}
+
+impl Stats {
+    pub fn record(&mut self, rule: usize) {
+        self.last = Some((rule, Instant::now()));
+        let now = Local::now();
+        self.rules
+            .entry(rule)
+            .and_modify(|r| {
+                r.count += 1;
+                r.last_fired = now;
+            })
+            .or_insert(RuleStats {
+                count: 1,
+                last_fired: now,
+            });
+    }
+
+    /// How often `rule` fired
+    pub fn count(&self, rule: usize) -> u64 {
+        self.rules.get(&rule).map_or(0, |r| r.count)
+    }
+
+    pub fn last_fired(&self) -> Option<(usize, Instant)> {
+        self.last
+    }
+
+    pub fn record_latency(&mut self, latency: Duration) {
+        self.latency.count += 1;
+        self.latency.total += latency;
+        self.latency.max = self.latency.max.max(latency);
+    }
+
+    pub fn reset(&mut self) {
+        self.rules.clear();
+        self.last = None;
+        self.latency = Latency::default();
+    }
+
+    /// Summary of the recorded latencies for the `stats latency` IPC command
+    pub fn latency_report(&self) -> String {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        format!(
+            "{} gestures, {:.2} ms on average, {:.2} ms at most\n",
+            self.latency.count,
+            ms(self.latency.average()),
+            ms(self.latency.max)
+        )
+    }
+
+    /// The same as [`Stats::latency_report`] as a JSON object
+    pub fn latency_json(&self) -> Value {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        serde_json::to_value(LatencyReport {
+            count: self.latency.count,
+            average_ms: ms(self.latency.average()),
+            max_ms: ms(self.latency.max),
+        })
+        .unwrap()
+    }
+
+    /// Render one line per configured rule for the `stats` IPC command
+    pub fn report(&self, config: &Config) -> String {
+        let mut out = String::new();
+        for (i, gesture) in config.gestures.iter().enumerate() {
+            let line = match self.rules.get(&i) {
+                Some(r) => format!(
+                    "{i}\t{}\t{}\t{}\n",
+                    gesture.kind(),
+                    r.count,
+                    r.last_fired.format("%Y-%m-%d %H:%M:%S")
+                ),
+                None => format!("{i}\t{}\t0\tnever\n", gesture.kind()),
+            };
+            out.push_str(&line);
+        }
+        out
+    }
+
+    /// The same as [`Stats::report`] as a JSON array, `last_fired` is RFC 3339 or null
+    pub fn report_json(&self, config: &Config) -> Value {
+        let rules = config.gestures.iter().enumerate();
+        let rules: Vec<_> = rules
+            .map(|(i, gesture)| RuleReport {
+                index: i,
+                kind: gesture.kind(),
+                count: self.count(i),
+                last_fired: self.rules.get(&i).map(|r| r.last_fired),
+            })
+            .collect();
+        serde_json::to_value(rules).unwrap()
+    }
+}
+
+/// A line of [`Stats::report_json`]
+#[derive(Serialize)]
+struct RuleReport {
+    index: usize,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    count: u64,
+    last_fired: Option<DateTime<Local>>,
+}
+
+/// The object of [`Stats::latency_json`]
+#[derive(Serialize)]
+struct LatencyReport {
+    count: u64,
+    average_ms: f64,
+    max_ms: f64,
+}
@@ -1,5 +1,114 @@
-use crate::config::Config;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use input::ffi;
+use log::Level;
+use parking_lot::RwLock;
+
+use crate::autostart::quote;
+use crate::bench::{bench, without_actions};
+use crate::config::{AccelProfile, Config, ConfigError, LibinputLog, Modifier, CONFIG_ENV};
+use crate::event_handler::{EventHandler, GestureInput};
+use crate::gestures::hold::Region;
+use crate::gestures::pinch::{PinchDir, PinchKind, ScaleMode};
 use crate::gestures::swipe::SwipeDir;
+use crate::gestures::{in_fallback_order, Click, Fingers, Gesture};
+use crate::hotkey;
+use crate::ipc::{handle_connection, reload_config, swap_config};
+use crate::ipc_client::synthetic_events;
+use crate::lint::{lint, Lint};
+use crate::media::MediaKey;
+use crate::power::Power;
+use crate::screenshot::{self, Geometry, Screenshot};
+use crate::stats::Stats;
+use crate::tune::Tune;
+use crate::utils::{
+    missing_command_fallback, missing_program, set_user, substitute_progress, substitute_value,
+    Runner,
+};
+use crate::window::WindowAction;
+use crate::xdo_handler::{
+    start_for_config, start_handler, start_recording, XDoCommand, XDoHandler,
+};
+use crate::{App, InjectGesture};
+
+/// An event handler for a test config, recording into stats of its own without Xorg
+struct Handler {
+    eh: EventHandler,
+    xdoh: XDoHandler,
+    config: Arc<RwLock<Config>>,
+    stats: Arc<RwLock<Stats>>,
+    paused: Arc<AtomicBool>,
+}
+
+impl Handler {
+    fn new(config: Config) -> Self {
+        Self::with_xdo(config, start_handler(false).unwrap())
+    }
+
+    fn with_xdo(config: Config, xdoh: XDoHandler) -> Self {
+        let config = Arc::new(RwLock::new(config));
+        let stats = Arc::new(RwLock::new(Stats::default()));
+        let paused = Arc::new(AtomicBool::new(false));
+        Self {
+            eh: EventHandler::new(config.clone(), stats.clone(), paused.clone()),
+            xdoh,
+            config,
+            stats,
+            paused,
+        }
+    }
+
+    fn dispatch(&mut self, events: impl IntoIterator<Item = GestureInput>) {
+        for event in events {
+            self.eh.dispatch(event, &mut self.xdoh).unwrap();
+        }
+    }
+
+    fn inject(&mut self, gestures: &[InjectGesture]) {
+        for gesture in gestures {
+            self.dispatch(synthetic_events(gesture));
+        }
+    }
+
+    /// How often each rule fired, in config order
+    fn counts(&self) -> Vec<u64> {
+        let stats = self.stats.read();
+        (0..self.config.read().gestures.len())
+            .map(|i| stats.count(i))
+            .collect()
+    }
+}
+
+/// Inject `gestures` into a handler for `config` and return what fired
+fn run_gestures(config: Config, gestures: &[InjectGesture]) -> Stats {
+    let mut handler = Handler::new(config);
+    handler.inject(gestures);
+    let stats = std::mem::take(&mut *handler.stats.write());
+    stats
+}
+
+/// The config as `dump-config` prints it
+fn dump(c: &Config) -> String {
+    toml::to_string(c).unwrap()
+}
+
+/// A swipe of `fingers` moving `dx` to the right in a single update
+fn swipe_east(fingers: i32, dx: f64) -> [GestureInput; 3] {
+    [
+        GestureInput::SwipeBegin { fingers },
+        GestureInput::SwipeUpdate { dx, dy: 0.0 },
+        GestureInput::SwipeEnd {
+            fingers: None,
+            cancelled: false,
+        },
+    ]
+}
 
 #[test]
 fn test_config_default() {
@@ -8,11 +117,50 @@ fn test_config_default() {
         c,
         Config {
             // // device: None,
+            invert_horizontal: false,
+            invert_vertical: false,
+            rotation: None,
+            swipe_hysteresis: None,
+            pinch_lock: false,
+            finger_tolerance: None,
+            coalesce_updates: None,
+            device_config: None,
+            libinput_log: None,
+            ignore_devices: vec![],
+            default_swipe_action: None,
+            default_pinch_action: None,
+            default_hold_action: None,
+            activation_key: None,
+            cheatsheet: None,
+            vars: Default::default(),
+            run_as: None,
+            dispatcher: None,
+            on_missing_command: None,
+            screenshot_command: None,
+            swipe_defaults: vec![],
+            include: vec![],
             gestures: vec![],
+            reloads: 0,
+            file: None,
         }
     );
 }
 
+#[test]
+fn test_config_invert() {
+    let c = knuffel::parse::<Config>(
+        "test.kdl",
+        r#"
+        invert-horizontal
+        swipe direction="w" fingers=4 end="xdotool key alt+Right"
+        "#,
+    )
+    .unwrap();
+    assert!(c.invert_horizontal);
+    assert!(!c.invert_vertical);
+    assert_eq!(c.gestures.len(), 1);
+}
+
 #[test]
 fn test_dir() {
     let test_cases = vec![
@@ -35,3 +183,2271 @@ fn test_dir() {
         assert_eq!(SwipeDir::dir(x, y), expected);
     }
 }
+
+#[test]
+fn test_stats_report() {
+    let c = knuffel::parse::<Config>(
+        "test.kdl",
+        r#"
+        swipe direction="w" fingers=4 end="xdotool key alt+Right"
+        hold fingers=4 action="xdotool key Super_L"
+        "#,
+    )
+    .unwrap();
+    let mut stats = Stats::default();
+    stats.record(1);
+    stats.record(1);
+    let report = stats.report(&c);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "0\tswipe\t0\tnever");
+    assert!(lines[1].starts_with("1\thold\t2\t"));
+
+    stats.reset();
+    assert!(stats.report(&c).lines().all(|l| l.ends_with("\tnever")));
+}
+
+#[test]
+fn test_latency_report() {
+    let mut stats = Stats::default();
+    stats.record_latency(Duration::from_millis(2));
+    stats.record_latency(Duration::from_millis(4));
+    assert_eq!(
+        stats.latency_report(),
+        "2 gestures, 3.00 ms on average, 4.00 ms at most\n"
+    );
+
+    stats.reset();
+    assert!(stats.latency_report().starts_with("0 gestures, 0.00 ms"));
+}
+
+#[test]
+fn test_fingers_any() {
+    let c = knuffel::parse::<Config>(
+        "test.kdl",
+        r#"
+        hold fingers="any" action="notify-send help"
+        hold fingers=0 action="notify-send help"
+        hold fingers=3 action="notify-send three"
+        "#,
+    )
+    .unwrap();
+    let fingers: Vec<Fingers> = c
+        .gestures
+        .iter()
+        .map(|g| match g {
+            Gesture::Hold(h) => h.fingers,
+            _ => unreachable!(),
+        })
+        .collect();
+    assert_eq!(fingers, vec![Fingers::ANY, Fingers::ANY, Fingers(3)]);
+    assert!(Fingers::ANY.matches(Fingers(4), 0));
+    assert!(Fingers(3).matches(Fingers(3), 0));
+    assert!(!Fingers(3).matches(Fingers(4), 0));
+}
+
+#[test]
+fn test_dir_hysteresis() {
+    // 30° below the x axis is NE, but within 10° of the E bucket's edge at 22.5°
+    let (x, y) = (30f64.to_radians().cos(), -(30f64.to_radians().sin()));
+    assert_eq!(SwipeDir::dir(x, y), SwipeDir::NE);
+    assert_eq!(
+        SwipeDir::dir_with_hysteresis(x, y, &SwipeDir::E, 10.0),
+        SwipeDir::E
+    );
+    assert_eq!(
+        SwipeDir::dir_with_hysteresis(x, y, &SwipeDir::E, 5.0),
+        SwipeDir::NE
+    );
+    assert_eq!(
+        SwipeDir::dir_with_hysteresis(x, y, &SwipeDir::Any, 10.0),
+        SwipeDir::NE
+    );
+    // Across the ±π seam
+    assert_eq!(
+        SwipeDir::dir_with_hysteresis(-1.0, 0.5, &SwipeDir::W, 10.0),
+        SwipeDir::W
+    );
+}
+
+#[test]
+fn test_fallback_order() {
+    let c = knuffel::parse::<Config>(
+        "test.kdl",
+        r#"
+        swipe direction="any" fingers=3 end="echo unhandled" fallback=true
+        swipe direction="w" fingers=3 end="xdotool key alt+Right"
+        hold fingers=3 action="echo hold"
+        "#,
+    )
+    .unwrap();
+    let order: Vec<usize> = in_fallback_order(&c.gestures).map(|(i, _)| i).collect();
+    assert_eq!(order, vec![1, 2, 0]);
+}
+
+#[test]
+fn test_rotation() {
+    let c = Config::parse_str("test.kdl", "rotation 90").unwrap();
+    assert_eq!(SwipeDir::dir(1.0, 0.0), SwipeDir::E);
+    let (dx, dy) = c.transform_swipe(1.0, 0.0);
+    assert_eq!(SwipeDir::dir(dx, dy), SwipeDir::S);
+
+    let c = Config::parse_str("test.kdl", "rotation 270\ninvert-horizontal").unwrap();
+    assert_eq!(c.transform_swipe(0.0, 1.0), (-1.0, 0.0));
+
+    assert!(Config::parse_str("test.kdl", "rotation 45").is_err());
+}
+
+#[test]
+fn test_config_error() {
+    assert!(matches!(
+        Config::parse_str("test.kdl", "rotation 45"),
+        Err(ConfigError::Invalid(_))
+    ));
+    assert!(matches!(
+        Config::parse_str("test.kdl", "rotation"),
+        Err(ConfigError::Parse(_))
+    ));
+}
+
+#[test]
+fn test_gesture_summary() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="nw" fingers="any" start="" end="xdotool key super+s"
+        pinch direction="counter-clockwise" fingers=3 update="echo $delta_angle"
+        "#,
+    )
+    .unwrap();
+    let swipe = &c.gestures[0];
+    assert_eq!(swipe.fingers().unwrap().to_string(), "any");
+    assert_eq!(swipe.direction(), "nw");
+    assert_eq!(swipe.actions(), vec![("end", "xdotool key super+s")]);
+    let pinch = &c.gestures[1];
+    assert_eq!(pinch.direction(), "counter-clockwise");
+    assert_eq!(pinch.actions(), vec![("update", "echo $delta_angle")]);
+    assert!(pinch.is_continuous());
+    assert!(!swipe.is_continuous());
+}
+
+#[test]
+fn test_desktop_entry_quote() {
+    assert_eq!(quote("/usr/bin/gestures"), "/usr/bin/gestures");
+    assert_eq!(
+        quote("/home/me/my gestures.kdl"),
+        "\"/home/me/my gestures.kdl\""
+    );
+    assert_eq!(quote("50%"), "50%%");
+    assert_eq!(quote("a$b"), "\"a\\\\$b\"");
+}
+
+#[test]
+fn test_hold_region() {
+    assert!(Region::BottomRight.contains(0.9, 0.95));
+    assert!(Region::BottomRight.contains(1.0, 1.0));
+    assert!(!Region::BottomRight.contains(0.5, 0.95));
+    assert!(Region::Center.contains(0.5, 0.5));
+    assert!(Region::TopLeft.contains(0.0, 0.0));
+}
+
+#[test]
+fn test_swipe_angle() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 angle=30.0 tolerance=20.0 end="echo 30""#,
+    )
+    .unwrap();
+    let Gesture::Swipe(swipe) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert!(swipe.is_directional());
+    // Up and to the right, y grows downwards
+    assert!(swipe.matches_direction(&SwipeDir::NE, 1.0, -0.5));
+    assert!(swipe.matches_direction(&SwipeDir::E, 1.0, -0.3));
+    assert!(!swipe.matches_direction(&SwipeDir::NE, 1.0, -1.5));
+    assert!(!swipe.matches_direction(&SwipeDir::Any, 0.0, 0.0));
+    assert_eq!(SwipeDir::angle(-1.0, 0.0), 180.0);
+}
+
+#[test]
+fn test_finger_tolerance() {
+    let c = Config::parse_str("test.kdl", "finger-tolerance 1").unwrap();
+    let tolerance = c.finger_tolerance.unwrap();
+    assert!(Fingers(4).matches(Fingers(3), tolerance));
+    assert!(Fingers(4).matches(Fingers(5), tolerance));
+    assert!(!Fingers(4).matches(Fingers(2), tolerance));
+    assert!(!Fingers(4).matches(Fingers(3), 0));
+}
+
+#[test]
+fn test_pinch_deadzone() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"pinch direction="in" fingers=2 scale-deadzone=0.05 update="echo in""#,
+    )
+    .unwrap();
+    let Gesture::Pinch(pinch) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert!(!pinch.matches_update(&PinchDir::In, 0.98));
+    assert!(pinch.matches_update(&PinchDir::In, 0.9));
+    assert!(!pinch.matches_update(&PinchDir::Out, 1.1));
+}
+
+#[test]
+fn test_scale_mode() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"pinch direction="in" fingers=2 update="echo in""#,
+    )
+    .unwrap();
+    let Gesture::Pinch(pinch) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert_eq!(pinch.scale_mode, None);
+
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"pinch direction="any" fingers=2 scale-mode="delta" update="echo $scale""#,
+    )
+    .unwrap();
+    let Gesture::Pinch(pinch) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert_eq!(pinch.scale_mode, Some(ScaleMode::Delta));
+}
+
+#[test]
+fn test_pinch_value() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"pinch direction="any" fingers=2 scale-min=0.5 scale-max=1.5 value-step=5.0 update="""#,
+    )
+    .unwrap();
+    let Gesture::Pinch(pinch) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert_eq!(pinch.value(1.0), 50.0);
+    assert_eq!(pinch.value(1.12), 60.0);
+    assert_eq!(pinch.value(0.2), 0.0);
+    assert_eq!(pinch.value(3.0), 100.0);
+    assert_eq!(
+        substitute_value("pamixer --set-volume $value", 60.0),
+        "pamixer --set-volume 60"
+    );
+    assert_eq!(
+        substitute_value("brightnessctl set $value%", 60.0),
+        "brightnessctl set 60%"
+    );
+    assert_eq!(substitute_value("$value", 2.5), "2.5");
+    let invalid = r#"pinch direction="any" fingers=2 scale-min=2.0 update="""#;
+    assert!(Config::parse_str("test.kdl", invalid).is_err());
+}
+
+#[test]
+fn test_group_expand() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        group end="xdotool key super+s" {
+            swipe direction="n" fingers=3
+            swipe direction="n" fingers=4 end="xdotool key super+a"
+        }
+        "#,
+    )
+    .unwrap();
+    assert_eq!(c.gestures.len(), 2);
+    assert_eq!(
+        c.gestures[0].actions(),
+        vec![("end", "xdotool key super+s")]
+    );
+    assert_eq!(
+        c.gestures[1].actions(),
+        vec![("end", "xdotool key super+a")]
+    );
+}
+
+#[test]
+fn test_device_config() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        device-config {
+            tap true
+            accel-profile "flat"
+        }
+        "#,
+    )
+    .unwrap();
+    let device_config = c.device_config.unwrap();
+    assert_eq!(device_config.tap, Some(true));
+    assert_eq!(device_config.natural_scroll, None);
+    assert_eq!(device_config.accel_profile, Some(AccelProfile::Flat));
+}
+
+#[test]
+fn test_swipe_defaults() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe-defaults fingers=3 acceleration=20 mouse-up-delay=500
+        swipe-defaults fingers="any" mouse-up-delay=300 button=3
+        swipe direction="any" fingers=3 acceleration=10
+        swipe direction="any" fingers=4
+        "#,
+    )
+    .unwrap();
+    let Gesture::Swipe(three) = &c.gestures[0] else {
+        panic!("expected a swipe");
+    };
+    assert_eq!(three.acceleration, Some(10));
+    assert_eq!(three.mouse_up_delay, Some(500));
+    assert_eq!(three.button, Some(3));
+    let Gesture::Swipe(four) = &c.gestures[1] else {
+        panic!("expected a swipe");
+    };
+    assert_eq!(four.acceleration, None);
+    assert_eq!(four.mouse_up_delay, Some(300));
+}
+
+#[test]
+fn test_click() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"hold fingers=4 click="double" click-interval=80"#,
+    )
+    .unwrap();
+    let Gesture::Hold(hold) = &c.gestures[0] else {
+        panic!("expected a hold");
+    };
+    assert_eq!(hold.click, Some(Click::Double));
+    assert_eq!(hold.click.unwrap().count(), 2);
+    assert_eq!(hold.click_interval, Some(80));
+    assert_eq!(hold.click_button, None);
+}
+
+#[test]
+fn test_ignore_devices() {
+    let c = Config::parse_str("test.kdl", r#"ignore-devices "trackpoint" "Keyboard""#).unwrap();
+    assert!(c.ignores_device("TPPS/2 Elan TrackPoint"));
+    assert!(c.ignores_device("USB keyboard with pointer"));
+    assert!(!c.ignores_device("SynPS/2 Synaptics TouchPad"));
+    assert!(!Config::default().ignores_device("TPPS/2 Elan TrackPoint"));
+}
+
+#[test]
+fn test_libinput_log() {
+    let c = Config::parse_str("test.kdl", r#"libinput-log "info""#).unwrap();
+    assert_eq!(c.libinput_log, Some(LibinputLog::Info));
+    for (priority, level) in [
+        (
+            ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_DEBUG,
+            Level::Debug,
+        ),
+        (
+            ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_INFO,
+            Level::Info,
+        ),
+        (
+            ffi::libinput_log_priority_LIBINPUT_LOG_PRIORITY_ERROR,
+            Level::Error,
+        ),
+    ] {
+        assert_eq!(LibinputLog::level(priority), level);
+    }
+}
+
+#[test]
+fn test_swipe_dir_names() {
+    for (name, dir) in [
+        ("n", SwipeDir::N),
+        ("N", SwipeDir::N),
+        ("North", SwipeDir::N),
+        ("up", SwipeDir::N),
+        ("South-West", SwipeDir::SW),
+        ("down_left", SwipeDir::SW),
+        ("E", SwipeDir::E),
+        ("ANY", SwipeDir::Any),
+    ] {
+        assert_eq!(name.parse::<SwipeDir>(), Ok(dir), "{name}");
+    }
+    assert!("sideways".parse::<SwipeDir>().is_err());
+    assert!(Config::parse_str("test.kdl", r#"swipe direction="East" fingers=3"#).is_ok());
+    assert!(Config::parse_str("test.kdl", r#"swipe direction="x" fingers=3"#).is_err());
+}
+
+#[test]
+fn test_json() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="n" fingers="any" end="notify-send \"up\"""#,
+    )
+    .unwrap();
+    assert_eq!(
+        serde_json::to_string(&c.gestures[0].listing(0)).unwrap(),
+        r#"{"index":0,"type":"swipe","fingers":"any","direction":"n","actions":{"end":"notify-send \"up\""},"fallback":false}"#
+    );
+    let mut stats = Stats::default();
+    stats.record(0);
+    let rules = stats.report_json(&c);
+    assert_eq!(rules[0]["count"], 1);
+    assert_eq!(rules[0]["type"], "swipe");
+    assert!(rules[0]["last_fired"].is_string());
+    assert_eq!(stats.latency_json()["count"], 0);
+}
+
+#[test]
+fn test_vars() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r##"
+        vars {
+            accent "#ff8800"
+            step 5
+            step 10
+        }
+        default-hold-action "osd --color {var.accent}"
+        pinch direction="out" fingers=2 update="osd --step {var.step} --color {var.accent}"
+        swipe direction="n" fingers=3 end="osd" double="osd --color {var.accent}"
+        "##,
+    )
+    .unwrap();
+    assert_eq!(
+        c.default_hold_action.as_deref(),
+        Some("osd --color #ff8800")
+    );
+    let Gesture::Pinch(pinch) = &c.gestures[0] else {
+        panic!("expected a pinch");
+    };
+    assert_eq!(
+        pinch.update.as_deref(),
+        Some("osd --step 10 --color #ff8800")
+    );
+    let Gesture::Swipe(swipe) = &c.gestures[1] else {
+        panic!("expected a swipe");
+    };
+    assert_eq!(swipe.double.as_deref(), Some("osd --color #ff8800"));
+    assert!(c.gestures[1].commands().contains(&"osd --color #ff8800"));
+    let invalid = r#"hold fingers=3 action="osd {var.missing}""#;
+    assert!(Config::parse_str("test.kdl", invalid).is_err());
+}
+
+#[test]
+fn test_include() {
+    let dir = std::env::temp_dir().join(format!("gestures-include-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("local")).unwrap();
+    std::fs::write(
+        dir.join("main.kdl"),
+        r#"
+        run-as "me"
+        include "local/machine.kdl"
+        swipe direction="n" fingers=3 end="echo shared"
+        "#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("local/machine.kdl"),
+        r#"
+        run-as "other"
+        swipe direction="s" fingers=3 end="echo local"
+        "#,
+    )
+    .unwrap();
+    let c = Config::read_from_file(&dir.join("main.kdl")).unwrap();
+    assert_eq!(c.run_as.as_deref(), Some("other"));
+    assert_eq!(c.gestures.len(), 2);
+    assert_eq!(c.gestures[1].direction(), "s");
+
+    std::fs::write(dir.join("local/machine.kdl"), r#"include "../main.kdl""#).unwrap();
+    assert!(matches!(
+        Config::read_from_file(&dir.join("main.kdl")),
+        Err(ConfigError::Invalid(_))
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_window_action() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="w" fingers=3 window="tile-left" end="""#,
+    )
+    .unwrap();
+    let Gesture::Swipe(swipe) = &c.gestures[0] else {
+        panic!("expected a swipe");
+    };
+    assert_eq!(swipe.window, Some(WindowAction::TileLeft));
+    assert!(Config::parse_str("test.kdl", r#"hold fingers=3 window="fullscreen""#).is_err());
+}
+
+#[test]
+fn test_dbus() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="w" fingers=3 {
+            dbus "org.mpris.MediaPlayer2.spotify" "/org/mpris/MediaPlayer2" \
+                "org.mpris.MediaPlayer2.Player.Seek" "int64:5000000"
+        }
+        "#,
+    )
+    .unwrap();
+    let Gesture::Swipe(swipe) = &c.gestures[0] else {
+        panic!("expected a swipe");
+    };
+    let message = swipe.dbus.as_deref().unwrap().message().unwrap();
+    let header = message.header();
+    assert_eq!(
+        header.destination().unwrap().as_str(),
+        "org.mpris.MediaPlayer2.spotify"
+    );
+    assert_eq!(header.path().unwrap().as_str(), "/org/mpris/MediaPlayer2");
+    assert_eq!(
+        header.interface().unwrap().as_str(),
+        "org.mpris.MediaPlayer2.Player"
+    );
+    assert_eq!(header.member().unwrap().as_str(), "Seek");
+    assert_eq!(message.body().deserialize::<i64>().unwrap(), 5000000);
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"hold fingers=3 { dbus "org.example" "/" "org.example.Say" "string:hi" "int32:2"; }"#,
+    )
+    .unwrap();
+    let Gesture::Hold(hold) = &c.gestures[0] else {
+        panic!("expected a hold");
+    };
+    let message = hold.dbus.as_deref().unwrap().message().unwrap();
+    let body = message.body().deserialize::<(String, i32)>().unwrap();
+    assert_eq!(body, ("hi".to_string(), 2));
+    let invalid = r#"hold fingers=3 { dbus "org.example" "/" "Quit"; }"#;
+    assert!(Config::parse_str("test.kdl", invalid).is_err());
+    let invalid = r#"hold fingers=3 { dbus "org.example" "/" "org.example.Say" "hello"; }"#;
+    assert!(Config::parse_str("test.kdl", invalid).is_err());
+    let invalid = r#"hold fingers=3 { dbus "org.example" "/" "org.example.Say" "int32:a"; }"#;
+    assert!(Config::parse_str("test.kdl", invalid).is_err());
+}
+
+#[test]
+fn test_needs_xdo() {
+    for (rule, needs) in [
+        (
+            r#"swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=500"#,
+            true,
+        ),
+        (
+            r#"swipe direction="n" fingers=3 acceleration=20 mouse-up-delay=500"#,
+            false,
+        ),
+        (r#"hold fingers=3 click="double""#, true),
+        (r#"hold fingers=3 region="top" action="true""#, true),
+        (
+            r#"pinch direction="in" fingers=2 update="xdotool key ctrl+minus""#,
+            false,
+        ),
+    ] {
+        let c = Config::parse_str("test.kdl", rule).unwrap();
+        assert_eq!(c.gestures[0].needs_xdo(), needs, "{rule}");
+    }
+    assert!(start_for_config(&Config::default(), false).is_ok());
+}
+
+#[test]
+fn test_gesture_input_format() {
+    let events = [
+        GestureInput::SwipeBegin { fingers: 3 },
+        GestureInput::SwipeUpdate { dx: 1.5, dy: -0.25 },
+        GestureInput::SwipeEnd {
+            fingers: Some(2),
+            cancelled: true,
+        },
+        GestureInput::PinchUpdate {
+            scale: 0.9,
+            angle_delta: 0.0,
+        },
+        GestureInput::HoldEnd { cancelled: false },
+    ];
+    for event in events {
+        assert_eq!(event.to_string().parse::<GestureInput>().unwrap(), event);
+    }
+    assert!("swipe-begin".parse::<GestureInput>().is_err());
+    assert!("swipe-end now".parse::<GestureInput>().is_err());
+}
+
+#[test]
+fn test_dispatch() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="e" fingers=3 end=""
+        swipe direction="w" fingers=3 end=""
+        "#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    h.dispatch(swipe_east(3, 5.0));
+    let report = h.stats.read().report(&h.config.read());
+    let lines: Vec<&str> = report.lines().collect();
+    assert!(lines[0].starts_with("0\tswipe\t1\t"));
+    assert_eq!(lines[1], "1\tswipe\t0\tnever");
+}
+
+#[test]
+fn test_cheatsheet() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        cheatsheet "true" modifier="super"
+        swipe direction="e" fingers=3 end=""
+        "#,
+    )
+    .unwrap();
+    assert_eq!(c.cheatsheet.as_ref().unwrap().modifier, Modifier::Super);
+    let mut h = Handler::new(c);
+    // Right super held, the swipe only shows the cheatsheet
+    h.eh.handle_key(126, true);
+    h.dispatch(swipe_east(3, 5.0));
+    assert_eq!(h.counts(), [0]);
+    h.eh.handle_key(126, false);
+    h.dispatch(swipe_east(3, 5.0));
+    assert_eq!(h.counts(), [1]);
+}
+
+#[test]
+fn test_activation_key() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        activation-key "ctrl"
+        swipe direction="e" fingers=3 end=""
+        "#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let swipe = |h: &mut Handler, release: bool| {
+        let [begin, update, end] = swipe_east(3, 5.0);
+        h.dispatch([begin, update]);
+        if release {
+            h.eh.handle_key(29, false);
+        }
+        h.dispatch([end]);
+    };
+    // Not held, released halfway through, then held throughout
+    swipe(&mut h, false);
+    h.eh.handle_key(29, true);
+    swipe(&mut h, true);
+    h.eh.handle_key(29, true);
+    swipe(&mut h, false);
+    assert_eq!(h.counts(), [1]);
+}
+
+#[test]
+fn test_end_fingers() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="e" fingers=3 end-fingers=2 end="""#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    // Unknown end counts are taken to be the begin count
+    for fingers in [Some(3), None, Some(2)] {
+        let [begin, update, _] = swipe_east(3, 5.0);
+        let end = GestureInput::SwipeEnd {
+            fingers,
+            cancelled: false,
+        };
+        h.dispatch([begin, update, end]);
+    }
+    assert_eq!(h.counts(), [1]);
+}
+
+#[test]
+fn test_return_radius() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="e" fingers=3 return-radius=10.0 end="" cancel="""#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    // Out and back again, then only out
+    for moves in [&[50.0, -45.0][..], &[50.0]] {
+        let [begin, _, end] = swipe_east(3, 0.0);
+        h.dispatch([begin]);
+        h.dispatch(
+            moves
+                .iter()
+                .map(|&dx| GestureInput::SwipeUpdate { dx, dy: 0.0 }),
+        );
+        h.dispatch([end]);
+    }
+    assert_eq!(h.counts(), [1]);
+}
+
+#[test]
+fn test_requires_previous() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="n" fingers=3 id="up" end=""
+        pinch direction="in" fingers=2 requires-previous="up" previous-within=500 end=""
+        "#,
+    )
+    .unwrap();
+    let pinch = || InjectGesture::Pinch {
+        fingers: 2,
+        dir: PinchDir::In,
+    };
+    let swipe = InjectGesture::Swipe {
+        fingers: 3,
+        dir: SwipeDir::N,
+    };
+    // Only the pinch right after the swipe fires
+    let stats = run_gestures(c, &[pinch(), swipe, pinch()]);
+    assert_eq!(stats.count(1), 1);
+
+    let invalid = r#"hold fingers=3 requires-previous="nothing" action="""#;
+    assert!(Config::parse_str("test.kdl", invalid).is_err());
+}
+
+#[test]
+fn test_inject() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="n" fingers=3 end=""
+        pinch direction="in" fingers=2 end=""
+        "#,
+    )
+    .unwrap();
+    let stats = run_gestures(
+        c,
+        &[
+            InjectGesture::Swipe {
+                fingers: 3,
+                dir: "north".parse().unwrap(),
+            },
+            InjectGesture::Pinch {
+                fingers: 2,
+                dir: PinchDir::In,
+            },
+        ],
+    );
+    assert_eq!((stats.count(0), stats.count(1)), (1, 1));
+}
+
+#[test]
+fn test_release_delay() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=900
+        swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=900 \
+            mouse-up-delay-min=0 flick-velocity=500.0 velocity-curve=2.0
+        "#,
+    )
+    .unwrap();
+    let [Gesture::Swipe(fixed), Gesture::Swipe(scaled)] = &c.gestures[..] else {
+        panic!("expected two swipes");
+    };
+    assert_eq!(fixed.release_delay(0.0), 900);
+    assert_eq!(scaled.release_delay(0.0), 0);
+    assert_eq!(scaled.release_delay(250.0), 225);
+    assert_eq!(scaled.release_delay(2000.0), 900);
+}
+
+#[test]
+fn test_min_coherence() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="e" fingers=3 min-coherence=10.0 end="""#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    // 3 fingers need 30 units, only the second swipe gets there
+    for dx in [20.0, 40.0] {
+        h.dispatch(swipe_east(3, dx));
+    }
+    assert_eq!(h.counts(), [1]);
+}
+
+#[test]
+fn test_pinch_lock() {
+    assert_eq!(PinchKind::decide(1.02, 1.0), None);
+    assert_eq!(PinchKind::decide(1.1, 4.0), Some(PinchKind::Scale));
+    assert_eq!(PinchKind::decide(1.02, -8.0), Some(PinchKind::Rotate));
+    // Once rotating, scaling is ignored
+    assert_eq!(
+        PinchDir::dir_locked(1.3, 0.5, PinchKind::Rotate),
+        PinchDir::Clockwise
+    );
+    assert_eq!(
+        PinchDir::dir_locked(0.7, 2.0, PinchKind::Scale),
+        PinchDir::In
+    );
+}
+
+#[test]
+fn test_dump_config() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        vars {
+            term "alacritty"
+        }
+        swipe-defaults fingers=3 acceleration=20
+        rotation 90
+        activation-key "super"
+        libinput-log "debug"
+        swipe direction="ne" fingers=3 end="{var.term} -e \"top\"" min-coherence=0.5
+        pinch direction="counter-clockwise" fingers="any" scale-mode="delta" update="zoom $scale"
+        hold fingers=4 region="top-left" window="tile-left" center-cursor=true {
+            dbus "org.example" "/org/example" "org.example.Run" "string:a" "int32:2" system=true
+        }
+        "#,
+    )
+    .unwrap();
+    let toml = dump(&c);
+    assert!(toml.contains(r#"end = 'alacritty -e "top"'"#));
+    assert!(toml.contains("acceleration = 20"));
+    assert!(toml.contains(r#"libinput-log = "debug""#));
+    assert!(!toml.contains("swipe-defaults") && !toml.contains("vars"));
+    let json = serde_json::to_value(&c).unwrap();
+    assert_eq!(json["rotation"], 90);
+    assert_eq!(json["activation-key"], "super");
+    let gestures = json["gestures"].as_array().unwrap();
+    assert_eq!(gestures[0]["kind"], "swipe");
+    assert_eq!(gestures[0]["direction"], "ne");
+    assert_eq!(gestures[1]["direction"], "counter-clockwise");
+    assert_eq!(gestures[1]["fingers"], "any");
+    assert_eq!(gestures[1]["scale-mode"], "delta");
+    assert_eq!(gestures[2]["region"], "top-left");
+    assert_eq!(gestures[2]["window"], "tile-left");
+    assert_eq!(gestures[2]["dbus"]["args"][1], "int32:2");
+}
+
+#[test]
+fn test_direction_debounce() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="n" fingers=3 direction-debounce=10000 update="true"
+        swipe direction="e" fingers=3 update="true"
+        swipe direction="e" fingers=3 direction-debounce=10000 update="true"
+        "#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    h.dispatch([
+        GestureInput::SwipeBegin { fingers: 3 },
+        GestureInput::SwipeUpdate { dx: 0.0, dy: -10.0 },
+        GestureInput::SwipeUpdate { dx: 10.0, dy: 0.0 },
+        GestureInput::SwipeUpdate { dx: 10.0, dy: 0.0 },
+        GestureInput::SwipeEnd {
+            fingers: None,
+            cancelled: false,
+        },
+    ]);
+    // The turn to the east doesn't last long enough for the debounced rules
+    assert_eq!(h.counts(), [1, 1, 0]);
+}
+
+#[test]
+fn test_double() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="n" fingers=3 end="" double="" double-within=5000"#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let swipe = || InjectGesture::Swipe {
+        fingers: 3,
+        dir: SwipeDir::N,
+    };
+    // The single swipe waits for a second one
+    h.inject(&[swipe()]);
+    assert_eq!(h.counts(), [0]);
+    // Which makes a double, leaving nothing to settle
+    h.inject(&[swipe()]);
+    assert_eq!(h.counts(), [1]);
+    let settled = std::time::Instant::now();
+    h.eh.settle(&mut h.xdoh).unwrap();
+    assert!(settled.elapsed() < Duration::from_secs(1));
+    assert_eq!(h.counts(), [1]);
+}
+
+#[test]
+fn test_double_actions() {
+    let out = std::env::temp_dir().join(format!("gestures-double-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"swipe direction="n" fingers=3 end="echo single" double="echo double" double-within=5000 serialize=true set="swipe-hysteresis 10" then="echo {{output}} >> {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let swipe = || InjectGesture::Swipe {
+        fingers: 3,
+        dir: SwipeDir::N,
+    };
+    h.inject(&[swipe(), swipe()]);
+    h.eh.serial(0).unwrap().wait();
+    // The double fires like a single swipe, only with its own command
+    assert_eq!(h.config.read().swipe_hysteresis, Some(10.0));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "double\n");
+}
+
+#[test]
+fn test_power() {
+    let dir = std::env::temp_dir().join(format!("gestures-power-{}", std::process::id()));
+    let supply = |name: &str, files: &[(&str, &str)]| {
+        std::fs::create_dir_all(dir.join(name)).unwrap();
+        for (file, value) in files {
+            std::fs::write(dir.join(name).join(file), format!("{value}\n")).unwrap();
+        }
+    };
+    supply(
+        "hidpp_battery_0",
+        &[("type", "Battery"), ("scope", "Device")],
+    );
+    assert_eq!(Power::read(&dir), Some(Power::Ac));
+    supply("BAT0", &[("type", "Battery")]);
+    supply("AC", &[("type", "Mains"), ("online", "0")]);
+    assert_eq!(Power::read(&dir), Some(Power::Battery));
+    supply("AC", &[("online", "1")]);
+    assert_eq!(Power::read(&dir), Some(Power::Ac));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(Power::read(&dir), None);
+}
+
+#[test]
+fn test_tune() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="any" fingers=3 mouse-up-delay=500 acceleration=20
+        hold fingers=3 set="acceleration +5"
+        hold fingers=4 set="acceleration -200"
+        pinch direction="in" fingers=2 set="swipe-hysteresis 10"
+        "#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let acceleration = |h: &Handler| match &h.config.read().gestures[0] {
+        Gesture::Swipe(s) => s.acceleration,
+        _ => unreachable!(),
+    };
+    h.inject(&[
+        InjectGesture::Hold { fingers: 3 },
+        InjectGesture::Hold { fingers: 3 },
+    ]);
+    assert_eq!(acceleration(&h), Some(30));
+    // Kept within bounds
+    h.inject(&[InjectGesture::Hold { fingers: 4 }]);
+    assert_eq!(acceleration(&h), Some(1));
+    h.inject(&[InjectGesture::Pinch {
+        fingers: 2,
+        dir: PinchDir::In,
+    }]);
+    assert_eq!(h.config.read().swipe_hysteresis, Some(10.0));
+
+    let err = Config::parse_str("test.kdl", r#"hold fingers=3 set="rotation 90""#);
+    assert!(matches!(err, Err(ConfigError::Parse(_))));
+    let err = Config::parse_str("test.kdl", r#"hold fingers=3 set="acceleration fast""#);
+    assert!(matches!(err, Err(ConfigError::Parse(_))));
+    // Acceleration is in tenths, a fraction of one would be rounded away
+    let err = Config::parse_str("test.kdl", r#"hold fingers=3 set="acceleration +0.1""#);
+    assert!(matches!(err, Err(ConfigError::Parse(_))));
+    assert!("swipe-hysteresis +0.5".parse::<Tune>().is_ok());
+}
+
+#[test]
+fn test_drag_smoothing() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 mouse-up-delay=500 acceleration=20 drag-smoothing=0.75"#,
+    )
+    .unwrap();
+    let Gesture::Swipe(s) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert_eq!(s.smooth((8.0, -4.0), None), (8.0, -4.0));
+    assert_eq!(s.smooth((8.0, -4.0), Some((0.0, 0.0))), (2.0, -1.0));
+    let err = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 mouse-up-delay=500 acceleration=20 drag-smoothing=1.0"#,
+    );
+    assert!(matches!(err, Err(ConfigError::Invalid(_))));
+}
+
+#[test]
+fn test_bench() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="n" fingers=3 end="false" sound="/nonexistent.oga"
+        pinch direction="in" fingers=2 set="finger-tolerance 3"
+        "#,
+    )
+    .unwrap();
+    let (events, _) = bench(c, 2).unwrap();
+    assert_eq!(events % 2, 0);
+    assert!(events > 0);
+}
+
+#[test]
+fn test_bench_without_actions() {
+    let mut c = Config::parse_str(
+        "test.kdl",
+        r#"
+        dispatcher "false"
+        swipe direction="s" fingers=3 repeat-key="Down" hold-modifiers="ctrl"
+        hold fingers=3 click="double" center-cursor=true
+        hold fingers=4 type="Best regards" set="swipe-hysteresis 10"
+        "#,
+    )
+    .unwrap();
+    without_actions(&mut c);
+    let (xdoh, rx) = start_recording();
+    let mut h = Handler::with_xdo(c, xdoh);
+    h.inject(&[
+        InjectGesture::Swipe {
+            fingers: 3,
+            dir: SwipeDir::S,
+        },
+        InjectGesture::Hold { fingers: 3 },
+        InjectGesture::Hold { fingers: 4 },
+    ]);
+    // Matched as configured rather than handed to the dispatcher, but nothing acted on. Holds
+    // still look up the pointer as they begin.
+    assert_eq!(h.counts(), [1, 1, 1]);
+    assert!(rx
+        .try_iter()
+        .all(|command| matches!(command, XDoCommand::PointerLocation(_))));
+    assert_eq!(h.config.read().swipe_hysteresis, None);
+}
+
+#[test]
+fn test_exclude_directions() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="any" fingers=3 exclude-directions="n" end=""
+        swipe direction="any" fingers=3 exclude-directions="up, down-left" end=""
+        "#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    for dir in [SwipeDir::N, SwipeDir::SW, SwipeDir::E] {
+        h.inject(&[InjectGesture::Swipe { fingers: 3, dir }]);
+    }
+    assert_eq!(h.counts(), [2, 1]);
+    assert!(Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 exclude-directions="n up-ish""#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_dispatcher() {
+    let out = std::env::temp_dir().join(format!("gestures-dispatcher-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"
+            dispatcher "echo $GESTURE_TYPE $GESTURE_PHASE $GESTURE_FINGERS $GESTURE_DIRECTION >> {}"
+            swipe direction="n" fingers=3 end="false"
+            "#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let events = synthetic_events(&InjectGesture::Swipe {
+        fingers: 3,
+        dir: SwipeDir::N,
+    });
+    h.dispatch(events.clone());
+    // The dispatcher runs in the background
+    let mut lines = Vec::new();
+    for _ in 0..50 {
+        lines = std::fs::read_to_string(&out)
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        if lines.len() == events.len() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(lines.len(), events.len());
+    assert!(lines.contains(&"swipe begin 3".to_string()));
+    assert!(lines.contains(&"swipe update 3 n".to_string()));
+    assert!(lines.contains(&"swipe end 3 n".to_string()));
+    // The rules are left alone
+    assert_eq!(h.counts(), [0]);
+}
+
+#[test]
+fn test_delay() {
+    let out = std::env::temp_dir().join(format!("gestures-delay-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"hold fingers=3 delay=300 action="touch {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    h.inject(&[InjectGesture::Hold { fingers: 3 }]);
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!out.exists());
+    let settled = std::time::Instant::now();
+    h.eh.settle(&mut h.xdoh).unwrap();
+    assert!(settled.elapsed() >= Duration::from_millis(100));
+    for _ in 0..50 {
+        if out.exists() {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    assert!(out.exists());
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn test_media() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="e" fingers=4 media="next"
+        hold fingers=3 media="play-pause"
+        "#,
+    )
+    .unwrap();
+    let Gesture::Hold(h) = &c.gestures[1] else {
+        unreachable!()
+    };
+    assert_eq!(h.media, Some(MediaKey::PlayPause));
+    assert_eq!(MediaKey::PlayPause.keysym(), "XF86AudioPlay");
+    assert!(c.gestures.iter().all(Gesture::needs_xdo));
+    assert!(dump(&c).contains(r#"media = "play-pause""#));
+    assert!(Config::parse_str("test.kdl", r#"hold fingers=3 media="eject""#).is_err());
+}
+
+#[test]
+fn test_reload_drops_rule_state() {
+    let out = std::env::temp_dir().join(format!("gestures-reload-{}", std::process::id()));
+    let rule = |delay| {
+        format!(
+            r#"hold fingers=3 delay={delay} action="touch {}""#,
+            out.display()
+        )
+    };
+    let mut h = Handler::new(Config::parse_str("test.kdl", &rule(200)).unwrap());
+    h.inject(&[InjectGesture::Hold { fingers: 3 }]);
+    // The delayed actions belong to the rule of the previous config, not to its replacement
+    let mut fresh = Config::parse_str("test.kdl", &rule(0)).unwrap();
+    fresh.reloads = h.config.read().reloads + 1;
+    *h.config.write() = fresh;
+    h.eh.settle(&mut h.xdoh).unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(!out.exists());
+}
+
+#[test]
+fn test_cycle() {
+    let out = std::env::temp_dir().join(format!("gestures-cycle-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"hold fingers=3 {{
+                cycle "echo a >> {0}" "echo b >> {0}"
+            }}"#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    assert!(dump(&c).contains("cycle = [\"echo a"));
+    let mut h = Handler::new(c);
+    let hold = |h: &mut Handler| {
+        h.inject(&[InjectGesture::Hold { fingers: 3 }]);
+        // Each command runs in the background, let it append before the next one
+        std::thread::sleep(Duration::from_millis(200));
+    };
+    for _ in 0..3 {
+        hold(&mut h);
+    }
+    h.config.write().reloads += 1;
+    hold(&mut h);
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "a\nb\na\na\n");
+}
+
+#[test]
+fn test_config_from_env() {
+    std::env::set_var(CONFIG_ENV, r#"hold fingers=3 action="notify-send held""#);
+    let c = Config::read_default_config();
+    std::env::set_var(CONFIG_ENV, "hold fingers=");
+    let broken = Config::read_default_config();
+    std::env::remove_var(CONFIG_ENV);
+    assert_eq!(c.unwrap().gestures.len(), 1);
+    assert!(matches!(broken, Err(ConfigError::Parse(_))));
+}
+
+#[test]
+fn test_reload_config() {
+    let config = RwLock::new(Config::default());
+    let fresh = Config::parse_str("test.kdl", r#"hold fingers=3 action="notify-send held""#);
+    assert!(swap_config(&config, fresh).is_ok());
+    // A failed reload keeps the running config and reports why
+    let broken = Config::parse_str("test.kdl", "hold fingers=");
+    assert!(matches!(
+        swap_config(&config, broken),
+        Err(ConfigError::Parse(_))
+    ));
+    assert_eq!(config.read().reloads, 1);
+    assert_eq!(config.read().gestures.len(), 1);
+}
+
+#[test]
+fn test_reload_config_file() {
+    let file = std::env::temp_dir().join(format!("gestures-reload-{}.kdl", std::process::id()));
+    std::fs::write(&file, r#"hold fingers=3 action="a""#).unwrap();
+    let mut c = Config::read_from_file(&file).unwrap();
+    c.file = Some(file.clone());
+    let config = RwLock::new(c);
+    // The file the daemon was started with is read again, not the default location
+    std::fs::write(
+        &file,
+        r#"hold fingers=3 action="a"; hold fingers=4 action="b""#,
+    )
+    .unwrap();
+    assert!(reload_config(&config).is_ok());
+    std::fs::remove_file(&file).unwrap();
+    assert_eq!(config.read().gestures.len(), 2);
+    assert_eq!(config.read().file.as_ref(), Some(&file));
+    // Stdin is used up by the first read
+    config.write().file = Some("-".into());
+    assert!(matches!(reload_config(&config), Err(ConfigError::Stdin)));
+    assert_eq!(config.read().gestures.len(), 2);
+}
+
+#[test]
+fn test_confirm() {
+    let out = std::env::temp_dir().join(format!("gestures-confirm-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"swipe direction="n" fingers=4 confirm=1000 end="touch {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let gesture = |h: &mut Handler, gesture| {
+        h.inject(&[gesture]);
+        std::thread::sleep(Duration::from_millis(200));
+    };
+    let swipe = || InjectGesture::Swipe {
+        fingers: 4,
+        dir: SwipeDir::N,
+    };
+    // Another swipe instead of the tap disarms the first one and arms the rule again
+    gesture(&mut h, swipe());
+    gesture(&mut h, swipe());
+    assert!(!out.exists());
+    gesture(&mut h, InjectGesture::Hold { fingers: 2 });
+    assert!(out.exists());
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn test_schema() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="n" fingers=3 end="a" acceleration=20 mouse-up-delay=500 drag-smoothing=0.5 exclude-directions="s" double="b" window="maximize" click="single" confirm=500 set="acceleration +2" fallback=true {
+            cycle "c" "d"
+        }
+        pinch direction="in" fingers="any" update="e" scale-mode="delta" value-step=5.0 delay=100
+        hold fingers=4 action="f" region="top" media="mute" center-cursor=true when-power="ac" id="x"
+        "#,
+    )
+    .unwrap();
+    let schema = serde_json::to_value(schemars::schema_for!(Config)).unwrap();
+    let rules = &schema["definitions"]["Gesture"]["oneOf"];
+    let rule = |kind: &str| {
+        let mut rules = rules.as_array().unwrap().iter();
+        let rule = rules.find(|r| r["properties"]["kind"]["enum"][0] == kind);
+        &rule.unwrap()["properties"]
+    };
+    // Every property the config can have is in the schema
+    let json = serde_json::to_value(&c).unwrap();
+    for gesture in json["gestures"].as_array().unwrap() {
+        let kind = gesture["kind"].as_str().unwrap();
+        for prop in gesture.as_object().unwrap().keys() {
+            assert!(
+                rule(kind).get(prop).is_some(),
+                "{prop} of {kind} is missing"
+            );
+        }
+    }
+    for key in json.as_object().unwrap().keys() {
+        assert!(schema["properties"].get(key).is_some(), "{key} is missing");
+    }
+    assert_eq!(rule("swipe")["double-within"]["default"], 300);
+    assert_eq!(rule("swipe")["fallback"]["default"], false);
+    assert_eq!(rule("pinch")["scale-min"]["default"], 0.5);
+    assert_eq!(rule("hold")["output-timeout"]["default"], 5000);
+}
+
+#[test]
+fn test_grab_speed() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=500 grab-speed=300.0"#,
+    )
+    .unwrap();
+    let Gesture::Swipe(s) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert!(s.grabs(20.0, Duration::from_millis(100)));
+    assert!(!s.grabs(60.0, Duration::from_millis(100)));
+    assert!(dump(&c).contains("grab-speed = 300.0"));
+}
+
+#[test]
+fn test_type_text() {
+    let c = Config::parse_str("test.kdl", r#"hold fingers=4 type="Best regards""#).unwrap();
+    let Gesture::Hold(h) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert_eq!(h.text.as_deref(), Some("Best regards"));
+    assert!(c.gestures[0].needs_xdo());
+    assert!(dump(&c).contains(r#"type = "Best regards""#));
+
+    // Handed to libxdo as written, which finds the keys for `+`, `€` and the newline itself
+    let c = Config::parse_str("test.kdl", r#"hold fingers=4 type="a+b €\n""#).unwrap();
+    let (xdoh, rx) = start_recording();
+    let mut h = Handler::with_xdo(c, xdoh);
+    h.inject(&[InjectGesture::Hold { fingers: 4 }]);
+    let typed: Vec<_> = rx
+        .try_iter()
+        .filter_map(|command| match command {
+            XDoCommand::Type(text) => Some(text),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(typed, ["a+b €\n"]);
+}
+
+#[test]
+fn test_skip_busy() {
+    let out = std::env::temp_dir().join(format!("gestures-skip-busy-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"pinch direction="any" fingers=2 skip-busy=true update="echo x >> {}; sleep 0.3""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    assert!(dump(&c).contains("skip-busy = true"));
+    let mut h = Handler::new(c);
+    let pinch = || InjectGesture::Pinch {
+        fingers: 2,
+        dir: PinchDir::Out,
+    };
+    h.inject(&[pinch()]);
+    std::thread::sleep(Duration::from_millis(100));
+    // The first update is still sleeping, so the second pinch's updates are skipped too
+    h.inject(&[pinch()]);
+    std::thread::sleep(Duration::from_millis(400));
+    h.inject(&[pinch()]);
+    std::thread::sleep(Duration::from_millis(100));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "x\nx\n");
+}
+
+#[test]
+fn test_when_tablet_mode() {
+    let out = std::env::temp_dir().join(format!("gestures-tablet-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"hold fingers=3 when-tablet-mode=true action="echo tablet >> {0}"
+            hold fingers=3 when-tablet-mode=false action="echo laptop >> {0}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    assert!(dump(&c).contains("when-tablet-mode = true"));
+    let mut h = Handler::new(c);
+    let hold = |h: &mut Handler| {
+        h.inject(&[InjectGesture::Hold { fingers: 3 }]);
+        std::thread::sleep(Duration::from_millis(200));
+    };
+    hold(&mut h);
+    h.eh.set_tablet_mode(true);
+    hold(&mut h);
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "laptop\ntablet\n");
+}
+
+#[test]
+fn test_run_as_environment() {
+    let mut cmd = std::process::Command::new("true");
+    assert!(set_user(&mut cmd, Some("root")));
+    let env: std::collections::HashMap<_, _> = cmd
+        .get_envs()
+        .map(|(k, v)| (k.to_str().unwrap(), v.unwrap().to_str().unwrap()))
+        .collect();
+    assert_eq!(env["USER"], "root");
+    assert_eq!(env["XDG_RUNTIME_DIR"], "/run/user/0");
+    assert_eq!(env["DBUS_SESSION_BUS_ADDRESS"], "unix:path=/run/user/0/bus");
+    assert!(!set_user(&mut cmd, Some("no-such-user")));
+}
+
+#[test]
+fn test_missing_programs() {
+    assert_eq!(missing_program("sh -c true"), None);
+    assert_eq!(missing_program("echo hi | no-such-program"), None);
+    assert_eq!(
+        missing_program("LANG=C no-such-program --flag"),
+        Some("no-such-program")
+    );
+    assert_eq!(
+        missing_program("/no/such/program"),
+        Some("/no/such/program")
+    );
+    assert_eq!(missing_program("$TERMINAL -e htop"), None);
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"on-missing-command "notify-send \"Not installed: $1\""
+        hold fingers=3 action="no-such-program --now"
+        hold fingers=4 action="sh -c true""#,
+    )
+    .unwrap();
+    // notify-send may or may not be installed where the tests run
+    let missing = c.missing_programs();
+    assert_eq!(missing[0], ("no-such-program --now", "no-such-program"));
+    assert!(missing.iter().all(|(command, _)| *command != "sh -c true"));
+    let mut cmd = std::process::Command::new("sh");
+    cmd.arg("-c").arg("no-such-program --now");
+    let fallback = missing_command_fallback(c.on_missing_command.as_deref().unwrap(), &cmd);
+    assert_eq!(fallback.get_program(), "sh");
+    assert_eq!(
+        fallback.get_args().collect::<Vec<_>>(),
+        [
+            "-c",
+            r#"notify-send "Not installed: $1""#,
+            "sh",
+            "no-such-program --now"
+        ]
+    );
+}
+
+#[test]
+fn test_on_missing_command() {
+    let out = std::env::temp_dir().join(format!("gestures-missing-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"on-missing-command "printf %s \"$1\" > {}"
+            hold fingers=3 action="no-such-program 'a;b' \"$(echo c)\"""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    run_gestures(c, &[InjectGesture::Hold { fingers: 3 }]);
+    std::thread::sleep(Duration::from_millis(200));
+    // The failed command is handed over as it is, the shell of the fallback doesn't run any of it
+    assert_eq!(
+        std::fs::read_to_string(&out).unwrap(),
+        r#"no-such-program 'a;b' "$(echo c)""#
+    );
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn test_angle_scale() {
+    let out = std::env::temp_dir().join(format!("gestures-angle-scale-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"pinch direction="clockwise" fingers=2 angle-scale=3.0 max-repeats=1 update="echo $delta_angle >> {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    assert!(dump(&c).contains("angle-scale = 3.0"));
+    // Each update of the injected pinch rotates it by 2 degrees
+    run_gestures(
+        c,
+        &[InjectGesture::Pinch {
+            fingers: 2,
+            dir: PinchDir::Clockwise,
+        }],
+    );
+    std::thread::sleep(Duration::from_millis(200));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "6\n");
+}
+
+#[test]
+fn test_lint() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 end="a"
+        swipe direction="n" fingers=3 end="b"
+        swipe direction="n" fingers=4 end="c"
+        swipe direction="s" fingers=4 end="d" fallback=true
+        swipe direction="n" fingers=3 end="e" fallback=true
+        pinch direction="in" fingers=3 end="f"
+        hold fingers=3 region="top" action="g"
+        hold fingers=3 region="bottom" action="h"
+        hold fingers=3 action="i"
+        swipe direction="any" fingers=4 angle=30.0 end="j"
+        swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=500
+        swipe direction="any" fingers=4 end="k" drag-threshold=30.0"#,
+    )
+    .unwrap();
+    let lints = lint(&c);
+    assert_eq!(
+        lints,
+        vec![
+            Lint::Overlap { rule: 1, with: 0 },
+            Lint::Shadowed { rule: 4, by: 0 },
+            Lint::Overlap { rule: 8, with: 6 },
+        ]
+    );
+    assert_eq!(
+        lints[1].message(&c.gestures),
+        "rule 4 (swipe n, 3 fingers) never fires: it is a fallback and rule 0 (swipe any, 3 \
+         fingers) matches every gesture it does"
+    );
+}
+
+#[test]
+fn test_repeat_key() {
+    let config = || {
+        Config::parse_str(
+            "test.kdl",
+            r#"swipe direction="s" fingers=3 repeat-key="Down""#,
+        )
+        .unwrap()
+    };
+    let c = config();
+    let Gesture::Swipe(s) = &c.gestures[0] else {
+        unreachable!()
+    };
+    assert_eq!(s.repeat_key.as_deref(), Some("Down"));
+    assert!(c.gestures[0].needs_xdo());
+    assert!(dump(&c).contains(r#"repeat-key = "Down""#));
+    // Without Xorg there is no key to hold, the swipe still goes through
+    let stats = run_gestures(
+        c,
+        &[InjectGesture::Swipe {
+            fingers: 3,
+            dir: SwipeDir::S,
+        }],
+    );
+    assert_eq!(stats.count(0), 1);
+
+    let (xdoh, rx) = start_recording();
+    let mut h = Handler::with_xdo(config(), xdoh);
+    let keys = |rx: &Receiver<XDoCommand>| {
+        rx.try_iter()
+            .filter_map(|command| match command {
+                XDoCommand::KeyDown(keys) => Some(format!("down {keys}")),
+                XDoCommand::KeyUp(keys) => Some(format!("up {keys}")),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    // Held while the swipe goes down, released once it turns away
+    h.dispatch([
+        GestureInput::SwipeBegin { fingers: 3 },
+        GestureInput::SwipeUpdate { dx: 0.0, dy: 20.0 },
+    ]);
+    assert_eq!(keys(&rx), ["down Down"]);
+    h.dispatch([GestureInput::SwipeUpdate { dx: 0.0, dy: 20.0 }]);
+    assert!(keys(&rx).is_empty());
+    h.dispatch([GestureInput::SwipeUpdate {
+        dx: 0.0,
+        dy: -100.0,
+    }]);
+    assert_eq!(keys(&rx), ["up Down"]);
+    // Or once the fingers lift
+    h.dispatch([GestureInput::SwipeUpdate { dx: 0.0, dy: 200.0 }]);
+    assert_eq!(keys(&rx), ["down Down"]);
+    h.dispatch([GestureInput::SwipeEnd {
+        fingers: None,
+        cancelled: false,
+    }]);
+    assert_eq!(keys(&rx), ["up Down"]);
+}
+
+#[test]
+fn test_idle_timeout() {
+    let out = std::env::temp_dir().join(format!("gestures-idle-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"swipe direction="e" fingers=3 idle-timeout=100 update="echo x >> {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    assert!(dump(&c).contains("idle-timeout = 100"));
+    let mut h = Handler::new(c);
+    let update = |dx| GestureInput::SwipeUpdate { dx, dy: 0.0 };
+    h.dispatch([
+        GestureInput::SwipeBegin { fingers: 3 },
+        update(10.0),
+        update(10.0),
+    ]);
+    std::thread::sleep(Duration::from_millis(150));
+    // The fingers rested too long, moving again doesn't bring the updates back
+    h.dispatch([update(0.1), update(10.0)]);
+    std::thread::sleep(Duration::from_millis(200));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "x\nx\n");
+}
+
+#[test]
+fn test_coalesce_updates() {
+    let out = std::env::temp_dir().join(format!("gestures-coalesce-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            "coalesce-updates 500\nswipe direction=\"e\" fingers=3 update=\"echo $delta_x >> {}\"",
+            out.display()
+        ),
+    )
+    .unwrap();
+    assert!(dump(&c).contains("coalesce-updates = 500"));
+    let mut h = Handler::new(c);
+    let [begin, update, end] = swipe_east(3, 10.0);
+    h.dispatch([begin]);
+    for _ in 0..3 {
+        h.dispatch([update.clone()]);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    // The first update runs right away, the end lets the two held back ones through as one
+    h.dispatch([end]);
+    std::thread::sleep(Duration::from_millis(200));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "10\n20\n");
+}
+
+#[test]
+fn test_hotkey() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="n" fingers=4 hotkey="super+Up" end="true""#,
+    )
+    .unwrap();
+    assert_eq!(c.gestures[0].hotkey(), Some("super+Up"));
+    assert!(dump(&c).contains(r#"hotkey = "super+Up""#));
+    let (mask, key) = hotkey::parse("Ctrl+alt+Left").unwrap();
+    assert_eq!(mask, x11::xlib::ControlMask | x11::xlib::Mod1Mask);
+    assert_eq!(key, "Left");
+    assert_eq!(hotkey::parse("F5"), Ok((0, "F5")));
+    assert!(hotkey::parse("ctrl+").is_err());
+    let err = Config::parse_str(
+        "test.kdl",
+        r#"hold fingers=3 hotkey="hyper+x" action="true""#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("unknown modifier"));
+
+    let rules = r#"
+        swipe direction="n" fingers=4 hotkey="super+Up" end="true"
+        hold fingers=3 hotkey="super+h" action="true" when-tablet-mode=true
+        "#;
+    let mut h = Handler::new(Config::parse_str("test.kdl", rules).unwrap());
+    let hotkey: GestureInput = "hotkey 0".parse().unwrap();
+    assert_eq!(hotkey, GestureInput::Hotkey { rule: 0 });
+    h.dispatch([hotkey, GestureInput::Hotkey { rule: 1 }]);
+    // Like its gesture, the second rule only fires in tablet mode
+    assert_eq!(h.counts(), [1, 0]);
+    let with_dispatcher = format!("dispatcher \"true\"\n{rules}");
+    let mut h = Handler::new(Config::parse_str("test.kdl", &with_dispatcher).unwrap());
+    h.dispatch([GestureInput::Hotkey { rule: 0 }]);
+    assert_eq!(h.counts(), [0, 0]);
+}
+
+#[test]
+fn test_hotkey_confirm() {
+    let out = std::env::temp_dir().join(format!("gestures-hotkey-confirm-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"hold fingers=4 hotkey="super+h" confirm=5000 serialize=true action="touch {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    // The hotkey arms the rule like its gesture, nothing runs yet
+    h.dispatch([GestureInput::Hotkey { rule: 0 }]);
+    assert_eq!(h.counts(), [1]);
+    assert!(h.eh.serial(0).is_none());
+    h.dispatch([
+        GestureInput::HoldBegin { fingers: 2 },
+        GestureInput::HoldEnd { cancelled: false },
+    ]);
+    h.eh.serial(0).unwrap().wait();
+    assert!(out.exists());
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn test_pinch_fingers() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        pinch direction="in" fingers=2 end=""
+        pinch direction="in" fingers=3 end=""
+        pinch direction="in" fingers=4 end=""
+        pinch direction="out" fingers=4 end=""
+        "#,
+    )
+    .unwrap();
+    let pinch = |fingers, dir| InjectGesture::Pinch { fingers, dir };
+    let stats = run_gestures(
+        c,
+        &[
+            pinch(3, PinchDir::In),
+            pinch(4, PinchDir::In),
+            pinch(4, PinchDir::Out),
+            pinch(4, PinchDir::In),
+        ],
+    );
+    // Each finger count only fires its own rules
+    let counts: Vec<u64> = (0..4).map(|i| stats.count(i)).collect();
+    assert_eq!(counts, [0, 1, 2, 1]);
+}
+
+#[test]
+fn test_progress() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="e" fingers=3 progress-max=20.0 update="notify-send -h int:value:{progress} Switching"
+        swipe direction="w" fingers=3 update=""
+        pinch direction="out" fingers=2 update="""#,
+    )
+    .unwrap();
+    assert!(dump(&c).contains("progress-max = 20.0"));
+    let (Gesture::Swipe(swipe), Gesture::Swipe(default), Gesture::Pinch(pinch)) =
+        (&c.gestures[0], &c.gestures[1], &c.gestures[2])
+    else {
+        unreachable!()
+    };
+    assert_eq!(swipe.progress(10.0), 50.0);
+    assert_eq!(swipe.progress(20.0), 100.0);
+    assert_eq!(swipe.progress(30.0), 100.0);
+    assert_eq!(default.progress(30.0), 10.0);
+    assert_eq!(pinch.progress(1.25), 50.0);
+    assert_eq!(pinch.progress(0.5), 100.0);
+    assert_eq!(pinch.progress(3.0), 100.0);
+    assert_eq!(
+        substitute_progress(swipe.update.as_deref().unwrap(), swipe.progress(10.0)),
+        "notify-send -h int:value:50 Switching"
+    );
+
+    let invalid = r#"swipe direction="e" fingers=3 progress-max=0.0 update="""#;
+    assert!(Config::parse_str("test.kdl", invalid).is_err());
+}
+
+#[test]
+fn test_serialize() {
+    let out = std::env::temp_dir().join(format!("gestures-serialize-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"swipe direction="e" fingers=3 serialize=true update="sleep $delta_x; echo $delta_x >> {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    assert!(dump(&c).contains("serialize = true"));
+    let mut h = Handler::new(c);
+    h.dispatch([GestureInput::SwipeBegin { fingers: 3 }]);
+    // Run at once, the shorter sleeps would finish first
+    h.dispatch([0.3, 0.2, 0.1].map(|dx| GestureInput::SwipeUpdate { dx, dy: 0.0 }));
+    h.eh.serial(0).unwrap().wait();
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "0.3\n0.2\n0.1\n");
+}
+
+#[test]
+fn test_serialize_all_commands() {
+    let out = std::env::temp_dir().join(format!("gestures-serialize-all-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"
+            swipe direction="e" fingers=3 serialize=true overshoot-distance=50.0 overshoot="sleep 0.3; echo overshoot >> {0}" end="echo end >> {0}"
+            hold fingers=3 serialize=true action="echo action >> {0}" {{
+                cycle "sleep 0.3; echo cycle >> {0}"
+            }}
+            "#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    // The overshoot and the end of the same swipe, the overshoot would finish last at once
+    let [begin, update, end] = swipe_east(3, 30.0);
+    h.dispatch([begin, update.clone(), update, end]);
+    h.eh.serial(0).unwrap().wait();
+    // The cycle and the action of a hold
+    h.dispatch([
+        GestureInput::HoldBegin { fingers: 3 },
+        GestureInput::HoldEnd { cancelled: false },
+    ]);
+    h.eh.serial(1).unwrap().wait();
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "overshoot\nend\ncycle\naction\n");
+}
+
+#[test]
+fn test_screenshot() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        screenshot-command "import -window root -crop \"$1\" shot.png"
+        hold fingers=4 screenshot="window"
+        swipe direction="s" fingers=4 screenshot="region"
+        "#,
+    )
+    .unwrap();
+    let toml = dump(&c);
+    assert!(toml.contains(r#"screenshot = "window""#));
+    assert!(toml.contains(r#"screenshot-command = 'import -window root -crop "$1" shot.png'"#));
+    let Gesture::Hold(hold) = &c.gestures[0] else {
+        panic!("not a hold");
+    };
+    assert_eq!(hold.screenshot, Some(Screenshot::Window));
+    // Only the window and the full screen are looked up through libxdo
+    assert!(c.gestures[0].needs_xdo());
+    assert!(!c.gestures[1].needs_xdo());
+    let window = Geometry {
+        x: 10,
+        y: 20,
+        width: 800,
+        height: 600,
+    };
+    let cmd = screenshot::command(
+        c.screenshot_command.as_deref().unwrap(),
+        &window.to_string(),
+    );
+    assert_eq!(cmd.get_program(), "sh");
+    assert_eq!(
+        cmd.get_args().collect::<Vec<_>>(),
+        [
+            "-c",
+            r#"import -window root -crop "$1" shot.png"#,
+            "sh",
+            "800x600+10+20"
+        ]
+    );
+}
+
+#[test]
+fn test_take_screenshot() {
+    let out = std::env::temp_dir().join(format!("gestures-screenshot-{}", std::process::id()));
+    let (xdoh, commands) = start_recording();
+    // Only the window is found, the full screen is not
+    std::thread::spawn(move || {
+        for command in commands {
+            if let XDoCommand::WindowGeometry(reply) = command {
+                let _ = reply.send(Some(Geometry {
+                    x: 10,
+                    y: 20,
+                    width: 800,
+                    height: 600,
+                }));
+            }
+        }
+    });
+    let template = format!(r#"printf %s "$1" > {}"#, out.display());
+    let runner = Runner::default();
+    assert!(Screenshot::Full
+        .take(Some(&template), &runner, &xdoh)
+        .is_none());
+    let taking = Screenshot::Window.take(Some(&template), &runner, &xdoh);
+    taking.expect("the window was found").join().unwrap();
+    assert_eq!(std::fs::read_to_string(&out).unwrap(), "800x600+10+20");
+    std::fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn test_drag_threshold() {
+    let counts = |drag: &str| {
+        let c = Config::parse_str(
+            "test.kdl",
+            &format!(
+                r#"
+                swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=0 {drag}
+                swipe direction="n" fingers=3 end=""
+                "#
+            ),
+        )
+        .unwrap();
+        let (xdoh, _commands) = start_recording();
+        let mut h = Handler::with_xdo(c, xdoh);
+        h.inject(&[InjectGesture::Swipe {
+            fingers: 3,
+            dir: SwipeDir::N,
+        }]);
+        h.counts()
+    };
+    // Only a drag that waited for its threshold takes the swipe from the directional rule
+    assert_eq!(counts(""), [1, 1]);
+    assert_eq!(counts("drag-threshold=10.0"), [1, 0]);
+    assert_eq!(counts("drag-threshold=1000.0"), [0, 1]);
+}
+
+#[test]
+fn test_overshoot() {
+    let out = std::env::temp_dir().join(format!("gestures-overshoot-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"swipe direction="e" fingers=3 overshoot-distance=50.0 overshoot="echo $delta_x >> {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let [begin, update, end] = swipe_east(3, 30.0);
+    h.dispatch([begin, update.clone(), update.clone(), update, end]);
+    std::thread::sleep(Duration::from_millis(200));
+    // Only once, as soon as the swipe got past the distance
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "60\n");
+}
+
+#[test]
+fn test_max_repeats() {
+    let out = std::env::temp_dir().join(format!("gestures-max-repeats-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"swipe direction="e" fingers=3 max-repeats=2 update="echo x >> {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let [begin, update, end] = swipe_east(3, 10.0);
+    h.dispatch([begin]);
+    h.dispatch(std::iter::repeat_n(update, 5));
+    h.dispatch([end]);
+    std::thread::sleep(Duration::from_millis(200));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "x\nx\n");
+}
+
+#[test]
+fn test_pause() {
+    let (mut client, server) = UnixStream::pair().unwrap();
+    let paused = Arc::new(AtomicBool::new(false));
+    let config = Arc::new(RwLock::new(Config::default()));
+    client
+        .write_all(b"status\npause\nstatus\nresume\nstatus\n")
+        .unwrap();
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    handle_connection(
+        server,
+        config,
+        Default::default(),
+        paused.clone(),
+        Default::default(),
+        true,
+    );
+    let mut replies = String::new();
+    client.read_to_string(&mut replies).unwrap();
+    assert_eq!(replies, "running\npaused\nrunning\n");
+    assert!(!paused.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_inject_paused() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        activation-key "ctrl"
+        swipe direction="e" fingers=3 end=""
+        "#,
+    )
+    .unwrap();
+    let config = Arc::new(RwLock::new(c));
+    let stats: Arc<RwLock<Stats>> = Default::default();
+    let paused = Arc::new(AtomicBool::new(true));
+    let swipe: String = swipe_east(3, 5.0)
+        .iter()
+        .map(|event| format!("inject {event}\n"))
+        .collect();
+    let (mut client, server) = UnixStream::pair().unwrap();
+    // Ignored while paused, then acted on without holding the activation key
+    client
+        .write_all(format!("{swipe}resume\n{swipe}").as_bytes())
+        .unwrap();
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+    handle_connection(
+        server,
+        config,
+        stats.clone(),
+        paused,
+        Default::default(),
+        true,
+    );
+    let mut replies = String::new();
+    client.read_to_string(&mut replies).unwrap();
+    assert_eq!(replies, "gestures are paused\n".repeat(3));
+    assert_eq!(stats.read().count(0), 1);
+}
+
+#[test]
+fn test_pause_releases_drag() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=500"#,
+    )
+    .unwrap();
+    let (xdoh, commands) = start_recording();
+    let mut h = Handler::with_xdo(c, xdoh);
+    h.dispatch([GestureInput::SwipeBegin { fingers: 3 }]);
+    assert!(matches!(commands.try_recv(), Ok(XDoCommand::MouseDown(1))));
+    // Let go of right away once the pause is noticed, while the fingers are still down
+    h.paused.store(true, Ordering::Relaxed);
+    h.eh.flush_pending(&mut h.xdoh).unwrap();
+    let released = commands.recv_timeout(Duration::from_millis(200));
+    assert!(matches!(released, Ok(XDoCommand::MouseUp(1))));
+}
+
+#[test]
+fn test_hold_modifiers() {
+    let out = std::env::temp_dir().join(format!("gestures-hold-mods-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"hold fingers=3 hold-modifiers="ctrl" action="touch {}""#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let (xdoh, commands) = start_recording();
+    let mut h = Handler::with_xdo(c, xdoh);
+    h.inject(&[InjectGesture::Hold { fingers: 3 }]);
+    let mut keys = Vec::new();
+    while let Ok(command) = commands.recv_timeout(Duration::from_secs(1)) {
+        match command {
+            XDoCommand::KeyDown(k) => keys.push(format!("down {k}")),
+            XDoCommand::KeyUp(k) => {
+                // Only released once the command is done
+                assert!(out.exists());
+                keys.push(format!("up {k}"));
+                break;
+            }
+            _ => (),
+        }
+    }
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(keys, ["down ctrl", "up ctrl"]);
+}
+
+#[test]
+fn test_default_actions() {
+    let out = std::env::temp_dir().join(format!("gestures-defaults-{}", std::process::id()));
+    let c = Config::parse_str(
+        "test.kdl",
+        &format!(
+            r#"
+            default-swipe-action "echo swipe >> {0}"
+            default-hold-action "echo hold >> {0}"
+            swipe direction="n" fingers=3 end=""
+            "#,
+            out.display()
+        ),
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let swipe = |dir| InjectGesture::Swipe { fingers: 3, dir };
+    // The swipe to the north has its rule, the others fall through to the defaults
+    for gesture in [
+        swipe(SwipeDir::N),
+        swipe(SwipeDir::S),
+        InjectGesture::Hold { fingers: 4 },
+    ] {
+        h.inject(&[gesture]);
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    // Cancelled swipes are left alone
+    let [begin, update, _] = swipe_east(3, 10.0);
+    let cancel = GestureInput::SwipeEnd {
+        fingers: None,
+        cancelled: true,
+    };
+    h.dispatch([begin, update, cancel]);
+    std::thread::sleep(Duration::from_millis(200));
+    let written = std::fs::read_to_string(&out).unwrap();
+    std::fs::remove_file(&out).unwrap();
+    assert_eq!(written, "swipe\nhold\n");
+}
+
+#[test]
+fn test_max_duration() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="e" fingers=3 max-duration=100 end="""#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    h.dispatch(swipe_east(3, 50.0));
+    // Too slow the second time
+    let [begin, update, end] = swipe_east(3, 50.0);
+    h.dispatch([begin, update]);
+    std::thread::sleep(Duration::from_millis(150));
+    h.dispatch([end]);
+    assert_eq!(h.counts(), [1]);
+}
+
+#[test]
+fn test_center_cursor() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        hold fingers=3 center-cursor=true
+        swipe direction="e" fingers=3 center-cursor=true end=""
+        "#,
+    )
+    .unwrap();
+    let (xdoh, commands) = start_recording();
+    let mut h = Handler::with_xdo(c, xdoh);
+    h.inject(&[InjectGesture::Hold { fingers: 3 }]);
+    h.dispatch(swipe_east(3, 50.0));
+    let centered = commands
+        .try_iter()
+        .filter(|c| matches!(c, XDoCommand::CenterCursor))
+        .count();
+    assert_eq!(centered, 2);
+}
+
+#[test]
+fn test_drag_delay() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=0 drag-delay=100"#,
+    )
+    .unwrap();
+    let (xdoh, commands) = start_recording();
+    let mut h = Handler::with_xdo(c, xdoh);
+    let [begin, update, _] = swipe_east(3, 10.0);
+    h.dispatch([begin, update.clone()]);
+    let pressed = |commands: &std::sync::mpsc::Receiver<_>| {
+        commands
+            .try_iter()
+            .any(|c| matches!(c, XDoCommand::MouseDown(1)))
+    };
+    assert!(!pressed(&commands));
+    std::thread::sleep(Duration::from_millis(150));
+    h.dispatch([update]);
+    assert!(pressed(&commands));
+}
+
+#[test]
+fn test_drag_release() {
+    let drag = r#"swipe direction="any" fingers=3 acceleration=20 mouse-up-delay=500"#;
+    let c = Config::parse_str("test.kdl", drag).unwrap();
+    let (xdoh, commands) = start_recording();
+    let mut h = Handler::with_xdo(c, xdoh);
+    let released = |commands: &std::sync::mpsc::Receiver<_>| {
+        std::thread::sleep(Duration::from_millis(100));
+        commands
+            .try_iter()
+            .any(|c| matches!(c, XDoCommand::MouseUp(1)))
+    };
+    // A swipe whose end got lost lets go of the button before the next one presses it again
+    let [begin, update, end] = swipe_east(3, 10.0);
+    h.dispatch([begin.clone(), update.clone()]);
+    assert!(!released(&commands));
+    h.dispatch([begin]);
+    assert!(released(&commands));
+    // A reload that leaves no rule to end the drag still lets go of it at the end of the swipe
+    let mut fresh = Config::parse_str("test.kdl", "").unwrap();
+    fresh.reloads = h.config.read().reloads + 1;
+    *h.config.write() = fresh;
+    h.dispatch([update, end]);
+    assert!(released(&commands));
+}
+
+#[test]
+fn test_cancelled_swipe() {
+    let c = Config::parse_str(
+        "test.kdl",
+        r#"
+        swipe direction="e" fingers=3 update="true" end=""
+        swipe direction="e" fingers=3 end=""
+        "#,
+    )
+    .unwrap();
+    let mut h = Handler::new(c);
+    let [begin, update, end] = swipe_east(3, 50.0);
+    let cancel = GestureInput::SwipeEnd {
+        fingers: None,
+        cancelled: true,
+    };
+    // Not even the rule whose updates ran ends a cancelled swipe
+    h.dispatch([begin.clone(), update.clone(), cancel]);
+    assert_eq!(h.counts(), [0, 0]);
+    h.dispatch([begin, update, end]);
+    assert_eq!(h.counts(), [1, 1]);
+}
+
+#[test]
+fn test_heartbeat() {
+    let c = Config::parse_str("test.kdl", r#"hold fingers=3 action="""#).unwrap();
+    let mut h = Handler::new(c);
+    assert_eq!(h.eh.due_heartbeat(), None);
+    h.eh.set_heartbeat(Duration::from_secs(60));
+    assert_eq!(h.eh.due_heartbeat(), None);
+    h.eh.set_heartbeat(Duration::ZERO);
+    h.dispatch([
+        GestureInput::HoldBegin { fingers: 3 },
+        GestureInput::HoldEnd { cancelled: false },
+    ]);
+    assert_eq!(
+        h.eh.due_heartbeat().unwrap(),
+        "Heartbeat: polling, 2 gesture events in the last 0ns"
+    );
+    h.paused.store(true, Ordering::Relaxed);
+    assert_eq!(
+        h.eh.due_heartbeat().unwrap(),
+        "Heartbeat: paused, 0 gesture events in the last 0ns"
+    );
+    // A heartbeat every 0 seconds would spin the main loop
+    assert!(App::try_parse_from(["gestures", "start", "--heartbeat", "0"]).is_err());
+    assert!(App::try_parse_from(["gestures", "start", "--heartbeat", "30"]).is_ok());
+}
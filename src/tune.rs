@@ -0,0 +1,188 @@
+use std::{fmt, str::FromStr};
+
+use knuffel::{
+    ast::{Literal, TypeName},
+    decode::{Context, Kind},
+    errors::DecodeError,
+    span::Spanned,
+    traits::ErrorSpan,
+    DecodeScalar,
+};
+use schemars::{gen::SchemaGenerator, schema::Schema, JsonSchema};
+use serde::{Serialize, Serializer};
+
+use crate::config::Config;
+use crate::gestures::Gesture;
+
+/// Config values a rule can change while the program runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Setting {
+    /// `acceleration` of every drag rule
+    Acceleration,
+    SwipeHysteresis,
+    FingerTolerance,
+}
+
+impl Setting {
+    /// Values the setting is kept within
+    fn bounds(self) -> (f64, f64) {
+        match self {
+            Setting::Acceleration => (1.0, i8::MAX.into()),
+            // Beyond half the width of a direction, a swipe could never change direction
+            Setting::SwipeHysteresis => (0.0, 22.5),
+            Setting::FingerTolerance => (0.0, 3.0),
+        }
+    }
+}
+
+impl Setting {
+    /// Whether the value may have a fraction, `acceleration` is a whole number of tenths
+    fn fractional(self) -> bool {
+        self == Setting::SwipeHysteresis
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Setting::Acceleration => write!(f, "acceleration"),
+            Setting::SwipeHysteresis => write!(f, "swipe-hysteresis"),
+            Setting::FingerTolerance => write!(f, "finger-tolerance"),
+        }
+    }
+}
+
+/// A change of a config value made by a rule as it fires, e.g. `acceleration +2` or
+/// `swipe-hysteresis 10`. Lasts until the config is reloaded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tune {
+    pub setting: Setting,
+    /// Added to the current value if `relative`, replaces it otherwise
+    pub value: f64,
+    pub relative: bool,
+}
+
+impl Tune {
+    /// Change the value in `config`, keeping it within the bounds of the setting
+    pub fn apply(&self, config: &mut Config) {
+        let (min, max) = self.setting.bounds();
+        let tuned = |current: f64| {
+            let value = if self.relative {
+                current + self.value
+            } else {
+                self.value
+            };
+            value.clamp(min, max)
+        };
+        match self.setting {
+            Setting::Acceleration => {
+                let drags = config.gestures.iter_mut().filter_map(|g| match g {
+                    Gesture::Swipe(s) if s.is_drag() => Some(s),
+                    _ => None,
+                });
+                for swipe in drags {
+                    let current = swipe.acceleration.unwrap_or_default();
+                    swipe.acceleration = Some(tuned(current.into()).round() as i8);
+                }
+            }
+            Setting::SwipeHysteresis => {
+                let current = config.swipe_hysteresis.unwrap_or_default();
+                config.swipe_hysteresis = Some(tuned(current));
+            }
+            Setting::FingerTolerance => {
+                let current = config.finger_tolerance.unwrap_or_default();
+                config.finger_tolerance = Some(tuned(current.into()).round() as u32);
+            }
+        }
+        log::info!("Tuned {self}");
+    }
+}
+
+impl FromStr for Tune {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((name, value)) = s.trim().split_once(' ') else {
+            return Err(format!("expected a setting and a value, got {s:?}"));
+        };
+        let setting = match name {
+            "acceleration" => Setting::Acceleration,
+            "swipe-hysteresis" => Setting::SwipeHysteresis,
+            "finger-tolerance" => Setting::FingerTolerance,
+            _ => {
+                return Err(format!(
+                    "unknown setting {name:?}, expected acceleration, swipe-hysteresis or \
+                     finger-tolerance"
+                ))
+            }
+        };
+        let value = value.trim();
+        let relative = value.starts_with(['+', '-']);
+        let value: f64 = value
+            .parse()
+            .map_err(|_| format!("expected a number or a +/- step for {name}, got {value:?}"))?;
+        if !setting.fractional() && value.fract() != 0.0 {
+            return Err(format!("{name} only takes whole numbers, got {value}"));
+        }
+        Ok(Self {
+            setting,
+            value,
+            relative,
+        })
+    }
+}
+
+impl<S: ErrorSpan> DecodeScalar<S> for Tune {
+    fn type_check(type_name: &Option<Spanned<TypeName, S>>, ctx: &mut Context<S>) {
+        <String as DecodeScalar<S>>::type_check(type_name, ctx)
+    }
+
+    fn raw_decode(
+        value: &Spanned<Literal, S>,
+        ctx: &mut Context<S>,
+    ) -> Result<Self, DecodeError<S>> {
+        match &**value {
+            Literal::String(s) => s.parse().map_err(|e| DecodeError::conversion(value, e)),
+            _ => {
+                ctx.emit_error(DecodeError::scalar_kind(Kind::String, value));
+                Ok(Self {
+                    setting: Setting::Acceleration,
+                    value: 0.0,
+                    relative: true,
+                })
+            }
+        }
+    }
+}
+
+/// Written the way it is parsed
+/// Written the way the config spells it, e.g. `"acceleration +2"`
+impl Serialize for Tune {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl JsonSchema for Tune {
+    fn is_referenceable() -> bool {
+        false
+    }
+
+    fn schema_name() -> String {
+        "Tune".to_string()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        String::json_schema(gen)
+    }
+}
+
+impl fmt::Display for Tune {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.relative {
+            write!(f, "{} {:+}", self.setting, self.value)
+        } else {
+            write!(f, "{} {}", self.setting, self.value)
+        }
+    }
+}
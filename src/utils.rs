@@ -1,28 +1,429 @@
 use miette::Result;
+use nix::unistd::User;
 use regex::Regex;
-use std::process::Command;
-
-pub fn exec_command_from_string(args: &str, dx: f64, dy: f64, da: f64, scale: f64) -> Result<()> {
-    if !&args.is_empty() {
-        let args = args.to_string();
-        std::thread::spawn(move || {
-            let rx = Regex::new(r"[^\\]\$delta_x").unwrap();
-            let ry = Regex::new(r"[^\\]\$delta_y").unwrap();
-            let rs = Regex::new(r"[^\\]\$scale").unwrap();
-            let ra = Regex::new(r"[^\\]\$delta_angle").unwrap();
-            let args = ry.replace_all(&args, format!(" {dy} "));
-            let args = rx.replace_all(&args, format!(" {dx} "));
-            let args = rs.replace_all(&args, format!(" {scale} "));
-            let args = ra.replace_all(&args, format!(" {da} "));
-            log::debug!("{:?}", &args);
-            Command::new("sh")
-                .arg("-c")
-                .arg(&*args)
-                .spawn()
-                .unwrap()
-                .wait()
-                .unwrap();
+use std::env;
+use std::io::{ErrorKind, Read};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use crate::dbus::DBusCall;
+use crate::xdo_handler::HeldKeys;
+
+/// How the commands of the rules run, from the `run-as` and `on-missing-command` options
+#[derive(Debug, Clone, Default)]
+pub struct Runner {
+    /// User to run them as instead of the daemon's own
+    pub run_as: Option<String>,
+    /// Run when a command can't run because its program is not installed, with that command in
+    /// `$1`
+    pub on_missing_command: Option<String>,
+}
+
+pub fn exec_command_from_string(
+    args: &str,
+    dx: f64,
+    dy: f64,
+    da: f64,
+    scale: f64,
+    runner: &Runner,
+    held_keys: Option<HeldKeys>,
+) -> Result<()> {
+    // Rules usually only set some of start/update/end, don't spawn a shell for the others
+    if args.trim().is_empty() {
+        log::trace!("No command to run");
+        return Ok(());
+    }
+    spawn_command(args, (dx, dy, da, scale), runner, held_keys, None, None);
+    Ok(())
+}
+
+/// Run the commands of a rule with `serialize` one after the other, in the order they were queued,
+/// on a thread of its own. The thread exits once every clone is dropped and the queue is done.
+#[derive(Debug, Clone)]
+pub struct Serial(mpsc::Sender<Box<dyn FnOnce() + Send>>);
+
+impl Serial {
+    pub fn new() -> Self {
+        let (tx, rx) = mpsc::channel::<Box<dyn FnOnce() + Send>>();
+        std::thread::spawn(move || rx.into_iter().for_each(|job| job()));
+        Self(tx)
+    }
+
+    /// Wait until the jobs queued so far are done
+    #[cfg(test)]
+    pub fn wait(&self) {
+        let (tx, rx) = mpsc::channel();
+        let done: Box<dyn FnOnce() + Send> = Box::new(move || {
+            let _ = tx.send(());
+        });
+        if self.0.send(done).is_ok() {
+            let _ = rx.recv();
+        }
+    }
+}
+
+/// Run `job` on a thread of its own, or after the jobs queued before it on `serial`
+fn run_in(serial: Option<&Serial>, job: impl FnOnce() + Send + 'static) {
+    let job: Box<dyn FnOnce() + Send> = Box::new(job);
+    let job = match serial {
+        Some(serial) => match serial.0.send(job) {
+            Ok(()) => return,
+            // The queue's thread is gone, the command shouldn't be
+            Err(mpsc::SendError(job)) => job,
+        },
+        None => job,
+    };
+    std::thread::spawn(job);
+}
+
+/// Run a command like [`exec_command_from_string`], queued on `serial` if it is set
+pub fn exec_command_queued(
+    args: &str,
+    deltas: (f64, f64, f64, f64),
+    runner: &Runner,
+    held_keys: Option<HeldKeys>,
+    serial: Option<&Serial>,
+) -> Result<()> {
+    if args.trim().is_empty() {
+        log::trace!("No command to run");
+        return Ok(());
+    }
+    spawn_command(args, deltas, runner, held_keys, None, serial);
+    Ok(())
+}
+
+/// Run a pinch rule's `update` like [`exec_command_from_string`], unless the command `busy` was
+/// set for is still running. `busy` is set until the new command exits.
+pub fn exec_command_unless_busy(
+    args: &str,
+    da: f64,
+    scale: f64,
+    runner: &Runner,
+    held_keys: Option<HeldKeys>,
+    busy: &Arc<AtomicBool>,
+    serial: Option<&Serial>,
+) -> Result<()> {
+    if args.trim().is_empty() {
+        log::trace!("No command to run");
+        return Ok(());
+    }
+    if busy.swap(true, Ordering::AcqRel) {
+        log::debug!("Skipping {args:?}, the previous update is still running");
+        return Ok(());
+    }
+    let busy = Busy(busy.clone());
+    let deltas = (0.0, 0.0, da, scale);
+    spawn_command(args, deltas, runner, held_keys, Some(busy), serial);
+    Ok(())
+}
+
+/// Clears the flag of [`exec_command_unless_busy`] when dropped, after its command exited
+struct Busy(Arc<AtomicBool>);
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// Run `args` with the deltas substituted in the background, waiting for it to exit there
+fn spawn_command(
+    args: &str,
+    (dx, dy, da, scale): (f64, f64, f64, f64),
+    runner: &Runner,
+    held_keys: Option<HeldKeys>,
+    busy: Option<Busy>,
+    serial: Option<&Serial>,
+) {
+    let template = args.to_string();
+    let runner = runner.clone();
+    run_in(serial, move || {
+        let _busy = busy;
+        let args = substitute_deltas(&template, dx, dy, da, scale);
+        log_command(&template, &args);
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(&args);
+        // The keys are released when `held_keys` is dropped, whether the command succeeded or not
+        let _held_keys = held_keys.map(|mut keys| {
+            keys.press();
+            keys
         });
+        run_command(cmd, &runner);
+    });
+}
+
+/// Run `args` with `env` added to its environment, for the `dispatcher`
+pub fn exec_command_with_env(args: &str, env: Vec<(&'static str, String)>, runner: &Runner) {
+    let args = args.to_string();
+    let runner = runner.clone();
+    std::thread::spawn(move || {
+        log::debug!("Running {args:?} with {env:?}");
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(&args).envs(env);
+        run_command(cmd, &runner);
+    });
+}
+
+/// How long [`exec_command_then`] waits for the output of a command by default
+pub const DEFAULT_OUTPUT_TIMEOUT_MS: u64 = 5000;
+
+/// Run a one-shot action. With `then`, the action's stdout is captured and `then` runs afterwards
+/// with `{output}` replaced by it, unless the action takes longer than `timeout_ms`. Both are
+/// queued on `serial` if it is set.
+pub fn exec_command_then(
+    args: &str,
+    then: Option<&str>,
+    timeout_ms: Option<u64>,
+    runner: &Runner,
+    held_keys: Option<HeldKeys>,
+    serial: Option<&Serial>,
+) -> Result<()> {
+    let Some(then) = then else {
+        return exec_command_queued(args, (0.0, 0.0, 0.0, 0.0), runner, held_keys, serial);
+    };
+    if args.trim().is_empty() {
+        log::trace!("No command to run");
+        return Ok(());
     }
+    let template = args.to_string();
+    let args = substitute_deltas(args, 0.0, 0.0, 0.0, 0.0);
+    let then = then.to_string();
+    let timeout = Duration::from_millis(timeout_ms.unwrap_or(DEFAULT_OUTPUT_TIMEOUT_MS));
+    let runner = runner.clone();
+    run_in(serial, move || {
+        log_command(&template, &args);
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(&args);
+        let output = {
+            let _held_keys = held_keys.map(|mut keys| {
+                keys.press();
+                keys
+            });
+            capture_output(cmd, &runner, timeout)
+        };
+        if let Some(output) = output {
+            log::debug!("{args:?} printed {output:?}");
+            let command = then.replace("{output}", output.trim_end());
+            log_command(&then, &command);
+            let mut cmd = Command::new("sh");
+            cmd.arg("-c").arg(&command);
+            run_command(cmd, &runner);
+        }
+    });
     Ok(())
 }
+
+/// Log the command that is about to run, and the rule's command it was made from if that differs
+fn log_command(template: &str, command: &str) {
+    if template == command {
+        log::debug!("Running {command:?}");
+    } else {
+        log::debug!("Running {command:?}, substituted from {template:?}");
+    }
+}
+
+/// Replace `$delta_x`, `$delta_y`, `$delta_angle` and `$scale` in a command
+fn substitute_deltas(args: &str, dx: f64, dy: f64, da: f64, scale: f64) -> String {
+    let rx = Regex::new(r"[^\\]\$delta_x").unwrap();
+    let ry = Regex::new(r"[^\\]\$delta_y").unwrap();
+    let rs = Regex::new(r"[^\\]\$scale").unwrap();
+    let ra = Regex::new(r"[^\\]\$delta_angle").unwrap();
+    let args = ry.replace_all(args, format!(" {dy} "));
+    let args = rx.replace_all(&args, format!(" {dx} "));
+    let args = rs.replace_all(&args, format!(" {scale} "));
+    let args = ra.replace_all(&args, format!(" {da} "));
+    args.into_owned()
+}
+
+/// Replace `$value` in a command, for the pinch rules that map their scale onto a range. Unlike
+/// the other placeholders it is replaced in place, so that it can be followed by a unit like `%`.
+pub fn substitute_value(args: &str, value: f64) -> String {
+    let rv = Regex::new(r"(^|[^\\])\$value").unwrap();
+    rv.replace_all(args, format!("${{1}}{value}")).into_owned()
+}
+
+/// Replace `{progress}` in a command, how far a swipe or pinch got towards its `progress-max`
+pub fn substitute_progress(args: &str, progress: f64) -> String {
+    args.replace("{progress}", &progress.to_string())
+}
+
+/// Play `path` with `paplay` as audible feedback for a fired gesture
+pub fn play_sound(path: &str, runner: &Runner) {
+    let mut cmd = Command::new("paplay");
+    cmd.arg(path);
+    let runner = runner.clone();
+    std::thread::spawn(move || run_command(cmd, &runner));
+}
+
+/// Make the D-Bus method call of a rule
+pub fn call_dbus(call: &DBusCall, runner: &Runner) {
+    log::debug!("Calling {} on {}", call.method, call.destination);
+    let (call, run_as) = (call.clone(), runner.run_as.clone());
+    std::thread::spawn(move || {
+        if let Err(e) = call.call(run_as.as_deref()) {
+            log::error!(
+                "Could not call {} on {}: {e}",
+                call.method,
+                call.destination
+            );
+        }
+    });
+}
+
+/// Exit status of `sh` when it can't find the command it was asked to run
+const NOT_FOUND_STATUS: i32 = 127;
+
+/// Shell builtins and keywords that commands may start with, they don't have to be in `PATH`
+const SHELL_BUILTINS: &[&str] = &[
+    "!", ".", ":", "[", "case", "cd", "echo", "eval", "exec", "exit", "export", "false", "for",
+    "if", "kill", "printf", "read", "set", "test", "true", "unset", "until", "wait", "while",
+];
+
+/// The program `command` starts with if it can't be found in `PATH`. Only the first program of
+/// the command is looked at, and none at all if it starts with something only the shell knows.
+pub fn missing_program(command: &str) -> Option<&str> {
+    let program = command
+        .split_whitespace()
+        // Variable assignments in front of the program
+        .find(|word| {
+            !word
+                .split_once('=')
+                .is_some_and(|(name, _)| is_var_name(name))
+        })?;
+    if SHELL_BUILTINS.contains(&program) || program.contains(['$', '{', '(', '`', '"', '\'', '~']) {
+        return None;
+    }
+    let installed = if program.contains('/') {
+        Path::new(program).is_file()
+    } else {
+        env::var_os("PATH")
+            .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+    };
+    (!installed).then_some(program)
+}
+
+fn is_var_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Spawn `cmd`, optionally as another user, and wait for it to exit
+pub fn run_command(mut cmd: Command, runner: &Runner) {
+    if !set_user(&mut cmd, runner.run_as.as_deref()) {
+        return;
+    }
+    match cmd.spawn() {
+        Ok(mut child) => {
+            let status = child.wait();
+            if status.is_ok_and(|status| status.code() == Some(NOT_FOUND_STATUS)) {
+                on_missing_command(&cmd, runner);
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            log::error!("Could not run {cmd:?}: {e}");
+            on_missing_command(&cmd, runner);
+        }
+        Err(e) => log::error!("Could not run {cmd:?}: {e}"),
+    }
+}
+
+/// Run the `on-missing-command` for `cmd`, which failed because its program is not installed
+fn on_missing_command(cmd: &Command, runner: &Runner) {
+    let Some(fallback) = &runner.on_missing_command else {
+        return;
+    };
+    let mut cmd = missing_command_fallback(fallback, cmd);
+    log::debug!("Running {cmd:?}");
+    if !set_user(&mut cmd, runner.run_as.as_deref()) {
+        return;
+    }
+    match cmd.spawn() {
+        Ok(mut child) => {
+            let _ = child.wait();
+        }
+        Err(e) => log::error!("Could not run {cmd:?}: {e}"),
+    }
+}
+
+/// The shell running `fallback` with the command `cmd` runs in `$1`, which keeps the shell from
+/// reading anything in that command
+pub fn missing_command_fallback(fallback: &str, cmd: &Command) -> Command {
+    // Shell commands are what the rule says, anything else is spawned directly
+    let failed = if cmd.get_program() == "sh" {
+        cmd.get_args().last().unwrap_or_default()
+    } else {
+        cmd.get_program()
+    };
+    let mut fallback_cmd = Command::new("sh");
+    fallback_cmd.arg("-c").arg(fallback).arg("sh").arg(failed);
+    fallback_cmd
+}
+
+/// Like [`run_command`], but returns what `cmd` printed to stdout. The command is killed if it
+/// doesn't finish within `timeout`.
+pub fn capture_output(mut cmd: Command, runner: &Runner, timeout: Duration) -> Option<String> {
+    if !set_user(&mut cmd, runner.run_as.as_deref()) {
+        return None;
+    }
+    let mut child = match cmd.stdout(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::error!("Could not run {cmd:?}: {e}");
+            return None;
+        }
+    };
+    let mut stdout = child.stdout.take()?;
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        let _ = tx.send(output);
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(output) => {
+            let _ = child.wait();
+            Some(output)
+        }
+        Err(_) => {
+            log::warn!("{cmd:?} did not finish within {timeout:?}, killing it");
+            let _ = child.kill();
+            let _ = child.wait();
+            None
+        }
+    }
+}
+
+/// Make `cmd` run as `run_as` if given, `false` if it must not run at all
+pub fn set_user(cmd: &mut Command, run_as: Option<&str>) -> bool {
+    if let Some(name) = run_as {
+        // Never fall back to running the command as the daemon's user
+        match User::from_name(name) {
+            Ok(Some(user)) => {
+                // The daemon's own runtime dir and session bus would be out of reach for the user
+                let runtime_dir = format!("/run/user/{}", user.uid);
+                cmd.uid(user.uid.as_raw())
+                    .gid(user.gid.as_raw())
+                    .env("HOME", &user.dir)
+                    .env("USER", &user.name)
+                    .env("LOGNAME", &user.name)
+                    .env(
+                        "DBUS_SESSION_BUS_ADDRESS",
+                        format!("unix:path={runtime_dir}/bus"),
+                    )
+                    .env("XDG_RUNTIME_DIR", runtime_dir);
+            }
+            Ok(None) => {
+                log::error!("run-as user {name:?} does not exist, not running {cmd:?}");
+                return false;
+            }
+            Err(e) => {
+                log::error!("Could not look up run-as user {name:?}: {e}");
+                return false;
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,146 @@
+use std::ffi::{c_long, c_uchar, CStr};
+use std::ptr;
+
+use knuffel::DecodeScalar;
+use schemars::JsonSchema;
+use serde::Serialize;
+use x11::xlib::{self, Atom, Display, Window};
+
+/// Window manager actions for the active window, sent as EWMH client messages
+#[derive(DecodeScalar, Serialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WindowAction {
+    Maximize,
+    /// Fill the left half of the work area
+    TileLeft,
+    /// Fill the right half of the work area
+    TileRight,
+    Close,
+    Minimize,
+}
+
+const NET_WM_STATE_REMOVE: c_long = 0;
+const NET_WM_STATE_ADD: c_long = 1;
+/// Requests from pagers and similar tools are honoured by window managers that second-guess
+/// applications
+const SOURCE_PAGER: c_long = 2;
+const ICONIC_STATE: c_long = 3;
+
+impl WindowAction {
+    /// Ask the window manager to apply the action to `window`
+    ///
+    /// # Safety
+    ///
+    /// `display` has to be an open connection to the X server
+    pub unsafe fn apply(self, display: *mut Display, window: Window) {
+        let root = xlib::XDefaultRootWindow(display);
+        let message = |name: &CStr, data: [c_long; 5]| send(display, root, window, name, data);
+        let maximized = [
+            intern(display, c"_NET_WM_STATE_MAXIMIZED_VERT") as c_long,
+            intern(display, c"_NET_WM_STATE_MAXIMIZED_HORZ") as c_long,
+        ];
+        match self {
+            WindowAction::Maximize => message(
+                c"_NET_WM_STATE",
+                [
+                    NET_WM_STATE_ADD,
+                    maximized[0],
+                    maximized[1],
+                    SOURCE_PAGER,
+                    0,
+                ],
+            ),
+            WindowAction::TileLeft | WindowAction::TileRight => {
+                let Some([x, y, width, height]) = work_area(display, root) else {
+                    log::warn!("The window manager does not report its work area");
+                    return;
+                };
+                // A maximized window ignores being moved and resized
+                message(
+                    c"_NET_WM_STATE",
+                    [
+                        NET_WM_STATE_REMOVE,
+                        maximized[0],
+                        maximized[1],
+                        SOURCE_PAGER,
+                        0,
+                    ],
+                );
+                let x = if self == WindowAction::TileLeft {
+                    x
+                } else {
+                    x + width / 2
+                };
+                // North west gravity, with x, y, width and height given
+                let flags = xlib::NorthWestGravity as c_long | 0xf00 | SOURCE_PAGER << 12;
+                message(c"_NET_MOVERESIZE_WINDOW", [flags, x, y, width / 2, height]);
+            }
+            WindowAction::Close => message(c"_NET_CLOSE_WINDOW", [0, SOURCE_PAGER, 0, 0, 0]),
+            WindowAction::Minimize => message(c"WM_CHANGE_STATE", [ICONIC_STATE, 0, 0, 0, 0]),
+        }
+        xlib::XFlush(display);
+    }
+}
+
+unsafe fn intern(display: *mut Display, name: &CStr) -> Atom {
+    xlib::XInternAtom(display, name.as_ptr(), xlib::False)
+}
+
+/// Send a client message about `window` to the window manager
+unsafe fn send(
+    display: *mut Display,
+    root: Window,
+    window: Window,
+    message: &CStr,
+    data: [c_long; 5],
+) {
+    let event = xlib::XClientMessageEvent {
+        type_: xlib::ClientMessage,
+        serial: 0,
+        send_event: xlib::True,
+        display,
+        window,
+        message_type: intern(display, message),
+        format: 32,
+        data: data.into(),
+    };
+    let mut event = xlib::XEvent {
+        client_message: event,
+    };
+    xlib::XSendEvent(
+        display,
+        root,
+        xlib::False,
+        xlib::SubstructureRedirectMask | xlib::SubstructureNotifyMask,
+        &mut event,
+    );
+}
+
+/// `x`, `y`, `width` and `height` of the screen minus panels and docks, of the first desktop
+unsafe fn work_area(display: *mut Display, root: Window) -> Option<[c_long; 4]> {
+    let (mut actual_type, mut format) = (0, 0);
+    let (mut items, mut remaining) = (0, 0);
+    let mut data: *mut c_uchar = ptr::null_mut();
+    let status = xlib::XGetWindowProperty(
+        display,
+        root,
+        intern(display, c"_NET_WORKAREA"),
+        0,
+        4,
+        xlib::False,
+        xlib::XA_CARDINAL,
+        &mut actual_type,
+        &mut format,
+        &mut items,
+        &mut remaining,
+        &mut data,
+    );
+    if status != xlib::Success as i32 || data.is_null() {
+        return None;
+    }
+    // Format 32 properties come as an array of longs
+    let longs = data as *const c_long;
+    let area = (format == 32 && items >= 4).then(|| std::array::from_fn(|i| *longs.add(i)));
+    xlib::XFree(data.cast());
+    area
+}
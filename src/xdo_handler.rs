@@ -1,77 +1,431 @@
 use chrono::Duration;
 use libxdo::XDo;
+use libxdo_sys::xdo_t;
+use miette::{miette, Result};
+use std::ffi::{c_int, c_uint, c_ulong};
 use std::sync::mpsc;
 use std::thread;
 use timer::Timer;
 
-// 1. 使用 #[derive(Copy, Clone)] 对简单枚举优化
-#[derive(Copy, Clone)]
+use crate::config::Config;
+use crate::gestures::Gesture;
+use crate::media::MediaKey;
+use crate::screenshot::Geometry;
+use crate::window::WindowAction;
+
+/// Milliseconds between the keystrokes of typed text, as `xdotool type` does, so that slow
+/// applications don't drop any
+const TYPE_DELAY_MS: u32 = 12;
+
+#[derive(Clone)]
 pub enum XDoCommand {
-    MouseUp,
-    MouseDown,
-    MoveMouseRelative,
+    MouseUp(i32),
+    MouseDown(i32),
+    MoveMouseRelative(i32, i32),
+    /// Click a button `count` times, waiting `interval` between the clicks
+    Click {
+        button: i32,
+        count: u32,
+        interval: std::time::Duration,
+    },
+    /// Press the keys of an xdotool style sequence such as `shift` or `ctrl+shift`
+    KeyDown(String),
+    KeyUp(String),
+    /// Press and release the keys of a sequence
+    Key(String),
+    /// Type text through xdo's `enter_text`, `TYPE_DELAY_MS` apart per character
+    Type(String),
+    /// Move the pointer to the center of the focused window
+    CenterCursor,
+    /// Apply a window manager action to the active window
+    Window(WindowAction),
+    /// Reply with the pointer position relative to its screen
+    PointerLocation(mpsc::Sender<Option<(f64, f64)>>),
+    /// Reply with the position and size of the active window
+    WindowGeometry(mpsc::Sender<Option<Geometry>>),
+    /// Reply with the size of the screen the pointer is on
+    ScreenGeometry(mpsc::Sender<Option<Geometry>>),
+}
+
+/// Raw libxdo handle for the queries the `libxdo` crate doesn't wrap
+struct XDoQuery(*mut xdo_t);
+
+impl XDoQuery {
+    fn new() -> Option<Self> {
+        let handle = unsafe { libxdo_sys::xdo_new(std::ptr::null()) };
+        (!handle.is_null()).then_some(Self(handle))
+    }
+
+    /// Pointer position as a fraction of the width and height of the screen it is on
+    fn pointer_location(&self) -> Option<(f64, f64)> {
+        let (mut x, mut y, mut screen): (c_int, c_int, c_int) = (0, 0, 0);
+        let (mut width, mut height): (c_uint, c_uint) = (0, 0);
+        unsafe {
+            if libxdo_sys::xdo_get_mouse_location(self.0, &mut x, &mut y, &mut screen) != 0
+                || libxdo_sys::xdo_get_viewport_dimensions(self.0, &mut width, &mut height, screen)
+                    != 0
+            {
+                return None;
+            }
+        }
+        if width == 0 || height == 0 {
+            return None;
+        }
+        Some((x as f64 / width as f64, y as f64 / height as f64))
+    }
+
+    /// The active window, or the focused one without a window manager that reports it
+    fn active_window(&self) -> Option<c_ulong> {
+        let mut window: c_ulong = 0;
+        unsafe {
+            if libxdo_sys::xdo_get_active_window(self.0, &mut window) != 0
+                && libxdo_sys::xdo_get_focused_window_sane(self.0, &mut window) != 0
+            {
+                return None;
+            }
+        }
+        Some(window)
+    }
+
+    /// Center of the active window together with the screen of the pointer
+    fn focused_window_center(&self) -> Option<(i32, i32, i32)> {
+        let window = self.window_geometry()?;
+        let (mut x, mut y, mut screen): (c_int, c_int, c_int) = (0, 0, 0);
+        unsafe {
+            if libxdo_sys::xdo_get_mouse_location(self.0, &mut x, &mut y, &mut screen) != 0 {
+                return None;
+            }
+        }
+        let center_x = window.x + window.width as i32 / 2;
+        let center_y = window.y + window.height as i32 / 2;
+        Some((center_x, center_y, screen))
+    }
+
+    /// Position and size of the active window
+    fn window_geometry(&self) -> Option<Geometry> {
+        let window = self.active_window()?;
+        let (mut x, mut y): (c_int, c_int) = (0, 0);
+        let (mut width, mut height): (c_uint, c_uint) = (0, 0);
+        unsafe {
+            if libxdo_sys::xdo_get_window_location(
+                self.0,
+                window,
+                &mut x,
+                &mut y,
+                std::ptr::null_mut(),
+            ) != 0
+                || libxdo_sys::xdo_get_window_size(self.0, window, &mut width, &mut height) != 0
+            {
+                return None;
+            }
+        }
+        Some(Geometry {
+            x,
+            y,
+            width,
+            height,
+        })
+    }
+
+    /// Size of the screen the pointer is on
+    fn screen_geometry(&self) -> Option<Geometry> {
+        let (mut x, mut y, mut screen): (c_int, c_int, c_int) = (0, 0, 0);
+        let (mut width, mut height): (c_uint, c_uint) = (0, 0);
+        unsafe {
+            if libxdo_sys::xdo_get_mouse_location(self.0, &mut x, &mut y, &mut screen) != 0
+                || libxdo_sys::xdo_get_viewport_dimensions(self.0, &mut width, &mut height, screen)
+                    != 0
+            {
+                return None;
+            }
+        }
+        Some(Geometry {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        })
+    }
+
+    fn window_action(&self, action: WindowAction) -> Option<()> {
+        let window = self.active_window()?;
+        unsafe { action.apply((*self.0).xdpy, window) };
+        Some(())
+    }
+}
+
+impl Drop for XDoQuery {
+    fn drop(&mut self) {
+        unsafe { libxdo_sys::xdo_free(self.0) };
+    }
 }
 
 pub struct XDoHandler {
-    tx: mpsc::Sender<(XDoCommand, i32, i32)>,
+    tx: mpsc::Sender<XDoCommand>,
     timer: Timer,
     guard: Option<timer::Guard>,
     handler_mouse_down: bool,
     pub is_xorg: bool,
 }
 
-pub fn start_handler(is_xorg: bool) -> XDoHandler {
+/// A handler that acts as if it was on Xorg, but hands its commands to the returned receiver
+/// instead of libxdo
+#[cfg(test)]
+pub fn start_recording() -> (XDoHandler, mpsc::Receiver<XDoCommand>) {
+    let (tx, rx) = mpsc::channel();
+    let xdoh = XDoHandler {
+        tx,
+        timer: Timer::new(),
+        guard: None,
+        handler_mouse_down: false,
+        is_xorg: true,
+    };
+    (xdoh, rx)
+}
+
+/// Start the thread that talks to the X server. On Xorg this fails when libxdo can't connect or
+/// can't even find the pointer, rather than ignoring every command later on.
+pub fn start_handler(is_xorg: bool) -> Result<XDoHandler> {
     let (tx, rx) = mpsc::channel();
     let timer = Timer::new();
     
     if is_xorg {
+        let (ready_tx, ready_rx) = mpsc::channel();
         thread::spawn(move || {
             // 2. 将 XDo 实例移到线程外部以避免重复创建
-            let xdo = XDo::new(None).expect("can not initialize libxdo");
+            let xdo = match XDo::new(None) {
+                Ok(xdo) => xdo,
+                Err(e) => {
+                    let _ = ready_tx.send(Err(format!("Could not initialize libxdo: {e}")));
+                    return;
+                }
+            };
+            let query = XDoQuery::new();
+            // Self-test: a query that changes nothing but needs a working X connection
+            let test = match query.as_ref().and_then(XDoQuery::pointer_location) {
+                Some(_) => Ok(()),
+                None => Err("libxdo could not find the pointer".to_string()),
+            };
+            let passed = test.is_ok();
+            let _ = ready_tx.send(test);
+            if !passed {
+                return;
+            }
             
             // 3. 使用 while let 替代 loop + match 模式，更符合 Rust 习惯
-            while let Ok((command, param1, param2)) = rx.recv() {
+            while let Ok(command) = rx.recv() {
                 // 4. 使用 let _ = 处理 Result，避免 unwrap
                 let _ = match command {
-                    XDoCommand::MouseDown => xdo.mouse_down(param1),
-                    XDoCommand::MouseUp => xdo.mouse_up(param1),
-                    XDoCommand::MoveMouseRelative => xdo.move_mouse_relative(param1, param2),
+                    XDoCommand::MouseDown(button) => xdo.mouse_down(button),
+                    XDoCommand::MouseUp(button) => xdo.mouse_up(button),
+                    XDoCommand::MoveMouseRelative(x, y) => xdo.move_mouse_relative(x, y),
+                    XDoCommand::Click {
+                        button,
+                        count,
+                        interval,
+                    } => (0..count).try_for_each(|i| {
+                        if i > 0 {
+                            thread::sleep(interval);
+                        }
+                        xdo.click(button)
+                    }),
+                    XDoCommand::KeyDown(keys) => xdo.send_keysequence_down(&keys, 0),
+                    XDoCommand::KeyUp(keys) => xdo.send_keysequence_up(&keys, 0),
+                    XDoCommand::Key(keys) => xdo.send_keysequence(&keys, 0),
+                    XDoCommand::Type(text) => xdo.enter_text(&text, TYPE_DELAY_MS * 1000),
+                    XDoCommand::CenterCursor => {
+                        match query.as_ref().and_then(XDoQuery::focused_window_center) {
+                            Some((x, y, screen)) => xdo.move_mouse(x, y, screen),
+                            None => {
+                                log::warn!("Could not find the focused window");
+                                Ok(())
+                            }
+                        }
+                    }
+                    XDoCommand::Window(action) => {
+                        let query = query.as_ref();
+                        if query.and_then(|q| q.window_action(action)).is_none() {
+                            log::warn!("Could not find the active window for {action:?}");
+                        }
+                        Ok(())
+                    }
+                    XDoCommand::PointerLocation(reply) => {
+                        let _ = reply.send(query.as_ref().and_then(XDoQuery::pointer_location));
+                        Ok(())
+                    }
+                    XDoCommand::WindowGeometry(reply) => {
+                        let _ = reply.send(query.as_ref().and_then(XDoQuery::window_geometry));
+                        Ok(())
+                    }
+                    XDoCommand::ScreenGeometry(reply) => {
+                        let _ = reply.send(query.as_ref().and_then(XDoQuery::screen_geometry));
+                        Ok(())
+                    }
                 };
             }
         });
+        ready_rx
+            .recv()
+            .map_err(|_| miette!("libxdo thread exited during startup"))?
+            .map_err(|e| miette!("{e}, is the X server reachable through $DISPLAY?"))?;
+        log::debug!("libxdo self-test passed");
     }
 
-    XDoHandler {
+    Ok(XDoHandler {
         tx,
         timer,
         guard: None,
         handler_mouse_down: false,
         is_xorg,
+    })
+}
+
+/// Start the handler for the daemon. When libxdo doesn't work the daemon carries on without it,
+/// unless rules in `config` need it.
+pub fn start_for_config(config: &Config, is_xorg: bool) -> Result<XDoHandler> {
+    match start_handler(is_xorg) {
+        Ok(xdoh) => Ok(xdoh),
+        Err(e) if config.gestures.iter().any(Gesture::needs_xdo) => {
+            Err(e.wrap_err("Rules that drag, click or press keys need libxdo"))
+        }
+        Err(e) => {
+            log::warn!("{e}, continuing without libxdo");
+            start_handler(false)
+        }
     }
 }
 
 impl XDoHandler {
     // 5. 使用 '&mut self' 而不是移动所有权
-    pub fn mouse_down(&mut self, button: i32) {
+    /// Press `button`, holding down `modifiers` first if given
+    pub fn mouse_down(&mut self, button: i32, modifiers: Option<&str>) {
         self.cancel_timer_if_present();
-        let _ = self.tx.send((XDoCommand::MouseDown, button, 255));
+        if let Some(keys) = modifiers {
+            let _ = self.tx.send(XDoCommand::KeyDown(keys.to_string()));
+        }
+        let _ = self.tx.send(XDoCommand::MouseDown(button));
         self.handler_mouse_down = true;
     }
 
-    pub fn mouse_up_delay(&mut self, button: i32, delay_ms: i64) {
+    /// Release `button` and then `modifiers` after `delay_ms`, unless the drag resumes first
+    pub fn mouse_up_delay(&mut self, button: i32, delay_ms: i64, modifiers: Option<&str>) {
         let tx_clone = self.tx.clone();
+        let modifiers = modifiers.map(str::to_string);
         self.guard = Some(self.timer.schedule_with_delay(
             Duration::milliseconds(delay_ms),
             move || {
-                let _ = tx_clone.send((XDoCommand::MouseUp, button, 255));
+                let _ = tx_clone.send(XDoCommand::MouseUp(button));
+                if let Some(keys) = &modifiers {
+                    let _ = tx_clone.send(XDoCommand::KeyUp(keys.clone()));
+                }
             },
         ));
         self.handler_mouse_down = false;
     }
 
+    /// Release `button` and then `modifiers` right away, dropping a delayed release
+    pub fn mouse_up(&mut self, button: i32, modifiers: Option<&str>) {
+        self.guard = None;
+        let _ = self.tx.send(XDoCommand::MouseUp(button));
+        if let Some(keys) = modifiers {
+            let _ = self.tx.send(XDoCommand::KeyUp(keys.to_string()));
+        }
+        self.handler_mouse_down = false;
+    }
+
     pub fn move_mouse_relative(&mut self, x_val: i32, y_val: i32) {
         self.cancel_timer_if_present();
-        let _ = self.tx.send((XDoCommand::MoveMouseRelative, x_val, y_val));
+        let _ = self.tx.send(XDoCommand::MoveMouseRelative(x_val, y_val));
+    }
+
+    /// Click `button` `count` times with `interval_ms` between the clicks, e.g. a double-click
+    pub fn click(&mut self, button: i32, count: u32, interval_ms: u64) {
+        if !self.is_xorg {
+            log::warn!("Clicking is only supported on Xorg");
+            return;
+        }
+        let _ = self.tx.send(XDoCommand::Click {
+            button,
+            count,
+            interval: std::time::Duration::from_millis(interval_ms),
+        });
+    }
+
+    /// Move the pointer to the center of the focused window
+    pub fn center_cursor(&mut self) {
+        if !self.is_xorg {
+            log::warn!("Centering the cursor is only supported on Xorg");
+            return;
+        }
+        let _ = self.tx.send(XDoCommand::CenterCursor);
+    }
+
+    /// Ask the window manager to apply `action` to the active window
+    pub fn window_action(&mut self, action: WindowAction) {
+        if !self.is_xorg {
+            log::warn!("Window actions are only supported on Xorg");
+            return;
+        }
+        let _ = self.tx.send(XDoCommand::Window(action));
+    }
+
+    /// Press and release a media key
+    pub fn media_key(&mut self, key: MediaKey) {
+        if !self.is_xorg {
+            log::warn!("Media keys are only supported on Xorg");
+            return;
+        }
+        let _ = self.tx.send(XDoCommand::Key(key.keysym().to_string()));
+    }
+
+    /// Type `text` as if it was entered on the keyboard
+    pub fn type_text(&mut self, text: &str) {
+        if !self.is_xorg {
+            log::warn!("Typing text is only supported on Xorg");
+            return;
+        }
+        let _ = self.tx.send(XDoCommand::Type(text.to_string()));
+    }
+
+    /// Pointer position as a fraction of the screen size, `None` outside of Xorg
+    pub fn pointer_location(&self) -> Option<(f64, f64)> {
+        self.ask(XDoCommand::PointerLocation)
+    }
+
+    /// Position and size of the active window, `None` outside of Xorg
+    pub fn window_geometry(&self) -> Option<Geometry> {
+        self.ask(XDoCommand::WindowGeometry)
+    }
+
+    /// Size of the screen the pointer is on, `None` outside of Xorg
+    pub fn screen_geometry(&self) -> Option<Geometry> {
+        self.ask(XDoCommand::ScreenGeometry)
+    }
+
+    /// Send the query `command` makes with a reply channel, and wait a moment for its answer
+    fn ask<T>(&self, command: impl FnOnce(mpsc::Sender<Option<T>>) -> XDoCommand) -> Option<T> {
+        if !self.is_xorg {
+            return None;
+        }
+        let (reply, rx) = mpsc::channel();
+        self.tx.send(command(reply)).ok()?;
+        rx.recv_timeout(std::time::Duration::from_millis(100))
+            .ok()
+            .flatten()
+    }
+
+    /// Keys to hold down around a command, `None` outside of Xorg or without `keys`
+    pub fn held_keys(&self, keys: Option<&str>) -> Option<HeldKeys> {
+        let keys = keys?;
+        if !self.is_xorg {
+            log::warn!("Holding {keys:?} is only supported on Xorg");
+            return None;
+        }
+        Some(HeldKeys {
+            keys: keys.to_string(),
+            tx: self.tx.clone(),
+            pressed: false,
+        })
     }
 
     fn cancel_timer_if_present(&mut self) {
@@ -81,3 +435,26 @@ impl XDoHandler {
         }
     }
 }
+
+/// An xdotool style key sequence that is released again once dropped
+#[derive(Debug)]
+pub struct HeldKeys {
+    keys: String,
+    tx: mpsc::Sender<XDoCommand>,
+    pressed: bool,
+}
+
+impl HeldKeys {
+    pub fn press(&mut self) {
+        let _ = self.tx.send(XDoCommand::KeyDown(self.keys.clone()));
+        self.pressed = true;
+    }
+}
+
+impl Drop for HeldKeys {
+    fn drop(&mut self) {
+        if self.pressed {
+            let _ = self.tx.send(XDoCommand::KeyUp(self.keys.clone()));
+        }
+    }
+}